@@ -0,0 +1,193 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Result, anyhow};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dates::{DateRange, Showtime, TimeFrame},
+    events::{Category, Event, dedup_key},
+};
+
+/// A listing of an event at one venue on one day, as stored in the archive. Only
+/// what's needed to look back at past listings is kept, not the descriptions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub title: String,
+    pub category: String,
+    pub venue: String,
+    pub date: NaiveDate,
+    pub url: Option<String>,
+    pub showtime: Option<Showtime>,
+    /// The variants listed on the day, such as "3D", for films.
+    pub tags: BTreeSet<String>,
+}
+
+impl ArchiveEntry {
+    /// Identifies the listing across runs: the same title, by its [dedup_key], at the
+    /// same venue on the same day.
+    pub fn key(&self) -> String {
+        return format!("{}|{}|{}", dedup_key(&self.title), self.date, self.venue);
+    }
+
+    /// Takes in a later listing with the same key, keeping the variants of both.
+    fn update(&mut self, newer: ArchiveEntry) {
+        let mut tags = std::mem::take(&mut self.tags);
+        tags.extend(newer.tags);
+        *self = ArchiveEntry {
+            url: newer.url.or(self.url.take()),
+            showtime: newer.showtime.or(self.showtime),
+            tags,
+            ..newer
+        };
+    }
+}
+
+/// Adds the listings of the events within `window` to the archive at `path`, a file
+/// with one JSON entry per line, creating it if missing. Listings already in the
+/// archive are updated rather than duplicated. Events with unknown dates are left
+/// out. Returns how many new listings were added.
+pub fn update_archive(path: &Path, categories: &[Category], window: &DateRange) -> Result<usize> {
+    let mut entries = load_archive(path)?;
+    let mut positions: HashMap<String, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| (entry.key(), index))
+        .collect();
+
+    let mut added = 0;
+    for entry in categories
+        .iter()
+        .flat_map(|c| &c.events)
+        .flat_map(|e| listings(e, window))
+    {
+        match positions.get(&entry.key()) {
+            Some(&index) => entries[index].update(entry),
+            None => {
+                positions.insert(entry.key(), entries.len());
+                entries.push(entry);
+                added += 1;
+            }
+        }
+    }
+
+    let mut content = String::new();
+    for entry in &entries {
+        content.push_str(&serde_json::to_string(entry)?);
+        content.push('\n');
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+
+    return Ok(added);
+}
+
+/// Reads the entries of the archive at `path`, or none if it doesn't exist yet.
+pub fn load_archive(path: &Path) -> Result<Vec<ArchiveEntry>> {
+    if !fs::exists(path)? {
+        return Ok(Vec::new());
+    }
+    return fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| anyhow!("Invalid archive entry on line {}: {e}", index + 1))
+        })
+        .collect();
+}
+
+/// The listings of an event on each of its days within `window`, at each venue.
+fn listings(event: &Event, window: &DateRange) -> Vec<ArchiveEntry> {
+    let days: Vec<NaiveDate> = match event.time_frame.as_ref().and_then(|tf| tf.clamp(window)) {
+        Some(TimeFrame::Dates(set)) => set.dates().clone(),
+        Some(TimeFrame::Period(range)) => range.iter_days().collect(),
+        None => return Vec::new(),
+    };
+
+    let mut listings = Vec::new();
+    for date in days {
+        for location in &event.locations {
+            listings.push(ArchiveEntry {
+                title: event.title.clone(),
+                category: event.category.clone(),
+                venue: location.name.clone(),
+                date,
+                url: location.url.clone(),
+                showtime: event.showtimes.get(&date).copied(),
+                tags: event.tags.iter().cloned().collect(),
+            });
+        }
+    }
+    return listings;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+
+    use chrono::NaiveTime;
+
+    use super::*;
+    use crate::{dates::DateSet, events::Location};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 1, day).unwrap()
+    }
+
+    fn film(title: &str, tags: &[&str], days: &[u32]) -> Event {
+        let dates = DateSet::new(days.iter().map(|d| date(*d)).collect()).unwrap();
+        Event::new(
+            title,
+            HashSet::from([Location::new("Ariston", None)]),
+            "Film",
+        )
+        .with_tags(tags.iter().map(|t| t.to_string()).collect())
+        .with_time_frame(Some(TimeFrame::Dates(dates)))
+    }
+
+    #[test]
+    fn test_archive_upserts_listings() {
+        let path = std::env::temp_dir().join(format!("archive_{}.jsonl", std::process::id()));
+        let week = DateRange::new(date(12), date(18));
+        let categories = |events| {
+            vec![Category {
+                name: "Film".to_string(),
+                events,
+            }]
+        };
+
+        // The day after the window isn't archived yet
+        let first = categories(vec![film("Dune", &[], &[17, 18, 19])]);
+        assert_eq!(update_archive(&path, &first, &week).unwrap(), 2);
+
+        // The next run lists the same days again, with a 3D variant and a showtime
+        let showtime = Showtime {
+            start: NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            end: None,
+        };
+        let second = categories(vec![
+            film("DUNE", &["3D"], &[18]).with_showtimes(BTreeMap::from([(date(18), showtime)])),
+            film("Avatar", &[], &[18]),
+        ]);
+        assert_eq!(update_archive(&path, &second, &week).unwrap(), 1);
+
+        let entries = load_archive(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        let dune = entries
+            .iter()
+            .find(|e| e.date == date(18) && e.title == "DUNE")
+            .unwrap();
+        assert_eq!(dune.showtime, Some(showtime));
+        assert_eq!(dune.tags, BTreeSet::from(["3D".to_string()]));
+        assert_eq!(dune.venue, "Ariston");
+    }
+}
@@ -1,6 +1,6 @@
-use std::{collections::HashSet, iter::Take};
+use std::{fmt::Display, iter::Take, str::FromStr};
 
-use chrono::{NaiveDate, naive::NaiveDateDaysIterator};
+use chrono::{Datelike, Days, NaiveDate, NaiveTime, Weekday, naive::NaiveDateDaysIterator};
 use serde::{Deserialize, Serialize};
 
 /// A set of dates, such as the days on which as event occurs.
@@ -23,7 +23,9 @@ impl DateSet {
         }
     }
 
-    /// Creates a new [DateSet] including only today's date.
+    /// Creates a new [DateSet] including only today's date. Only meant for events
+    /// known to happen today: events whose dates can't be found should have no
+    /// [TimeFrame] instead, so that they're shown as to be confirmed.
     pub fn today() -> Self {
         Self {
             dates: vec![chrono::Local::now().date_naive()],
@@ -62,12 +64,35 @@ impl DateSet {
         }
     }
 
+    /// Checks if the date is one of the dates in this [DateSet].
+    pub fn contains(&self, date: &NaiveDate) -> bool {
+        self.dates.contains(date)
+    }
+
+    /// Checks if any of the dates in this [DateSet] falls within the range. Unlike
+    /// `as_range().overlaps(...)`, scattered dates around the range don't count.
+    pub fn overlaps(&self, range: &DateRange) -> bool {
+        self.dates.iter().any(|d| range.contains(d))
+    }
+
+    /// Returns a new [DateSet] with only the dates that fall within the range,
+    /// or `None` if there are none.
+    pub fn filter_window(&self, range: &DateRange) -> Option<Self> {
+        let dates = self
+            .dates
+            .iter()
+            .filter(|d| range.contains(d))
+            .cloned()
+            .collect();
+        DateSet::new(dates)
+    }
+
+    /// Joins two sets, without duplicates. The dates of the result are sorted.
     pub fn merge(self, other: Self) -> Self {
-        // Use a temporary hashset to deduplicate
-        let mut new_set = HashSet::new();
-        new_set.extend(self.dates);
-        new_set.extend(other.dates);
-        DateSet::new(Vec::from_iter(new_set)).unwrap()
+        let mut dates = [self.dates, other.dates].concat();
+        dates.sort();
+        dates.dedup();
+        DateSet::new(dates).unwrap()
     }
 }
 
@@ -83,8 +108,9 @@ impl DateRange {
         Self { start, end }
     }
 
-    /// Returns the distance in days between the first and last dates of the set.
-    pub fn days_spanned(&self) -> i64 {
+    /// Returns the number of days in this [DateRange], ends included, so a range
+    /// starting and ending on the same day has one.
+    pub fn num_days(&self) -> i64 {
         (self.end - self.start).num_days() + 1
     }
 
@@ -93,6 +119,38 @@ impl DateRange {
         self.start <= other.end && self.end >= other.start
     }
 
+    /// Checks if the date falls within this [DateRange], ends included.
+    pub fn contains(&self, date: &NaiveDate) -> bool {
+        self.start <= *date && *date <= self.end
+    }
+
+    /// Returns the part of this [DateRange] that falls within the other,
+    /// or `None` if they don't overlap.
+    pub fn clamp(&self, other: &DateRange) -> Option<Self> {
+        if !self.overlaps(other) {
+            return None;
+        }
+        Some(Self {
+            start: self.start.max(other.start),
+            end: self.end.min(other.end),
+        })
+    }
+
+    /// Returns the first weekend (Friday to Sunday) in this [DateRange], cut to fit
+    /// within it. Returns `None` if the range doesn't include any weekend day.
+    pub fn weekend(&self) -> Option<Self> {
+        let start = self
+            .iter_days()
+            .find(|d| matches!(d.weekday(), Weekday::Fri | Weekday::Sat | Weekday::Sun))?;
+        let to_sunday = Weekday::Sun.days_since(start.weekday());
+        let end = start + Days::new(to_sunday as u64);
+
+        Some(Self {
+            start,
+            end: end.min(self.end),
+        })
+    }
+
     pub fn merge(self, other: Self) -> Self {
         Self {
             start: self.start.min(other.start),
@@ -100,8 +158,20 @@ impl DateRange {
         }
     }
 
+    /// Iterates over the days of this [DateRange] in order, from `start` through `end`
+    /// included. Days are thus `start` plus 0 up to `num_days() - 1`.
     pub fn iter_days(&self) -> Take<NaiveDateDaysIterator> {
-        self.start.iter_days().take(self.days_spanned() as usize)
+        self.start.iter_days().take(self.num_days() as usize)
+    }
+
+    /// Returns a file name, without extension, for outputs covering this [DateRange],
+    /// e.g., `2026-01-12_2026-01-18`.
+    pub fn filename(&self) -> String {
+        format!(
+            "{}_{}",
+            self.start.format("%Y-%m-%d"),
+            self.end.format("%Y-%m-%d")
+        )
     }
 }
 
@@ -114,6 +184,31 @@ pub enum TimeFrame {
 }
 
 impl TimeFrame {
+    /// Whether the event happens on `date`. Date sets must include the exact date,
+    /// while periods only need to span it.
+    pub fn includes(&self, date: &NaiveDate) -> bool {
+        match self {
+            TimeFrame::Dates(set) => set.contains(date),
+            TimeFrame::Period(range) => range.contains(date),
+        }
+    }
+
+    /// Whether the event happens at some point within the range.
+    pub fn overlaps(&self, range: &DateRange) -> bool {
+        match self {
+            Self::Dates(set) => set.overlaps(range),
+            Self::Period(period) => period.overlaps(range),
+        }
+    }
+
+    /// Whether the two time frames have a day in common.
+    pub fn shares_day_with(&self, other: &TimeFrame) -> bool {
+        match (self, other) {
+            (Self::Dates(set), other) => set.dates().iter().any(|d| other.includes(d)),
+            (Self::Period(period), other) => other.overlaps(period),
+        }
+    }
+
     pub fn as_range(&self) -> DateRange {
         match self {
             Self::Dates(set) => set.as_range(),
@@ -121,18 +216,153 @@ impl TimeFrame {
         }
     }
 
+    /// Restricts the time frame to the given range, dropping the dates outside of it.
+    /// Returns `None` if nothing is left.
+    pub fn clamp(&self, range: &DateRange) -> Option<Self> {
+        match self {
+            Self::Dates(set) => set.filter_window(range).map(Self::Dates),
+            Self::Period(period) => period.clamp(range).map(Self::Period),
+        }
+    }
+
+    /// Combines two time frames of the same event. Date sets are joined, while periods
+    /// become the period spanning both. A date set merged with a period is absorbed
+    /// into the period, which is stretched to span the dates too, since a period
+    /// already stands for every day within it.
     pub fn merge(self, other: Self) -> Self {
         match (self, other) {
             (Self::Dates(set1), Self::Dates(set2)) => Self::Dates(set1.merge(set2)),
             (Self::Period(range1), Self::Period(range2)) => Self::Period(range1.merge(range2)),
-            _ => todo!(),
+            (Self::Dates(set), Self::Period(range)) | (Self::Period(range), Self::Dates(set)) => {
+                Self::Period(range.merge(set.as_range()))
+            }
+        }
+    }
+}
+
+/// The time at which a show starts and, if known, ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Showtime {
+    pub start: NaiveTime,
+    pub end: Option<NaiveTime>,
+}
+
+/// The reason a date string could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateParseError {
+    /// The string is empty or only whitespace.
+    Empty,
+    /// The string has a different number of parts than the format expects.
+    WrongArity { expected: usize, found: usize },
+    /// A part that should be a month is not a known Italian month name.
+    UnknownMonth(String),
+    /// The parts are well-formed but don't make up a valid date (e.g., "31/2/2026").
+    InvalidDate(String),
+    /// The string doesn't match any of the supported formats.
+    UnknownFormat(String),
+}
+
+impl Display for DateParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty date string"),
+            Self::WrongArity { expected, found } => {
+                write!(f, "expected {expected} parts in date, found {found}")
+            }
+            Self::UnknownMonth(month) => write!(f, "unknown month \"{month}\""),
+            Self::InvalidDate(date) => write!(f, "invalid date \"{date}\""),
+            Self::UnknownFormat(date) => write!(f, "unknown date format \"{date}\""),
         }
     }
 }
 
-/// Parse Italian month names to numbers
+impl std::error::Error for DateParseError {}
+
+/// Checks that a date string was split in the expected number of parts.
+pub fn expect_parts(parts: &[&str], expected: usize) -> Result<(), DateParseError> {
+    if parts.len() != expected {
+        return Err(DateParseError::WrongArity {
+            expected,
+            found: parts.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Parses an Italian month name, reporting it if it's unknown.
+pub fn parse_month(month_name: &str) -> Result<u32, DateParseError> {
+    italian_month_to_number(month_name)
+        .ok_or_else(|| DateParseError::UnknownMonth(month_name.to_string()))
+}
+
+/// Parses a numeric date component, such as a day or a year.
+pub fn parse_number<T: FromStr>(text: &str) -> Result<T, DateParseError> {
+    text.parse::<T>()
+        .map_err(|_| DateParseError::InvalidDate(text.to_string()))
+}
+
+/// Builds a date from its components, reporting it if it doesn't exist.
+pub fn make_date(year: i32, month: u32, day: u32) -> Result<NaiveDate, DateParseError> {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .ok_or_else(|| DateParseError::InvalidDate(format!("{day}/{month}/{year}")))
+}
+
+/// Parses the time of a show from text such as "ore 19.30" or "ore 19.30 - 22.00".
+/// Returns `None` if the text doesn't mention a time.
+pub fn parse_showtime(text: &str) -> Result<Option<Showtime>, DateParseError> {
+    let lowercase = text.to_lowercase();
+    let mut parts = lowercase.split_whitespace().skip_while(|p| *p != "ore");
+    if parts.next().is_none() {
+        return Ok(None);
+    }
+
+    let times: String = parts.collect();
+    let mut times = times.split('-');
+    let start = parse_time(times.next().unwrap_or_default())?;
+    let end = times.next().map(parse_time).transpose()?;
+
+    Ok(Some(Showtime { start, end }))
+}
+
+/// Parses a time written as "19.30", "19:30" or just "21".
+fn parse_time(text: &str) -> Result<NaiveTime, DateParseError> {
+    let (hour, minute) = text.split_once(['.', ':']).unwrap_or((text, "0"));
+    NaiveTime::from_hms_opt(parse_number(hour)?, parse_number(minute)?, 0)
+        .ok_or_else(|| DateParseError::InvalidDate(text.to_string()))
+}
+
+/// Italian weekday names, full and abbreviated.
+const ITALIAN_WEEKDAYS: &[&str] = &[
+    "lun",
+    "mar",
+    "mer",
+    "gio",
+    "ven",
+    "sab",
+    "dom",
+    "lunedì",
+    "martedì",
+    "mercoledì",
+    "giovedì",
+    "venerdì",
+    "sabato",
+    "domenica",
+];
+
+/// Whether a word is the name of a weekday in Italian, such as "Sab." or "martedì".
+/// Note that "mar" is also short for March.
+pub fn is_italian_weekday(word: &str) -> bool {
+    return ITALIAN_WEEKDAYS.contains(&word.to_lowercase().trim_end_matches(['.', ',']));
+}
+
+/// Parse Italian month names to numbers. Accepts full names and abbreviations in any
+/// case, with or without a trailing dot (e.g., "Dicembre", "DIC", "dic.", "sett.").
 pub fn italian_month_to_number(month_name: &str) -> Option<u32> {
-    match month_name.to_lowercase().as_str() {
+    let month_name = month_name
+        .trim()
+        .trim_end_matches(['.', ','])
+        .to_lowercase();
+    match month_name.as_str() {
         "gen" => Some(1),
         "gennaio" => Some(1),
         "feb" => Some(2),
@@ -150,6 +380,7 @@ pub fn italian_month_to_number(month_name: &str) -> Option<u32> {
         "ago" => Some(8),
         "agosto" => Some(8),
         "set" => Some(9),
+        "sett" => Some(9),
         "settembre" => Some(9),
         "ott" => Some(10),
         "ottobre" => Some(10),
@@ -166,12 +397,126 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_days_spanned_is_end_inclusive() {
+    fn test_italian_month_names() {
+        let cases = [
+            ("gen", 1),
+            ("Gennaio", 1),
+            ("FEB", 2),
+            ("febbraio", 2),
+            ("mar", 3),
+            ("Marzo", 3),
+            ("apr.", 4),
+            ("aprile", 4),
+            ("mag", 5),
+            ("MAGGIO", 5),
+            ("giu", 6),
+            ("giugno", 6),
+            ("lug", 7),
+            ("Luglio", 7),
+            ("ago", 8),
+            ("agosto", 8),
+            ("set", 9),
+            ("sett.", 9),
+            ("Settembre", 9),
+            ("ott", 10),
+            ("ottobre,", 10),
+            ("nov", 11),
+            ("Novembre", 11),
+            ("dic", 12),
+            ("Dicembre", 12),
+            ("DICEMBRE", 12),
+            (" dic. ", 12),
+        ];
+        for (name, number) in cases {
+            assert_eq!(italian_month_to_number(name), Some(number), "{name}");
+        }
+
+        for name in ["", "genaio", "dec", "13", "d"] {
+            assert_eq!(italian_month_to_number(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_scattered_dates_dont_overlap_gap() {
+        let date = |day| NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+        let set = DateSet::new(vec![date(1), date(30)]).unwrap();
+        let window = DateRange::new(date(10), date(15));
+
+        assert!(set.as_range().overlaps(&window));
+        assert!(!set.overlaps(&window));
+        assert!(!TimeFrame::Dates(set.clone()).overlaps(&window));
+        assert!(set.overlaps(&DateRange::new(date(15), date(30))));
+        assert!(set.contains(&date(30)));
+        assert!(!set.contains(&date(15)));
+    }
+
+    #[test]
+    fn test_num_days_is_end_inclusive() {
         let start = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap();
         let end = NaiveDate::from_ymd_opt(2026, 1, 5).unwrap();
         let range = DateRange::new(start, end);
 
-        assert_eq!(range.days_spanned(), 5);
+        assert_eq!(range.num_days(), 5);
+    }
+
+    fn dates(days: &[u32]) -> TimeFrame {
+        let dates = days
+            .iter()
+            .map(|d| NaiveDate::from_ymd_opt(2026, 1, *d).unwrap())
+            .collect();
+        TimeFrame::Dates(DateSet::new(dates).unwrap())
+    }
+
+    fn period(start: u32, end: u32) -> TimeFrame {
+        TimeFrame::Period(DateRange::new(
+            NaiveDate::from_ymd_opt(2026, 1, start).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 1, end).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_merge_date_sets_joins_them() {
+        match dates(&[3, 5]).merge(dates(&[5, 9])) {
+            TimeFrame::Dates(set) => {
+                let days: Vec<u32> = set.dates().iter().map(|d| d.day()).collect();
+                assert_eq!(days, vec![3, 5, 9]);
+            }
+            other => panic!("unexpected time frame {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_merge_periods_spans_both() {
+        let merged = period(1, 5).merge(period(10, 12)).as_range();
+        assert_eq!((merged.start.day(), merged.end.day()), (1, 12));
+    }
+
+    #[test]
+    fn test_merge_dates_into_period() {
+        // Either way around, dates within the period are absorbed by it
+        for merged in [
+            dates(&[3, 4]).merge(period(1, 10)),
+            period(1, 10).merge(dates(&[3, 4])),
+        ] {
+            assert!(matches!(merged, TimeFrame::Period(_)));
+            let range = merged.as_range();
+            assert_eq!((range.start.day(), range.end.day()), (1, 10));
+        }
+
+        // Dates outside of it stretch it
+        let merged = period(5, 10).merge(dates(&[2, 14]));
+        assert!(matches!(merged, TimeFrame::Period(_)));
+        let range = merged.as_range();
+        assert_eq!((range.start.day(), range.end.day()), (2, 14));
+    }
+
+    #[test]
+    fn test_single_day_range() {
+        let day = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap();
+        let range = DateRange::new(day, day);
+
+        assert_eq!(range.num_days(), 1);
+        assert_eq!(range.iter_days().collect::<Vec<NaiveDate>>(), vec![day]);
     }
 
     #[test]
@@ -186,4 +531,35 @@ mod tests {
         assert_eq!(days.first(), Some(&start));
         assert_eq!(days.last(), Some(&end));
     }
+
+    #[test]
+    fn test_filename_matches_window() {
+        let range = DateRange::new(
+            NaiveDate::from_ymd_opt(2026, 1, 12).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 1, 18).unwrap(),
+        );
+        assert_eq!(range.filename(), "2026-01-12_2026-01-18");
+    }
+
+    #[test]
+    fn test_weekend_of_week() {
+        // Monday to Sunday
+        let start = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let end = NaiveDate::from_ymd_opt(2026, 1, 18).unwrap();
+        let weekend = DateRange::new(start, end).weekend().unwrap();
+
+        assert_eq!(weekend.start, NaiveDate::from_ymd_opt(2026, 1, 16).unwrap());
+        assert_eq!(weekend.end, end);
+    }
+
+    #[test]
+    fn test_weekend_starting_on_saturday() {
+        // Saturday to Friday
+        let start = NaiveDate::from_ymd_opt(2026, 1, 17).unwrap();
+        let end = NaiveDate::from_ymd_opt(2026, 1, 23).unwrap();
+        let weekend = DateRange::new(start, end).weekend().unwrap();
+
+        assert_eq!(weekend.start, start);
+        assert_eq!(weekend.end, NaiveDate::from_ymd_opt(2026, 1, 18).unwrap());
+    }
 }
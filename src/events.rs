@@ -1,9 +1,14 @@
+use chrono::{Days, NaiveDate};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, hash::Hash};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    hash::Hash,
+};
 
-use crate::dates::TimeFrame;
+use crate::dates::{DateRange, Showtime, TimeFrame};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Category {
     pub name: String,
     pub events: Vec<Event>,
@@ -20,6 +25,13 @@ pub struct Event {
     pub description: Option<String>,
     pub summary: Option<String>,
     pub tags: HashSet<String>,
+    /// Venue-specific extras that don't warrant a dedicated field, such as
+    /// `director`, `cast`, `duration` or `age_rating`.
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// The time of the show on each date, for venues that publish it.
+    #[serde(default)]
+    pub showtimes: BTreeMap<NaiveDate, Showtime>,
 }
 
 impl PartialEq for Event {
@@ -48,6 +60,18 @@ impl Hash for Event {
     }
 }
 
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut locations: Vec<&Location> = self.locations.iter().collect();
+        locations.sort_by(|a, b| a.name.cmp(&b.name));
+        let locations: Vec<String> = locations.iter().map(|l| l.to_string()).collect();
+        match locations.is_empty() {
+            true => write!(f, "{}", self.title),
+            false => write!(f, "{} @ {}", self.title, locations.join(", ")),
+        }
+    }
+}
+
 impl Event {
     pub fn new(title: &str, locations: HashSet<Location>, category: &str) -> Self {
         Self {
@@ -59,6 +83,8 @@ impl Event {
             description: None,
             summary: None,
             tags: HashSet::new(),
+            metadata: HashMap::new(),
+            showtimes: BTreeMap::new(),
         }
     }
 
@@ -87,14 +113,117 @@ impl Event {
     pub fn with_tags(self: Self, tags: HashSet<String>) -> Self {
         Self { tags, ..self }
     }
+
+    pub fn with_metadata(self: Self, metadata: HashMap<String, String>) -> Self {
+        Self { metadata, ..self }
+    }
+
+    pub fn with_showtimes(self: Self, showtimes: BTreeMap<NaiveDate, Showtime>) -> Self {
+        Self { showtimes, ..self }
+    }
+
+    /// A key to recognize the same event listed by different sources, which may not
+    /// agree on the casing, punctuation and spacing of its title.
+    pub fn dedup_key(&self) -> String {
+        return dedup_key(&self.title);
+    }
+
+    /// Restricts the event to the dates within the range. Returns `None` if the
+    /// event doesn't happen within the range or its dates are unknown.
+    pub fn clamp(self: Self, range: &DateRange) -> Option<Self> {
+        let time_frame = self.time_frame.as_ref()?.clamp(range)?;
+        let mut event = self.with_time_frame(Some(time_frame));
+        event.showtimes.retain(|date, _| range.contains(date));
+        Some(event)
+    }
+}
+
+/// Finds the events happening within `radius_days` days of `center`, either way,
+/// sorted by how close their earliest date is to `center`.
+pub fn events_near(events: &[Event], center: NaiveDate, radius_days: i64) -> Vec<&Event> {
+    let radius = Days::new(radius_days.unsigned_abs());
+    let window = DateRange::new(center - radius, center + radius);
+
+    let mut near: Vec<&Event> = events
+        .iter()
+        .filter(|e| {
+            e.time_frame
+                .as_ref()
+                .is_some_and(|tf| tf.clamp(&window).is_some())
+        })
+        .collect();
+    near.sort_by_key(|e| {
+        let first_date = e.time_frame.as_ref().unwrap().as_range().start;
+        (first_date - center).num_days().abs()
+    });
+    return near;
+}
+
+/// Merges the events listed in more than one category, such as a book presentation
+/// scraped from both a bookstore and a theater, into the category they appear in
+/// first. Events match if they have the same [dedup_key] and a day in common. The
+/// merged event gets the locations and dates of both and the longer description,
+/// along with its summary. Events within the same category are left alone, as film variants
+/// share their title on purpose.
+pub fn merge_across_categories(categories: &mut Vec<Category>) {
+    for index in 1..categories.len() {
+        let (earlier, rest) = categories.split_at_mut(index);
+        rest[0].events.retain(|event| {
+            let twin = earlier
+                .iter_mut()
+                .flat_map(|c| c.events.iter_mut())
+                .find(|e| is_same_event(e, event));
+            let Some(twin) = twin else {
+                return true;
+            };
+            merge_locations(&mut twin.locations, event.locations.clone());
+            merge_dates(twin, event.clone());
+            let longer = |d: &Option<String>| d.as_ref().map_or(0, |d| d.chars().count());
+            if longer(&event.description) > longer(&twin.description) {
+                twin.description = event.description.clone();
+                twin.summary = event.summary.clone();
+            }
+            return false;
+        });
+    }
+    categories.retain(|c| !c.events.is_empty());
+}
+
+/// Adds to an event the dates and showtimes of `other`, another listing of the same
+/// event, such as the same film from an older cache with days that have since
+/// scrolled off the venue's listing, another occurrence of a recurring event or the
+/// same event listed in another category.
+pub fn merge_dates(event: &mut Event, other: Event) {
+    event.time_frame = match (event.time_frame.take(), other.time_frame) {
+        (Some(time_frame), Some(other_time_frame)) => Some(time_frame.merge(other_time_frame)),
+        (time_frame, other_time_frame) => time_frame.or(other_time_frame),
+    };
+    for (date, showtime) in other.showtimes {
+        event.showtimes.entry(date).or_insert(showtime);
+    }
+}
+
+fn is_same_event(a: &Event, b: &Event) -> bool {
+    let shares_day = match (&a.time_frame, &b.time_frame) {
+        (Some(a), Some(b)) => a.shares_day_with(b),
+        _ => false,
+    };
+    return shares_day && a.dedup_key() == b.dedup_key();
 }
 
+/// Separates the name of a venue from the hall of a location within it, as in
+/// "Rossetti – Sala Bartoli".
+pub const HALL_SEPARATOR: &str = " – ";
+
 /// A location for an event, possibly with a URL to a website with info
 /// about the event at that location.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Location {
     pub name: String,
     pub url: Option<String>,
+    /// The street address of the venue, if known.
+    #[serde(default)]
+    pub address: Option<String>,
 }
 
 impl PartialEq for Location {
@@ -116,6 +245,257 @@ impl Location {
         Self {
             name: name.to_string(),
             url,
+            address: None,
+        }
+    }
+
+    pub fn with_address(self: Self, address: Option<String>) -> Self {
+        Self { address, ..self }
+    }
+
+    /// The name of the venue, without the hall if the location is one of its halls.
+    pub fn venue(&self) -> &str {
+        return self.name.split(HALL_SEPARATOR).next().unwrap_or(&self.name);
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.url {
+            Some(url) => write!(f, "{} <{url}>", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Adds locations to a set. Locations are the same if they have the same name, so
+/// one known both with and without a URL is kept with its URL.
+pub fn merge_locations(locations: &mut HashSet<Location>, others: HashSet<Location>) {
+    for location in others {
+        let has_url = locations.get(&location).is_some_and(|l| l.url.is_some());
+        if !has_url {
+            locations.replace(location);
+        }
+    }
+}
+
+/// The key of [Event::dedup_key] for a title: its lowercase words, without punctuation.
+pub fn dedup_key(title: &str) -> String {
+    return title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::DateSet;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 1, day).unwrap()
+    }
+
+    #[test]
+    fn test_weekend_filter() {
+        // Monday 12 to Sunday 18 January 2026
+        let week = DateRange::new(date(12), date(18));
+        let weekend = week.weekend().unwrap();
+
+        let tuesday = Event::new("Martedì", HashSet::new(), "Teatri").with_time_frame(Some(
+            TimeFrame::Dates(DateSet::new(vec![date(13)]).unwrap()),
+        ));
+        let fri_to_sun = Event::new("Weekend", HashSet::new(), "Teatri").with_time_frame(Some(
+            TimeFrame::Dates(DateSet::new(vec![date(16), date(17), date(18)]).unwrap()),
+        ));
+        let all_week = Event::new("Mostra", HashSet::new(), "Mostre")
+            .with_time_frame(Some(TimeFrame::Period(DateRange::new(date(1), date(31)))));
+
+        assert!(tuesday.clamp(&weekend).is_none());
+        let fri_to_sun = fri_to_sun.clamp(&weekend).unwrap();
+        assert_eq!(fri_to_sun.time_frame.unwrap().as_range().num_days(), 3);
+        let all_week = all_week.clamp(&weekend).unwrap();
+        let range = all_week.time_frame.unwrap().as_range();
+        assert_eq!(range.start, date(16));
+        assert_eq!(range.end, date(18));
+    }
+
+    #[test]
+    fn test_metadata_survives_cache_round_trip() {
+        let metadata = HashMap::from([
+            ("director".to_string(), "Denis Villeneuve".to_string()),
+            ("duration".to_string(), "166'".to_string()),
+        ]);
+        let event = Event::new("Dune", HashSet::new(), "Film").with_metadata(metadata.clone());
+
+        let json = serde_json::to_string(&vec![event]).unwrap();
+        let events: Vec<Event> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(events[0].metadata, metadata);
+    }
+
+    #[test]
+    fn test_metadata_defaults_to_empty_for_old_caches() {
+        let json = r#"{
+            "id": "dune",
+            "title": "Dune",
+            "time_frame": null,
+            "locations": [],
+            "category": "Film",
+            "description": null,
+            "summary": null,
+            "tags": []
+        }"#;
+        let event: Event = serde_json::from_str(json).unwrap();
+
+        assert!(event.metadata.is_empty());
+    }
+
+    fn on(title: &str, days: &[u32]) -> Event {
+        let dates = DateSet::new(days.iter().map(|d| date(*d)).collect()).unwrap();
+        Event::new(title, HashSet::new(), "Teatri").with_time_frame(Some(TimeFrame::Dates(dates)))
+    }
+
+    fn titles(events: Vec<&Event>) -> Vec<&str> {
+        events.iter().map(|e| e.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_events_near_exact_day() {
+        let events = vec![on("Amleto", &[14]), on("Macbeth", &[15])];
+        assert_eq!(titles(events_near(&events, date(14), 0)), vec!["Amleto"]);
+    }
+
+    #[test]
+    fn test_events_near_within_radius_sorted_by_proximity() {
+        let events = vec![
+            on("Amleto", &[16]),
+            on("Macbeth", &[13]),
+            on("Otello", &[14]),
+            // Its earliest date is outside the window, but the second one is inside
+            on("Tempesta", &[5, 15]),
+        ];
+        assert_eq!(
+            titles(events_near(&events, date(14), 2)),
+            vec!["Otello", "Macbeth", "Amleto", "Tempesta"]
+        );
+    }
+
+    #[test]
+    fn test_events_near_just_outside_radius() {
+        let events = vec![
+            on("Amleto", &[11]),
+            on("Macbeth", &[17]),
+            on("Otello", &[5, 20]),
+        ];
+        assert!(events_near(&events, date(14), 2).is_empty());
+    }
+
+    #[test]
+    fn test_location_with_url_wins_merge() {
+        let url = Some("https://www.ilrossetti.it/it/stagione/amleto".to_string());
+        let mut locations = HashSet::from([Location::new("Rossetti", None)]);
+
+        merge_locations(
+            &mut locations,
+            HashSet::from([Location::new("Rossetti", url.clone())]),
+        );
+        merge_locations(
+            &mut locations,
+            HashSet::from([Location::new("Rossetti", None)]),
+        );
+
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations.iter().next().unwrap().url, url);
+    }
+
+    #[test]
+    fn test_display_links_locations() {
+        let locations = HashSet::from([
+            Location::new("Verdi", None),
+            Location::new("Rossetti", Some("https://www.ilrossetti.it".to_string())),
+        ]);
+        let event = Event::new("Amleto", locations, "Teatri");
+
+        assert_eq!(
+            event.to_string(),
+            "Amleto @ Rossetti <https://www.ilrossetti.it>, Verdi"
+        );
+    }
+
+    #[test]
+    fn test_merge_across_categories() {
+        let event = |title: &str, location: &str, day, description: &str| {
+            Event::new(
+                title,
+                HashSet::from([Location::new(location, None)]),
+                "Teatri",
+            )
+            .with_time_frame(Some(TimeFrame::Dates(
+                DateSet::new(vec![date(day)]).unwrap(),
+            )))
+            .with_description(Some(description.to_string()))
+        };
+        let mut categories = vec![
+            Category {
+                name: "Librerie".to_string(),
+                events: vec![event("Incontro con l'autore", "Lovat", 12, "Breve.")],
+            },
+            Category {
+                name: "Teatri".to_string(),
+                events: vec![
+                    event(
+                        "INCONTRO CON L'AUTORE",
+                        "Miela",
+                        12,
+                        "Una descrizione più lunga.",
+                    ),
+                    event("Incontro con l'autore", "Miela", 20, "Un altro giorno."),
+                ],
+            },
+        ];
+
+        merge_across_categories(&mut categories);
+
+        assert_eq!(categories[0].events.len(), 1);
+        let merged = &categories[0].events[0];
+        assert_eq!(merged.locations.len(), 2);
+        assert_eq!(
+            merged.description.as_deref(),
+            Some("Una descrizione più lunga.")
+        );
+        // The same title on a different day is a different event
+        assert_eq!(categories[1].events.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_across_categories_keeps_every_day() {
+        let event = |category: &str, days: Vec<u32>| {
+            let dates = days.into_iter().map(date).collect();
+            Event::new("Concerto", HashSet::new(), category)
+                .with_time_frame(Some(TimeFrame::Dates(DateSet::new(dates).unwrap())))
+        };
+        let mut categories = vec![
+            Category {
+                name: "Teatri".to_string(),
+                events: vec![event("Teatri", vec![11, 12])],
+            },
+            Category {
+                name: "Musica".to_string(),
+                events: vec![event("Musica", vec![12, 13])],
+            },
+        ];
+
+        merge_across_categories(&mut categories);
+
+        assert_eq!(categories.len(), 1);
+        match &categories[0].events[0].time_frame {
+            Some(TimeFrame::Dates(set)) => {
+                assert_eq!(set.dates(), &vec![date(11), date(12), date(13)])
+            }
+            other => panic!("unexpected time frame {other:?}"),
         }
     }
 }
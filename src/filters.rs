@@ -0,0 +1,287 @@
+use std::collections::HashSet;
+
+use chrono::{NaiveDateTime, NaiveTime};
+
+use crate::{
+    dates::TimeFrame,
+    events::{Category, Event, Location},
+};
+
+/// Keeps only the events with some tags, such as films in 3D.
+pub struct TagFilter {
+    /// The tags to look for, compared case-insensitively.
+    pub tags: Vec<String>,
+    /// Whether events must have all of the tags, rather than any of them.
+    pub match_all: bool,
+}
+
+impl TagFilter {
+    /// Drops the events that don't have the requested tags. Variants of a film are
+    /// saved as separate events with the same title, so the whole film is kept if
+    /// any of its variants matches.
+    pub fn apply(&self, categories: &mut Vec<Category>) {
+        for category in categories.iter_mut() {
+            let matching_titles: HashSet<String> = category
+                .events
+                .iter()
+                .filter(|e| self.matches(e))
+                .map(|e| e.title.clone())
+                .collect();
+            category
+                .events
+                .retain(|e| matching_titles.contains(&e.title));
+        }
+        categories.retain(|c| !c.events.is_empty());
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        let has_tag = |tag: &String| event.tags.iter().any(|t| t.eq_ignore_ascii_case(tag));
+        return match self.match_all {
+            true => self.tags.iter().all(has_tag),
+            false => self.tags.iter().any(has_tag),
+        };
+    }
+}
+
+/// Hides the events at some venues, matched case-insensitively against the name of
+/// their locations. This only affects the output, as venues are still scraped and cached.
+pub struct VenueFilter {
+    /// If not empty, only these venues are kept.
+    pub only: Vec<String>,
+    /// Venues to drop.
+    pub exclude: Vec<String>,
+}
+
+impl VenueFilter {
+    /// Removes the filtered out locations from the events, then drops the events left
+    /// without any. Events without locations at all are only dropped by `only`.
+    pub fn apply(&self, categories: &mut Vec<Category>) {
+        for category in categories.iter_mut() {
+            category.events.retain_mut(|event| {
+                if event.locations.is_empty() {
+                    return self.only.is_empty();
+                }
+                event.locations.retain(|l| self.keeps(l));
+                return !event.locations.is_empty();
+            });
+        }
+        categories.retain(|c| !c.events.is_empty());
+    }
+
+    fn keeps(&self, location: &Location) -> bool {
+        let named = |venues: &Vec<String>| {
+            venues.iter().any(|v| {
+                v.trim().eq_ignore_ascii_case(location.name.trim())
+                    || v.trim().eq_ignore_ascii_case(location.venue().trim())
+            })
+        };
+        return (self.only.is_empty() || named(&self.only)) && !named(&self.exclude);
+    }
+}
+
+/// Hides the events that are already over, for a view of what's still to come.
+pub struct UpcomingFilter {
+    pub now: NaiveDateTime,
+}
+
+impl UpcomingFilter {
+    /// Drops the events whose last date is before `now`. On the last day, an event is
+    /// over once its showtime has ended, or started if its end isn't known, while
+    /// events without a showtime are kept for the whole day. Events with unknown
+    /// dates are kept.
+    pub fn apply(&self, categories: &mut Vec<Category>) {
+        for category in categories.iter_mut() {
+            category.events.retain(|event| !self.is_over(event));
+        }
+        categories.retain(|c| !c.events.is_empty());
+    }
+
+    fn is_over(&self, event: &Event) -> bool {
+        let last_date = match &event.time_frame {
+            Some(TimeFrame::Dates(set)) => set.last(),
+            Some(TimeFrame::Period(range)) => range.end,
+            None => return false,
+        };
+        let last_time = match event.showtimes.get(&last_date) {
+            Some(showtime) => showtime.end.unwrap_or(showtime.start),
+            None => NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
+        };
+        return last_date.and_time(last_time) < self.now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::dates::{DateRange, DateSet, Showtime};
+
+    fn event(title: &str, category: &str, tags: &[&str]) -> Event {
+        let tags: HashSet<String> = tags.iter().map(|t| t.to_string()).collect();
+        Event::new(title, HashSet::new(), category).with_tags(tags)
+    }
+
+    #[test]
+    fn test_filter_down_to_3d() {
+        let mut categories = vec![
+            Category {
+                name: "Film".to_string(),
+                events: vec![
+                    event("Avatar", "Film", &[]),
+                    event("Avatar", "Film", &["3D"]),
+                    event("Dune", "Film", &[]),
+                    event("Dune", "Film", &["Originale"]),
+                ],
+            },
+            Category {
+                name: "Teatri".to_string(),
+                events: vec![event("Amleto", "Teatri", &["Prima nazionale"])],
+            },
+        ];
+        let filter = TagFilter {
+            tags: vec!["3d".to_string()],
+            match_all: false,
+        };
+        filter.apply(&mut categories);
+
+        assert_eq!(categories.len(), 1);
+        let films: Vec<(&str, usize)> = categories[0]
+            .events
+            .iter()
+            .map(|e| (e.title.as_str(), e.tags.len()))
+            .collect();
+        // The 2D variant is kept along with the 3D one
+        assert_eq!(films, vec![("Avatar", 0), ("Avatar", 1)]);
+    }
+
+    #[test]
+    fn test_match_all_tags() {
+        let mut categories = vec![Category {
+            name: "Film".to_string(),
+            events: vec![
+                event("Avatar", "Film", &["3D"]),
+                event("Dune", "Film", &["3D", "Originale"]),
+            ],
+        }];
+        let filter = TagFilter {
+            tags: vec!["3D".to_string(), "Originale".to_string()],
+            match_all: true,
+        };
+        filter.apply(&mut categories);
+
+        assert_eq!(categories[0].events.len(), 1);
+        assert_eq!(categories[0].events[0].title, "Dune");
+    }
+
+    #[test]
+    fn test_exclude_venue() {
+        let locations = |names: &[&str]| -> HashSet<Location> {
+            names.iter().map(|n| Location::new(n, None)).collect()
+        };
+        let mut categories = vec![Category {
+            name: "Film".to_string(),
+            events: vec![
+                Event::new("Avatar", locations(&["The Space"]), "Film"),
+                Event::new("Dune", locations(&["The Space", "Ariston"]), "Film"),
+                Event::new("Amleto", HashSet::new(), "Film"),
+            ],
+        }];
+        let filter = VenueFilter {
+            only: Vec::new(),
+            exclude: vec!["the space".to_string()],
+        };
+        filter.apply(&mut categories);
+
+        let events: Vec<(&str, usize)> = categories[0]
+            .events
+            .iter()
+            .map(|e| (e.title.as_str(), e.locations.len()))
+            .collect();
+        assert_eq!(events, vec![("Dune", 1), ("Amleto", 0)]);
+    }
+
+    #[test]
+    fn test_only_venue() {
+        let mut categories = vec![Category {
+            name: "Film".to_string(),
+            events: vec![
+                Event::new(
+                    "Avatar",
+                    HashSet::from([Location::new("The Space", None)]),
+                    "Film",
+                ),
+                Event::new("Amleto", HashSet::new(), "Film"),
+            ],
+        }];
+        let filter = VenueFilter {
+            only: vec!["Ariston".to_string()],
+            exclude: Vec::new(),
+        };
+        filter.apply(&mut categories);
+
+        assert!(categories.is_empty());
+    }
+
+    #[test]
+    fn test_venue_matches_its_halls() {
+        let mut categories = vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![Event::new(
+                "Amleto",
+                HashSet::from([Location::new("Rossetti – Sala Bartoli", None)]),
+                "Teatri",
+            )],
+        }];
+        let filter = VenueFilter {
+            only: vec!["rossetti".to_string()],
+            exclude: Vec::new(),
+        };
+        filter.apply(&mut categories);
+
+        assert_eq!(categories[0].events.len(), 1);
+    }
+
+    #[test]
+    fn test_upcoming_only_drops_past_events() {
+        let date = |day| NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+        let time = |hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap();
+        let on = |title: &str, days: &[u32]| {
+            let dates = DateSet::new(days.iter().map(|d| date(*d)).collect()).unwrap();
+            Event::new(title, HashSet::new(), "Teatri")
+                .with_time_frame(Some(TimeFrame::Dates(dates)))
+        };
+        let at = |title: &str, hour| {
+            let showtime = Showtime {
+                start: time(hour),
+                end: None,
+            };
+            on(title, &[14]).with_showtimes(BTreeMap::from([(date(14), showtime)]))
+        };
+        let mut categories = vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![
+                on("Ieri", &[12, 13]),
+                on("Ancora oggi", &[12, 14]),
+                at("Stamattina", 10),
+                at("Stasera", 21),
+                Event::new("Mostra chiusa", HashSet::new(), "Teatri")
+                    .with_time_frame(Some(TimeFrame::Period(DateRange::new(date(1), date(13))))),
+                Event::new("Da confermare", HashSet::new(), "Teatri"),
+            ],
+        }];
+        let filter = UpcomingFilter {
+            now: date(14).and_time(time(18)),
+        };
+        filter.apply(&mut categories);
+
+        let titles: Vec<&str> = categories[0]
+            .events
+            .iter()
+            .map(|e| e.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["Ancora oggi", "Stasera", "Da confermare"]);
+    }
+}
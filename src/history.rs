@@ -0,0 +1,136 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::events::{Category, Event};
+
+/// Name of the file, within a profile's cache directory, where the history is stored.
+pub const HISTORY_FILE: &str = "seen.json";
+
+/// Remembers the date each event was first listed on, so that events can be
+/// recognized across runs (e.g., to spot films that have been running for weeks).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SeenHistory {
+    first_seen: HashMap<String, NaiveDate>,
+}
+
+impl SeenHistory {
+    /// Loads the history from `path`, or returns an empty one if it doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !fs::exists(path)? {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        return Ok(serde_json::from_str(&content)?);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        return Ok(());
+    }
+
+    /// Marks every event not seen before as first seen on `today`.
+    pub fn record(&mut self, categories: &[Category], today: NaiveDate) {
+        for event in categories.iter().flat_map(|c| &c.events) {
+            self.first_seen.entry(Self::key(event)).or_insert(today);
+        }
+    }
+
+    pub fn first_seen(&self, event: &Event) -> Option<NaiveDate> {
+        self.first_seen.get(&Self::key(event)).copied()
+    }
+
+    fn key(event: &Event) -> String {
+        format!("{}/{}", event.category, event.id)
+    }
+}
+
+/// Hides events that have been listed for a long time, such as blockbusters
+/// that stay in cinemas for months.
+pub struct RerunFilter {
+    /// Events first seen more than this many weeks ago are dropped.
+    pub weeks: u64,
+    /// The categories the filter applies to. Events in other categories are kept.
+    pub categories: Vec<String>,
+}
+
+impl RerunFilter {
+    /// Drops the events of the filtered categories that were first seen more than
+    /// `weeks` weeks before `today`. Events missing from the history are kept.
+    pub fn apply(&self, categories: &mut Vec<Category>, history: &SeenHistory, today: NaiveDate) {
+        let threshold = today - Days::new(self.weeks * 7);
+
+        for category in categories.iter_mut() {
+            if !self.categories.contains(&category.name) {
+                continue;
+            }
+            category
+                .events
+                .retain(|e| history.first_seen(e).is_none_or(|date| date >= threshold));
+        }
+        categories.retain(|c| !c.events.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, month, day).unwrap()
+    }
+
+    fn category(name: &str, titles: &[&str]) -> Category {
+        Category {
+            name: name.to_string(),
+            events: titles
+                .iter()
+                .map(|t| Event::new(t, HashSet::new(), name))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_old_films_are_hidden() {
+        let mut history = SeenHistory::default();
+        history.record(&[category("Film", &["Avatar"])], date(1, 5));
+        history.record(&[category("Teatri", &["Amleto"])], date(1, 5));
+        history.record(&[category("Film", &["Dune"])], date(2, 2));
+
+        let mut categories = vec![
+            category("Film", &["Avatar", "Dune", "Nuovo"]),
+            category("Teatri", &["Amleto"]),
+        ];
+        let filter = RerunFilter {
+            weeks: 3,
+            categories: vec!["Film".to_string()],
+        };
+        filter.apply(&mut categories, &history, date(2, 9));
+
+        let films: Vec<&str> = categories[0]
+            .events
+            .iter()
+            .map(|e| e.title.as_str())
+            .collect();
+        assert_eq!(films, vec!["Dune", "Nuovo"]);
+        // Theaters are exempt even though Amleto is just as old as Avatar
+        assert_eq!(categories[1].events[0].title, "Amleto");
+    }
+
+    #[test]
+    fn test_record_keeps_first_date() {
+        let mut history = SeenHistory::default();
+        let films = [category("Film", &["Dune"])];
+        history.record(&films, date(1, 5));
+        history.record(&films, date(1, 12));
+
+        assert_eq!(history.first_seen(&films[0].events[0]), Some(date(1, 5)));
+    }
+}
@@ -1,11 +1,62 @@
 #![allow(unused)]
 
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
 use anyhow::{Result, bail};
+use clap::ValueEnum;
 use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use tracing::{Instrument, info_span, warn};
+
+use crate::utils::normalize_whitespace;
 
 pub(super) const SUMMARY_PROMPT: &str = "Accorcia la seguente descrizione di un evento a non più di un paragrafo. Se la descrizione è già un paragrafo o meno, ripetila verbatim. Non andare a capo. Rispondi esclusivamente in testo semplice. Non usare markdown.";
 
+const SHORT_SUMMARY_PROMPT: &str = "Accorcia la seguente descrizione di un evento a non più di due frasi. Se la descrizione è già di due frasi o meno, ripetila verbatim. Non andare a capo. Rispondi esclusivamente in testo semplice. Non usare markdown.";
+
+const LONG_SUMMARY_PROMPT: &str = "Accorcia la seguente descrizione di un evento a non più di tre paragrafi. Se la descrizione è già di tre paragrafi o meno, ripetila verbatim. Rispondi esclusivamente in testo semplice. Non usare markdown.";
+
+/// Token cap for requests that don't ask for anything more specific.
+pub const DEFAULT_MAX_TOKENS: u32 = 2048;
+
+/// Low enough for summaries to stick to the description rather than embellish it.
+pub const DEFAULT_TEMPERATURE: f32 = 0.1;
+
+/// How long to wait for a completion before giving up on it. Generating text can
+/// take much longer than fetching a page, so this is separate from fetch timeouts.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Beginnings of answers in which the model refuses or fails to do the task.
+const REFUSAL_PHRASES: &[&str] = &[
+    "non posso",
+    "mi dispiace",
+    "non sono in grado",
+    "non è possibile",
+    "come modello linguistico",
+    "i can't",
+    "i cannot",
+    "i'm sorry",
+    "as an ai",
+];
+
+/// Sentences that come from the page around a description rather than from the
+/// description itself, such as cookie notices and ticket buttons.
+const BOILERPLATE_PHRASES: &[&str] = &[
+    "acquista biglietti",
+    "acquista i biglietti",
+    "acquista il biglietto",
+    "utilizza cookie",
+    "utilizza i cookie",
+    "usa i cookie",
+    "cookie policy",
+    "accetta i cookie",
+];
+
+pub(super) const INTRO_PROMPT: &str = "Scrivi un breve paragrafo introduttivo su cosa fare questa settimana, basandoti sui seguenti eventi. Cita solo alcuni eventi tra i più interessanti. Non andare a capo. Rispondi esclusivamente in testo semplice. Non usare markdown.";
+
 /// Request body for OpenAI-compatible API
 #[derive(Serialize)]
 struct ChatCompletionRequest {
@@ -40,11 +91,56 @@ struct CompletionMessage {
     content: String,
 }
 
+/// How long event summaries should be.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SummaryLength {
+    /// A couple of sentences.
+    Short,
+    /// A single paragraph.
+    #[default]
+    Medium,
+    /// Up to three paragraphs.
+    Long,
+}
+
+impl SummaryLength {
+    pub fn prompt(&self) -> &'static str {
+        match self {
+            SummaryLength::Short => SHORT_SUMMARY_PROMPT,
+            SummaryLength::Medium => SUMMARY_PROMPT,
+            SummaryLength::Long => LONG_SUMMARY_PROMPT,
+        }
+    }
+
+    /// The token cap for the summary, which leaves some room over the length asked
+    /// in the prompt so that answers aren't cut short.
+    pub fn max_tokens(&self) -> u32 {
+        match self {
+            SummaryLength::Short => 512,
+            SummaryLength::Medium => DEFAULT_MAX_TOKENS,
+            SummaryLength::Long => 4096,
+        }
+    }
+}
+
 pub struct InferenceService {
     api_url: String,
     api_key: String,
     model: String,
     client: Client,
+    summary_length: SummaryLength,
+    /// Descriptions up to this many characters are used as their own summary.
+    summary_threshold: usize,
+    /// How long to wait for each completion.
+    timeout: Duration,
+    temperature: f32,
+    /// Token cap for requests other than summaries, such as the intro.
+    max_tokens: u32,
+    /// Token cap for summaries, instead of the one of the summary length.
+    summary_max_tokens: Option<u32>,
+    /// Whether requests are skipped, e.g. when working offline. Atomic so that the
+    /// shared service can be turned off after being configured.
+    disabled: AtomicBool,
 }
 
 impl InferenceService {
@@ -54,21 +150,116 @@ impl InferenceService {
             api_key: api_key.to_string(),
             model: model.to_string(),
             client,
+            summary_length: SummaryLength::default(),
+            summary_threshold: 0,
+            timeout: DEFAULT_TIMEOUT,
+            temperature: DEFAULT_TEMPERATURE,
+            max_tokens: DEFAULT_MAX_TOKENS,
+            summary_max_tokens: None,
+            disabled: AtomicBool::new(api_url.trim().is_empty()),
         }
     }
 
-    /// Infers with a language model from an OpenAI-compatible API.
-    pub async fn infer(&self, prompt: &str) -> Result<String> {
-        let request = ChatCompletionRequest {
-            model: self.model.clone(),
-            messages: vec![Message {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            }],
-            temperature: 0.1,
-            max_tokens: Some(2048),
-        };
+    /// Skips all requests from now on: summaries are left out and other inference
+    /// fails. Services without an API URL start out disabled.
+    pub fn disable(&self) {
+        self.disabled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return !self.disabled.load(Ordering::Relaxed);
+    }
+
+    /// Sample answers with `temperature`. Higher values give more varied answers.
+    pub fn with_temperature(self, temperature: f32) -> Self {
+        Self {
+            temperature,
+            ..self
+        }
+    }
+
+    /// Cap the answers to requests other than summaries to `max_tokens` tokens.
+    pub fn with_max_tokens(self, max_tokens: u32) -> Self {
+        Self { max_tokens, ..self }
+    }
+
+    /// Cap summaries to `summary_max_tokens` tokens, whatever their length. Without
+    /// it, the cap depends on the [SummaryLength].
+    pub fn with_summary_max_tokens(self, summary_max_tokens: Option<u32>) -> Self {
+        Self {
+            summary_max_tokens,
+            ..self
+        }
+    }
+
+    /// Abort completions that take longer than `timeout`, such as a stuck generation.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Only summarize descriptions longer than `summary_threshold` characters, and
+    /// use shorter ones as they are, since they're already concise enough.
+    pub fn with_summary_threshold(self, summary_threshold: usize) -> Self {
+        Self {
+            summary_threshold,
+            ..self
+        }
+    }
 
+    pub fn with_summary_length(self, summary_length: SummaryLength) -> Self {
+        Self {
+            summary_length,
+            ..self
+        }
+    }
+
+    /// Infers with a language model from an OpenAI-compatible API, with an answer
+    /// of at most `max_tokens` tokens.
+    pub async fn infer(&self, prompt: &str, max_tokens: u32) -> Result<String> {
+        if !self.is_enabled() {
+            bail!("Inference is disabled");
+        }
+        return self
+            .request_completion(prompt, max_tokens)
+            .instrument(info_span!("inference", model = self.model))
+            .await;
+    }
+
+    /// Shortens an event description to the configured [SummaryLength]. Returns `None`
+    /// if the request fails or the model's answer is not a usable summary, in which
+    /// case the event should fall back to its description. Descriptions within the
+    /// summary threshold are returned as they are, without inference. Returns `None`
+    /// right away if the service is disabled.
+    pub async fn summarize_description(&self, description: &str) -> Option<String> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let description = clean_description(description);
+        if description.is_empty() {
+            return None;
+        }
+        if description.chars().count() <= self.summary_threshold {
+            return Some(description);
+        }
+
+        let instructions = self.summary_length.prompt();
+        let prompt = format!("{instructions}\n\n{description}");
+        let max_tokens = self
+            .summary_max_tokens
+            .unwrap_or(self.summary_length.max_tokens());
+        let summary = self
+            .infer(&prompt, max_tokens)
+            .await
+            .inspect_err(|err| warn!("Failed to generate summary: {err}"))
+            .ok()?;
+
+        return check_summary(instructions, &summary)
+            .inspect_err(|reason| warn!("Discarding summary: {reason}"))
+            .ok();
+    }
+
+    async fn request_completion(&self, prompt: &str, max_tokens: u32) -> Result<String> {
+        let request = self.completion_request(prompt, max_tokens);
         let response = self.build_request(request).send().await?;
 
         if !response.status().is_success() {
@@ -86,11 +277,24 @@ impl InferenceService {
         }
     }
 
+    fn completion_request(&self, prompt: &str, max_tokens: u32) -> ChatCompletionRequest {
+        ChatCompletionRequest {
+            model: self.model.clone(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: prompt.to_string(),
+            }],
+            temperature: self.temperature,
+            max_tokens: Some(max_tokens),
+        }
+    }
+
     fn build_request(&self, request: ChatCompletionRequest) -> RequestBuilder {
         self.client
             .post(format!("{}/chat/completions", self.api_url))
             .header("Content-Type", "application/json")
             .header("Authorization", format!("Bearer {}", self.api_key))
+            .timeout(self.timeout)
             .json(&request)
     }
 
@@ -98,3 +302,231 @@ impl InferenceService {
         return text.replace("*", "").replace("—", ", ");
     }
 }
+
+/// Something that can produce text from a prompt, such as a language model.
+#[allow(async_fn_in_trait)]
+pub trait Summarizer {
+    async fn summarize(&self, prompt: &str) -> Result<String>;
+}
+
+impl Summarizer for InferenceService {
+    async fn summarize(&self, prompt: &str) -> Result<String> {
+        return self.infer(prompt, self.max_tokens).await;
+    }
+}
+
+/// Cleans up a description before sending it to a model, to save tokens and avoid
+/// summarizing noise: drops sentences that are boilerplate of the page and sentences
+/// repeating the previous one. Line breaks between paragraphs are kept.
+pub fn clean_description(description: &str) -> String {
+    let mut previous: Option<String> = None;
+    let mut paragraphs = Vec::new();
+    for line in description.lines() {
+        let mut sentences = Vec::new();
+        for sentence in split_sentences(line) {
+            let normalized = normalize_whitespace(sentence).to_lowercase();
+            if BOILERPLATE_PHRASES.iter().any(|p| normalized.contains(p)) {
+                continue;
+            }
+            if previous.as_ref() == Some(&normalized) {
+                continue;
+            }
+            sentences.push(normalize_whitespace(sentence));
+            previous = Some(normalized);
+        }
+        if !sentences.is_empty() {
+            paragraphs.push(sentences.join(" "));
+        }
+    }
+    return paragraphs.join("\n");
+}
+
+/// Splits a line into its sentences, each ending with its punctuation, if any.
+fn split_sentences(line: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = line.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let at_boundary = chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if matches!(c, '.' | '!' | '?' | '…') && at_boundary {
+            let end = index + c.len_utf8();
+            sentences.push(&line[start..end]);
+            start = end;
+        }
+    }
+    sentences.push(&line[start..]);
+    return sentences
+        .into_iter()
+        .filter(|s| !s.trim().is_empty())
+        .collect();
+}
+
+/// Rejects degenerate model outputs, such as empty answers, answers repeating the
+/// instructions of the prompt or refusals to do the task.
+fn check_summary(instructions: &str, summary: &str) -> Result<String, &'static str> {
+    let trimmed = summary.trim();
+    if trimmed.is_empty() {
+        return Err("the summary is empty");
+    }
+
+    let lowercase = trimmed.to_lowercase();
+    if lowercase.contains(&instructions.trim().to_lowercase()) {
+        return Err("the summary repeats the prompt");
+    }
+    if REFUSAL_PHRASES.iter().any(|p| lowercase.starts_with(p)) {
+        return Err("the model refused to summarize");
+    }
+
+    return Ok(trimmed.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    const DESCRIPTION: &str = "Uno spettacolo di teatro.";
+
+    #[test]
+    fn test_echoed_prompt_is_rejected() {
+        let echo = format!("{SUMMARY_PROMPT}\n\n{DESCRIPTION}");
+        assert!(check_summary(SUMMARY_PROMPT, &echo).is_err());
+    }
+
+    #[test]
+    fn test_refusal_is_rejected() {
+        let refusal = "Non posso riassumere questa descrizione.";
+        assert!(check_summary(SUMMARY_PROMPT, refusal).is_err());
+        assert!(check_summary(SUMMARY_PROMPT, "  ").is_err());
+    }
+
+    #[test]
+    fn test_clean_description() {
+        let description = "Una commedia brillante. Una commedia brillante.  Con Mario Rossi!\n\
+            Una commedia brillante.\n\
+            Acquista biglietti\n\
+            Questo sito utilizza cookie tecnici. Durata: 90 minuti";
+
+        assert_eq!(
+            clean_description(description),
+            "Una commedia brillante. Con Mario Rossi!\nUna commedia brillante.\nDurata: 90 minuti"
+        );
+    }
+
+    #[test]
+    fn test_short_summary_length() {
+        let service = InferenceService::new("", "", "model", Client::new())
+            .with_summary_length(SummaryLength::Short);
+        let length = service.summary_length;
+
+        let request = service.completion_request(length.prompt(), length.max_tokens());
+
+        assert!(request.messages[0].content.contains("non più di due frasi"));
+        assert!(request.max_tokens.unwrap() < SummaryLength::Medium.max_tokens());
+    }
+
+    #[test]
+    fn test_custom_temperature() {
+        let service = InferenceService::new("", "", "model", Client::new()).with_temperature(0.7);
+
+        let request = service.completion_request(DESCRIPTION, DEFAULT_MAX_TOKENS);
+
+        assert_eq!(request.temperature, 0.7);
+        let default = InferenceService::new("", "", "model", Client::new());
+        assert_eq!(
+            default
+                .completion_request(DESCRIPTION, DEFAULT_MAX_TOKENS)
+                .temperature,
+            DEFAULT_TEMPERATURE
+        );
+    }
+
+    /// Serves canned completions on a local port, counting the requests.
+    async fn serve_completions(requests: Arc<AtomicUsize>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                requests.fetch_add(1, Ordering::SeqCst);
+                let body = r#"{"choices":[{"message":{"content":"Un riassunto."}}]}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        return url;
+    }
+
+    #[tokio::test]
+    async fn test_slow_completion_times_out() {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf).await;
+            // Never answer within the timeout
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+        let service = InferenceService::new(&url, "", "model", Client::new())
+            .with_timeout(Duration::from_millis(200));
+
+        let err = service.infer(DESCRIPTION, 16).await.unwrap_err();
+
+        let err = err.downcast::<reqwest::Error>().unwrap();
+        assert!(err.is_timeout());
+    }
+
+    #[tokio::test]
+    async fn test_short_descriptions_skip_inference() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = serve_completions(requests.clone()).await;
+        let service =
+            InferenceService::new(&url, "", "model", Client::new()).with_summary_threshold(400);
+
+        let short = service.summarize_description(DESCRIPTION).await;
+        assert_eq!(short.as_deref(), Some(DESCRIPTION));
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+
+        let long = service.summarize_description(&DESCRIPTION.repeat(20)).await;
+        assert_eq!(long.as_deref(), Some("Un riassunto."));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_service_skips_requests() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let url = serve_completions(requests.clone()).await;
+        let service = InferenceService::new(&url, "", "model", Client::new());
+        service.disable();
+
+        assert_eq!(service.summarize_description(DESCRIPTION).await, None);
+        assert!(service.infer(DESCRIPTION, 16).await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+
+        assert!(!InferenceService::new("", "key", "model", Client::new()).is_enabled());
+    }
+
+    #[test]
+    fn test_verbatim_description_is_accepted() {
+        // The prompt asks to repeat short descriptions as they are
+        assert_eq!(
+            check_summary(SUMMARY_PROMPT, DESCRIPTION).unwrap(),
+            DESCRIPTION
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::{
+    events::Category,
+    inference::{INTRO_PROMPT, Summarizer},
+};
+
+/// Generates an introductory paragraph about what to do this week from the titles
+/// of the events.
+///
+/// Intros are cached in `cache_dir` by the hash of the event titles, so the same
+/// events always get the same intro without calling the summarizer again.
+pub async fn generate_intro(
+    summarizer: &impl Summarizer,
+    categories: &[Category],
+    cache_dir: &Path,
+) -> Result<String> {
+    let cache_path = cache_dir.join(format!("{:016x}.txt", titles_hash(categories)));
+    if let Ok(intro) = fs::read_to_string(&cache_path) {
+        info!("Loading intro from cache");
+        return Ok(intro);
+    }
+
+    info!("Generating intro...");
+    let intro = summarizer.summarize(&build_prompt(categories)).await?;
+
+    fs::create_dir_all(cache_dir)?;
+    fs::write(&cache_path, &intro)?;
+
+    return Ok(intro);
+}
+
+fn build_prompt(categories: &[Category]) -> String {
+    let mut prompt = format!("{INTRO_PROMPT}\n");
+    for category in categories {
+        for event in &category.events {
+            prompt.push_str(&format!("\n- {}: {}", category.name, event.title));
+        }
+    }
+    return prompt;
+}
+
+/// A 64-bit FNV-1a hash of the sorted event titles, each followed by a newline. It
+/// names cache files, so unlike the standard library hasher it must stay the same
+/// across Rust releases.
+fn titles_hash(categories: &[Category]) -> u64 {
+    let mut titles: Vec<&str> = categories
+        .iter()
+        .flat_map(|c| &c.events)
+        .map(|e| e.title.as_str())
+        .collect();
+    titles.sort();
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in titles.iter().flat_map(|t| t.bytes().chain([b'\n'])) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::HashSet};
+
+    use super::*;
+    use crate::events::Event;
+
+    /// Answers every prompt with a fixed intro, counting the calls.
+    struct MockSummarizer {
+        calls: Cell<usize>,
+    }
+
+    impl Summarizer for MockSummarizer {
+        async fn summarize(&self, prompt: &str) -> Result<String> {
+            self.calls.set(self.calls.get() + 1);
+            assert!(prompt.contains("- Teatri: Amleto"));
+            return Ok("Settimana di Amleto".to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_intro_is_generated_and_cached() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("scraper-caffe-intro-{}", std::process::id()));
+        let categories = vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![Event::new("Amleto", HashSet::new(), "Teatri")],
+        }];
+        let summarizer = MockSummarizer {
+            calls: Cell::new(0),
+        };
+
+        let first = generate_intro(&summarizer, &categories, &cache_dir)
+            .await
+            .unwrap();
+        let second = generate_intro(&summarizer, &categories, &cache_dir)
+            .await
+            .unwrap();
+        fs::remove_dir_all(&cache_dir).unwrap();
+
+        assert_eq!(first, "Settimana di Amleto");
+        assert_eq!(second, first);
+        assert_eq!(summarizer.calls.get(), 1);
+    }
+
+    #[test]
+    fn test_titles_hash_is_stable() {
+        let category = |titles: &[&str]| Category {
+            name: "Teatri".to_string(),
+            events: titles
+                .iter()
+                .map(|t| Event::new(t, HashSet::new(), "Teatri"))
+                .collect(),
+        };
+
+        let hash = titles_hash(&[category(&["Amleto", "Macbeth"])]);
+        assert_eq!(hash, 0xd56725cb5e472381);
+        assert_eq!(titles_hash(&[category(&["Macbeth", "Amleto"])]), hash);
+    }
+}
@@ -0,0 +1,72 @@
+pub mod archive;
+pub mod dates;
+pub mod events;
+pub mod filters;
+pub mod history;
+pub mod inference;
+pub mod intro;
+pub mod logging;
+pub mod profiles;
+pub mod profiling;
+pub mod rendering;
+mod utils;
+pub mod venues;
+
+use std::{env, time::Duration};
+
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use reqwest::Client;
+
+pub use crate::{
+    dates::{DateRange, DateSet},
+    events::{Category, Event, Location},
+    inference::InferenceService,
+    rendering::render_to_html,
+    venues::{CacheManager, ScrapeSettings, scrape_all},
+};
+
+lazy_static! {
+    /// Summarizes descriptions, configured through the `INFERENCE_*` environment variables.
+    pub static ref INFERENCE_SERVICE: InferenceService = InferenceService::new(
+        &env::var("INFERENCE_API_URL").unwrap_or_default(),
+        &env::var("INFERENCE_API_KEY").unwrap_or_default(),
+        &env::var("INFERENCE_MODEL").unwrap_or_default(),
+        Client::new()
+    )
+    .with_summary_length(
+        env::var("INFERENCE_SUMMARY_LENGTH")
+            .ok()
+            .and_then(|length| inference::SummaryLength::from_str(&length, true).ok())
+            .unwrap_or_default()
+    )
+    .with_summary_threshold(
+        env::var("INFERENCE_SUMMARY_THRESHOLD")
+            .ok()
+            .and_then(|threshold| threshold.parse().ok())
+            .unwrap_or_default()
+    )
+    .with_temperature(
+        env::var("INFERENCE_TEMPERATURE")
+            .ok()
+            .and_then(|temperature| temperature.parse().ok())
+            .unwrap_or(inference::DEFAULT_TEMPERATURE)
+    )
+    .with_max_tokens(
+        env::var("INFERENCE_MAX_TOKENS")
+            .ok()
+            .and_then(|tokens| tokens.parse().ok())
+            .unwrap_or(inference::DEFAULT_MAX_TOKENS)
+    )
+    .with_summary_max_tokens(
+        env::var("INFERENCE_SUMMARY_MAX_TOKENS")
+            .ok()
+            .and_then(|tokens| tokens.parse().ok())
+    )
+    .with_timeout(
+        env::var("INFERENCE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .map_or(inference::DEFAULT_TIMEOUT, Duration::from_secs)
+    );
+}
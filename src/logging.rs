@@ -0,0 +1,82 @@
+use std::{
+    env,
+    io::{IsTerminal, stderr},
+    path::Path,
+};
+
+use tracing_chrome::FlushGuard;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    prelude::*,
+};
+
+use crate::profiling;
+
+/// Environment variable overriding the verbosity, with the directives of
+/// [Targets], e.g. "scraper_caffe=trace,reqwest=debug".
+const LOG_ENV: &str = "RUST_LOG";
+
+/// Starts printing log events to stderr, where they interleave with the progress
+/// bars, and optionally recording spans to a Chrome trace file at `trace_path` (see
+/// [profiling]).
+///
+/// `verbosity` is how many times `-v` was given: our own info events are shown by
+/// default, debug events with one and trace events with two or more. Other crates
+/// only show warnings. [LOG_ENV] replaces these defaults if set.
+///
+/// The trace file is completed when the returned guard is dropped, so keep it alive
+/// until the end of the program.
+pub fn init(verbosity: u8, trace_path: Option<&Path>) -> Option<FlushGuard> {
+    let filter = env::var(LOG_ENV)
+        .ok()
+        .and_then(|directives| {
+            directives
+                .parse::<Targets>()
+                .inspect_err(|e| eprintln!("Ignoring invalid {LOG_ENV}: {e}"))
+                .ok()
+        })
+        .unwrap_or_else(|| default_filter(verbosity));
+    let (chrome, guard) = trace_path.map(profiling::chrome_layer).unzip();
+
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(stderr)
+        .with_ansi(stderr().is_terminal())
+        .with_target(false)
+        .without_time()
+        .with_filter(filter);
+    tracing_subscriber::registry().with(chrome).with(fmt).init();
+
+    return guard;
+}
+
+fn default_filter(verbosity: u8) -> Targets {
+    let level = match verbosity {
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    return Targets::new()
+        .with_target("scraper_caffe", level)
+        .with_default(LevelFilter::WARN);
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+
+    use super::*;
+
+    #[test]
+    fn test_verbosity_only_raises_our_level() {
+        let quiet = default_filter(0);
+        assert!(quiet.would_enable("scraper_caffe::venues", &Level::INFO));
+        assert!(!quiet.would_enable("scraper_caffe::venues", &Level::DEBUG));
+        assert!(!quiet.would_enable("reqwest", &Level::INFO));
+
+        let verbose = default_filter(1);
+        assert!(verbose.would_enable("scraper_caffe::venues", &Level::DEBUG));
+        assert!(!verbose.would_enable("scraper_caffe::venues", &Level::TRACE));
+        assert!(!verbose.would_enable("hyper", &Level::DEBUG));
+        assert!(default_filter(2).would_enable("scraper_caffe", &Level::TRACE));
+    }
+}
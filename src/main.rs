@@ -1,48 +1,70 @@
-mod dates;
-mod events;
-mod inference;
-mod rendering;
-mod utils;
-mod venues;
-
-use std::{collections::HashMap, env};
-
-use anyhow::Result;
-use chrono::Days;
-use clap::Parser;
-use lazy_static::lazy_static;
-use reqwest::{self, Client};
-
-use crate::{
+use std::{
+    collections::HashSet,
+    env,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow, bail};
+use chrono::{Days, NaiveDate};
+use clap::{Parser, Subcommand};
+use reqwest::Client;
+use tracing::{error, info};
+
+use scraper_caffe::{
+    INFERENCE_SERVICE,
+    archive::update_archive,
     dates::DateRange,
-    events::{Category, Event},
-    inference::InferenceService,
+    events::{Category, merge_across_categories},
+    filters::{TagFilter, UpcomingFilter, VenueFilter},
+    history::{HISTORY_FILE, RerunFilter, SeenHistory},
+    intro::generate_intro,
+    logging,
+    profiles::CityProfile,
+    rendering::{
+        self, DEFAULT_DISCLAIMER, OutputFormat,
+        category_order::CategoryOrder,
+        ics::Reminders,
+        output::{DEFAULT_OUTPUT_DIR, LineEnding, OutputOptions},
+    },
     venues::{
-        CATEGORY_BOOKSTORES, CATEGORY_MOVIES, CATEGORY_THEATRES, CacheManager, cinemas, custom,
-        libraries, theaters,
+        CACHE_ROOT, CATEGORY_MOVIES, CacheFormat, CacheManager, DEFAULT_FETCH_TIMEOUT,
+        DEFAULT_MAX_EVENTS_PER_VENUE, RequestDelays, ScrapeSettings, VENUES, aliases::TitleAliases,
+        blocklist::VenueBlocklist, build_client, check_listings, prune_cache, scrape_all,
+        set_request_delays, theaters::DescriptionMerge, validate_cache,
     },
 };
 
-lazy_static! {
-    static ref INFERENCE_SERVICE: InferenceService = InferenceService::new(
-        &env::var("INFERENCE_API_URL").unwrap_or_default(),
-        &env::var("INFERENCE_API_KEY").unwrap_or_default(),
-        &env::var("INFERENCE_MODEL").unwrap_or_default(),
-        Client::new()
-    );
-}
-
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(
         short,
         long,
         default_value_t = 7,
-        help = "The number of days to fetch events for, today included"
+        help = "The number of days to fetch events for, the first one included"
     )]
     days: u64,
 
+    #[arg(
+        long,
+        value_parser = parse_date_arg,
+        help = "The first day to fetch events for, as YYYY-MM-DD. Defaults to today"
+    )]
+    from: Option<NaiveDate>,
+
+    #[arg(
+        long,
+        value_parser = parse_date_arg,
+        conflicts_with = "days",
+        help = "The last day to fetch events for, as YYYY-MM-DD. Overrides --days"
+    )]
+    to: Option<NaiveDate>,
+
     #[arg(
         short,
         long,
@@ -70,6 +92,298 @@ struct Args {
         help = "Forcefully rebuild the entire cache. Does nothing without --cache"
     )]
     rebuild_cache: bool,
+
+    #[arg(
+        long,
+        help = "Add freshly fetched events to the existing caches instead of replacing them, keeping events venues no longer list. Does nothing without --cache"
+    )]
+    merge_cache: bool,
+
+    #[arg(
+        long,
+        help = "Print which venues would be fetched, loaded from cache or skipped, then exit without fetching anything"
+    )]
+    dry_run: bool,
+
+    #[arg(
+        long,
+        help = "Fetch venues again if their cache is older than this many hours. Does nothing without --cache"
+    )]
+    cache_max_age: Option<u64>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = CacheFormat::Json,
+        help = "The format cache files are stored in. Caches in a different format are ignored"
+    )]
+    cache_format: CacheFormat,
+
+    #[arg(
+        long,
+        help = "Cinemas whose subtitles tell apart different films, such as the films of a rassegna, as a space-separated list of snake_case names. None by default, so that films are grouped across cinemas without their subtitle"
+    )]
+    significant_subtitles: Option<String>,
+
+    #[arg(
+        long,
+        help = "Merge the same show playing at more than one theater into a single event"
+    )]
+    merge_theaters: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DescriptionMerge::First,
+        help = "How to merge the descriptions of a show playing at more than one theater. Does nothing without --merge-theaters"
+    )]
+    merge_descriptions: DescriptionMerge,
+
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_EVENTS_PER_VENUE,
+        help = "Maximum number of events a single venue may return before it's considered broken and skipped"
+    )]
+    max_events: usize,
+
+    #[arg(
+        long,
+        help = "Milliseconds to wait between the pages requested from a venue [default: 20]"
+    )]
+    delay_ms: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Seconds to wait for a venue's site to connect or send more of a page before giving up [default: 15]"
+    )]
+    timeout_secs: Option<u64>,
+
+    #[arg(
+        long,
+        help = "HTTP(S) proxy to request the venues' pages through [default: $HTTPS_PROXY]"
+    )]
+    proxy: Option<String>,
+
+    #[arg(
+        long,
+        value_parser = parse_venue_delay,
+        help = "Override --delay-ms for one venue, as venue=milliseconds (e.g., verdi=200). Can be repeated"
+    )]
+    venue_delay: Vec<(String, u64)>,
+
+    #[arg(
+        short,
+        long,
+        help = "The name of the output files, without extension. Defaults to the city and the dates of the time window"
+    )]
+    output: Option<String>,
+
+    #[arg(
+        long,
+        help = "Also write RSS feeds, one combined and one per category in the feeds directory"
+    )]
+    rss: bool,
+
+    #[arg(
+        long,
+        help = "Also write an Atom feed with the events of all categories"
+    )]
+    atom: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Html,
+        help = "The format of the main output file"
+    )]
+    format: OutputFormat,
+
+    #[arg(
+        long,
+        help = "Also write the events as JSON, which can be rendered again with render-from"
+    )]
+    json: bool,
+
+    #[arg(
+        long,
+        help = "Also write each event as its own JSON file in the events directory"
+    )]
+    json_per_event: bool,
+
+    #[arg(
+        long,
+        help = "Also write the events as an iCalendar file to import in a calendar app"
+    )]
+    ics: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LineEnding::Lf,
+        help = "The line endings of the written files"
+    )]
+    line_endings: LineEnding,
+
+    #[arg(
+        long,
+        default_value = DEFAULT_OUTPUT_DIR,
+        help = "The directory to write the output files to, created if missing"
+    )]
+    out_dir: PathBuf,
+
+    #[arg(long, help = "Start the written files with a UTF-8 byte order mark")]
+    bom: bool,
+
+    #[arg(
+        long,
+        help = "Add a reminder to calendar events with a showtime, as an iCalendar duration relative to the start (e.g., -PT1H)"
+    )]
+    reminder: Option<String>,
+
+    #[arg(
+        long,
+        help = "Add a reminder to all-day calendar events, as an iCalendar duration from midnight (e.g., PT9H)"
+    )]
+    all_day_reminder: Option<String>,
+
+    #[arg(
+        long,
+        help = "Group the theater shows that are part of a rassegna, such as a festival, under its name"
+    )]
+    group_rassegne: bool,
+
+    #[arg(
+        long,
+        help = "Only keep events happening during the first weekend (Friday to Sunday) of the time window"
+    )]
+    weekend: bool,
+
+    #[arg(
+        long,
+        default_value = "trieste",
+        help = "The city to scrape events for, which determines the venues, output title and cache directory"
+    )]
+    city: String,
+
+    #[arg(
+        long,
+        help = "Hide events that were first listed more than this many weeks ago, such as long-running films"
+    )]
+    hide_after_weeks: Option<u64>,
+
+    #[arg(
+        long,
+        default_value = CATEGORY_MOVIES,
+        help = "The categories --hide-after-weeks applies to, as a space-separated list"
+    )]
+    hide_categories: String,
+
+    #[arg(
+        long,
+        help = "Only keep events with this tag (e.g., 3D). Can be repeated to filter by multiple tags"
+    )]
+    tag: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Only keep events with every --tag, instead of any of them"
+    )]
+    all_tags: bool,
+
+    #[arg(
+        long,
+        help = "Hide the events that are already over, counting their showtimes when known"
+    )]
+    upcoming_only: bool,
+
+    #[arg(
+        long,
+        help = "Only show events at this venue. Can be repeated to keep multiple venues"
+    )]
+    only_venue: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Hide events at this venue (e.g., \"The Space\"). Venues are still scraped and cached. Can be repeated"
+    )]
+    exclude_venue: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Add an AI-generated introduction about the week's events to the top of the page"
+    )]
+    intro: bool,
+
+    #[arg(
+        long,
+        help = "Also add the listings of the week to this archive, with one JSON entry per line, to look back at past weeks"
+    )]
+    archive: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Don't summarize descriptions or generate the intro with a language model. Implied if INFERENCE_API_URL isn't set"
+    )]
+    no_inference: bool,
+
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        help = "Show more log messages: -v for debug and -vv for trace. RUST_LOG takes precedence"
+    )]
+    verbose: u8,
+
+    #[arg(
+        long,
+        help = "Record how long each fetch and inference call takes to trace.json in the output directory, in Chrome tracing format"
+    )]
+    profile: bool,
+
+    #[arg(
+        long,
+        default_value = DEFAULT_DISCLAIMER,
+        help = "The disclaimer shown at the top of the page"
+    )]
+    disclaimer: String,
+
+    #[arg(
+        long,
+        help = "A Handlebars template to render the page with, instead of the built-in one"
+    )]
+    template: Option<PathBuf>,
+
+    #[arg(long, help = "Don't show a disclaimer at the top of the page")]
+    no_disclaimer: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that every cache file still deserializes into the current event schema, without scraping
+    ValidateCache {
+        #[arg(long, default_value = CACHE_ROOT, help = "The cache directory to validate")]
+        dir: String,
+    },
+    /// Delete cache files older than a given age or belonging to venues that are no longer scraped
+    PruneCache {
+        #[arg(long, default_value = CACHE_ROOT, help = "The cache directory to prune")]
+        dir: String,
+        #[arg(
+            long,
+            default_value_t = 30,
+            help = "Delete cache files last written more than this many days ago"
+        )]
+        max_age_days: u64,
+        #[arg(long, help = "Only print the files that would be deleted")]
+        dry_run: bool,
+    },
+    /// Check that the main selector of each venue still matches its listing page, without scraping
+    Check,
+    /// Render the outputs from the events of a previous run written with --json, without scraping
+    RenderFrom {
+        #[arg(help = "The JSON file to render")]
+        file: PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -77,75 +391,391 @@ async fn main() -> Result<()> {
     let args = Args::parse();
     dotenv::dotenv().ok();
 
+    let trace_path = args.profile.then(|| args.out_dir.join("trace.json"));
+    if args.profile {
+        std::fs::create_dir_all(&args.out_dir).map_err(|e| {
+            anyhow!(
+                "Failed to create output directory {}: {e}",
+                args.out_dir.display()
+            )
+        })?;
+    }
+    let _trace_guard = logging::init(args.verbose, trace_path.as_deref());
+
+    if args.no_inference {
+        INFERENCE_SERVICE.disable();
+    } else if !INFERENCE_SERVICE.is_enabled() {
+        info!("INFERENCE_API_URL isn't set, descriptions won't be summarized");
+    }
+
+    if let Some(Command::ValidateCache { dir }) = &args.command {
+        let failures = validate_cache(Path::new(dir))?;
+        for (path, err) in &failures {
+            eprintln!("{}: {err}", path.display());
+        }
+        if !failures.is_empty() {
+            bail!("{} cache files failed validation", failures.len());
+        }
+        println!("All cache files are valid");
+        return Ok(());
+    }
+
+    if let Some(Command::PruneCache {
+        dir,
+        max_age_days,
+        dry_run,
+    }) = &args.command
+    {
+        let max_age = Duration::from_secs(max_age_days * 24 * 60 * 60);
+        let pruned = prune_cache(Path::new(dir), max_age, *dry_run)?;
+        for path in &pruned {
+            match dry_run {
+                true => println!("Would remove {}", path.display()),
+                false => println!("Removed {}", path.display()),
+            }
+        }
+        println!("{} stale cache files", pruned.len());
+        return Ok(());
+    }
+
+    if let Some(Command::Check) = &args.command {
+        let checks = check_listings(&http_client(&args)?).await;
+        let color = std::io::stdout().is_terminal();
+        let paint = |text: String, code: &str| match color {
+            true => format!("\x1b[{code}m{text}\x1b[0m"),
+            false => text,
+        };
+        let mut failed = 0;
+        for check in &checks {
+            let line = match &check.matches {
+                Ok(0) => paint(format!("0 matches ({})", check.url), "31"),
+                Ok(count) => paint(format!("{count} matches"), "32"),
+                Err(err) => paint(format!("failed: {err}"), "31"),
+            };
+            if !matches!(check.matches, Ok(count) if count > 0) {
+                failed += 1;
+            }
+            println!("{:<15} {line}", check.venue);
+        }
+        if failed > 0 {
+            bail!("{failed} venues have stale selectors or unreachable listings");
+        }
+        return Ok(());
+    }
+
+    let Some(profile) = CityProfile::by_name(&args.city) else {
+        bail!("Unknown city: {}", args.city);
+    };
+
+    if let Some(Command::RenderFrom { file }) = &args.command {
+        let mut document = rendering::json::read_json(&std::fs::read_to_string(file)?)?;
+        if let Some(filter) = tag_filter(&args) {
+            filter.apply(&mut document.categories);
+        }
+        if let Some(filter) = venue_filter(&args) {
+            filter.apply(&mut document.categories);
+        }
+        if let Some(filter) = upcoming_filter(&args) {
+            filter.apply(&mut document.categories);
+        }
+        let filename = match &args.output {
+            Some(name) => name.as_str(),
+            None => match file.file_stem().and_then(|s| s.to_str()) {
+                Some(stem) => stem,
+                None => bail!("Invalid file name: {}", file.display()),
+            },
+        };
+
+        write_outputs(
+            &args,
+            &profile,
+            &document.title,
+            document.categories,
+            &document.date_range,
+            filename,
+        )
+        .await?;
+
+        info!("Done!");
+        return Ok(());
+    }
+
     let today = chrono::Local::now().date_naive();
-    let in_a_week = today + Days::new(args.days - 1);
-    let current_week = DateRange::new(today, in_a_week);
+    let start = args.from.unwrap_or(today);
+    let end = args
+        .to
+        .unwrap_or_else(|| start + Days::new(args.days.saturating_sub(1)));
+    if end < start {
+        bail!("The time window ends before it starts");
+    }
+    let mut current_week = DateRange::new(start, end);
+    let weekend = match args.weekend {
+        true => Some(
+            current_week
+                .weekend()
+                .ok_or_else(|| anyhow!("The time window doesn't include a weekend"))?,
+        ),
+        false => None,
+    };
 
-    drop(std::fs::create_dir("qsat"));
-    let filename = format!(
-        "SettimanaTrieste_{}_{}",
-        today.format("%d-%m"),
-        in_a_week.format("%d-%m")
-    );
+    let rerun_filter = args.hide_after_weeks.map(|weeks| RerunFilter {
+        weeks,
+        categories: args
+            .hide_categories
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .collect(),
+    });
+    let mut categories = fetch_events(&current_week, &profile, &args).await?;
+    if args.dry_run {
+        return Ok(());
+    }
+    merge_across_categories(&mut categories);
 
-    let categories = fetch_events(&current_week, args).await;
-    let html = rendering::render_to_html(categories, &current_week)?;
-    std::fs::write(format!("qsat/{filename}.html"), &html)?;
+    if let Some(path) = &args.archive {
+        let added = update_archive(path, &categories, &current_week)?;
+        info!("Archived {added} new listings to {}", path.display());
+    }
+    if let Some(filter) = rerun_filter {
+        // Remember when each event was first listed, then hide the old ones
+        let history_path = Path::new(CACHE_ROOT)
+            .join(&profile.cache_subdir)
+            .join(HISTORY_FILE);
+        let mut history = SeenHistory::load(&history_path)?;
+        history.record(&categories, today);
+        history.save(&history_path)?;
+        filter.apply(&mut categories, &history, today);
+    }
+    if let Some(filter) = tag_filter(&args) {
+        filter.apply(&mut categories);
+    }
+    if let Some(filter) = venue_filter(&args) {
+        filter.apply(&mut categories);
+    }
+    if let Some(filter) = upcoming_filter(&args) {
+        filter.apply(&mut categories);
+    }
+
+    if let Some(range) = weekend {
+        for category in categories.iter_mut() {
+            let events = std::mem::take(&mut category.events);
+            category.events = events.into_iter().filter_map(|e| e.clamp(&range)).collect();
+        }
+        categories.retain(|c| !c.events.is_empty());
+        current_week = range;
+    }
+
+    let filename = args
+        .output
+        .clone()
+        .unwrap_or_else(|| format!("{}_{}", profile.name, current_week.filename()));
 
-    println!("Done!");
+    write_outputs(
+        &args,
+        &profile,
+        &profile.title,
+        categories,
+        &current_week,
+        &filename,
+    )
+    .await?;
+
+    info!("Done!");
     Ok(())
 }
 
-async fn fetch_events(date_range: &DateRange, args: Args) -> Vec<Category> {
-    println!("Fetching events...");
-    let client = Client::builder()
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64; rv:146.0) Gecko/20100101 Firefox/146.0")
-        .build()
-        .unwrap();
-
-    let mut cache_manager = CacheManager::new(
-        "",
-        args.cache,
-        args.rebuild_cache,
-        args.rebuild_venues.map_or_else(Vec::new, |list| {
+fn tag_filter(args: &Args) -> Option<TagFilter> {
+    return (!args.tag.is_empty()).then(|| TagFilter {
+        tags: args.tag.clone(),
+        match_all: args.all_tags,
+    });
+}
+
+fn venue_filter(args: &Args) -> Option<VenueFilter> {
+    return (!args.only_venue.is_empty() || !args.exclude_venue.is_empty()).then(|| VenueFilter {
+        only: args.only_venue.clone(),
+        exclude: args.exclude_venue.clone(),
+    });
+}
+
+fn upcoming_filter(args: &Args) -> Option<UpcomingFilter> {
+    return args.upcoming_only.then(|| UpcomingFilter {
+        now: chrono::Local::now().naive_local(),
+    });
+}
+
+/// Parses a per-venue delay such as "verdi=200".
+fn parse_venue_delay(arg: &str) -> Result<(String, u64)> {
+    let Some((venue, millis)) = arg.split_once('=') else {
+        bail!("Expected venue=milliseconds, found {arg}");
+    };
+    if !VENUES.contains(&venue) {
+        bail!("Unknown venue: {venue}");
+    }
+    return Ok((venue.to_string(), millis.parse()?));
+}
+
+fn parse_date_arg(arg: &str) -> Result<NaiveDate, chrono::ParseError> {
+    return NaiveDate::parse_from_str(arg, "%Y-%m-%d");
+}
+
+/// Writes the HTML page and any other output format requested in `args`, all named
+/// after `filename` and saved in the output directory.
+async fn write_outputs(
+    args: &Args,
+    profile: &CityProfile,
+    title: &str,
+    categories: Vec<Category>,
+    date_range: &DateRange,
+    filename: &str,
+) -> Result<()> {
+    let output = OutputOptions {
+        line_ending: args.line_endings,
+        bom: args.bom,
+    };
+    let out_dir = &args.out_dir;
+    let order = CategoryOrder::load("categories.toml")
+        .inspect_err(|e| error!("Failed to load the category order: {e}"))
+        .unwrap_or_default();
+
+    if args.json || args.format == OutputFormat::Json {
+        let json = rendering::json::write_json(&categories, date_range, title, &order)?;
+        output.write(out_dir.join(format!("{filename}.json")), &json)?;
+    }
+
+    if args.json_per_event {
+        for file in rendering::json::write_event_json(&categories, date_range, title, &order)? {
+            output.write(out_dir.join("events").join(&file.filename), &file.json)?;
+        }
+    }
+
+    if args.rss {
+        let rss = rendering::rss::write_rss(&categories, date_range, title, &order)?;
+        output.write(out_dir.join("feed.xml"), &rss)?;
+
+        for feed in rendering::rss::write_category_rss(&categories, date_range, title, &order)? {
+            output.write(out_dir.join("feeds").join(&feed.filename), &feed.xml)?;
+        }
+    }
+
+    if args.atom {
+        let atom = rendering::atom::write_atom(&categories, date_range, title, &order)?;
+        output.write(out_dir.join("feed.atom"), &atom)?;
+    }
+
+    if args.ics || args.format == OutputFormat::Ical {
+        let reminders = Reminders {
+            timed: args.reminder.clone(),
+            all_day: args.all_day_reminder.clone(),
+        };
+        let ics = rendering::ics::write_ics(&categories, date_range, &reminders);
+        output.write(out_dir.join(format!("{filename}.ics")), &ics)?;
+    }
+
+    if args.format != OutputFormat::Html {
+        return Ok(());
+    }
+
+    let intro = match args.intro {
+        true => {
+            let cache_dir = Path::new(CACHE_ROOT)
+                .join(&profile.cache_subdir)
+                .join("intro");
+            generate_intro(&*INFERENCE_SERVICE, &categories, &cache_dir)
+                .await
+                .inspect_err(|e| error!("Failed to generate intro: {e}"))
+                .ok()
+        }
+        false => None,
+    };
+
+    let disclaimer = (!args.no_disclaimer).then(|| args.disclaimer.clone());
+    let today = chrono::Local::now().date_naive();
+    let html = rendering::render_to_html(
+        categories,
+        date_range,
+        title,
+        Some(profile.name.clone()),
+        intro,
+        disclaimer,
+        args.group_rassegne,
+        today,
+        args.template.as_deref(),
+        &order,
+    )?;
+    output.write(out_dir.join(format!("{filename}.html")), &html)?;
+
+    Ok(())
+}
+
+/// The client for venue requests, with the timeout and proxy of the arguments.
+fn http_client(args: &Args) -> Result<Client> {
+    let timeout = args
+        .timeout_secs
+        .map_or(DEFAULT_FETCH_TIMEOUT, Duration::from_secs);
+    let proxy = args.proxy.clone().or_else(|| env::var("HTTPS_PROXY").ok());
+    return build_client(timeout, proxy.as_deref());
+}
+
+async fn fetch_events(
+    date_range: &DateRange,
+    profile: &CityProfile,
+    args: &Args,
+) -> Result<Vec<Category>> {
+    let client = http_client(args)?;
+    set_request_delays(RequestDelays {
+        global: args.delay_ms.map(Duration::from_millis),
+        venues: args
+            .venue_delay
+            .iter()
+            .map(|(venue, millis)| (venue.clone(), Duration::from_millis(*millis)))
+            .collect(),
+    });
+
+    let mut cache_manager = CacheManager::default()
+        .with_cache(args.cache)
+        .with_rebuild(args.rebuild_cache)
+        .with_max_age(
+            args.cache_max_age
+                .map(|hours| Duration::from_secs(hours * 60 * 60)),
+        )
+        .with_venues_to_rebuild(args.rebuild_venues.as_ref().map_or_else(Vec::new, |list| {
             list.split_whitespace().map(|s| s.to_string()).collect()
-        }),
-        args.skip_venues.map_or_else(Vec::new, |list| {
+        }))
+        .with_venues_to_skip(args.skip_venues.as_ref().map_or_else(Vec::new, |list| {
             list.split_whitespace().map(|s| s.to_string()).collect()
-        }),
-    );
-
-    let mut events_by_category: HashMap<String, Vec<Event>> = HashMap::new();
-
-    let movies = cinemas::fetch(&client, &date_range, &mut cache_manager)
-        .await
-        .unwrap();
-    events_by_category.insert(CATEGORY_MOVIES.to_string(), movies);
-
-    let shows = theaters::fetch(&client, &date_range, &mut cache_manager)
-        .await
-        .unwrap();
-    events_by_category.insert(CATEGORY_THEATRES.to_string(), shows);
-
-    let libraries = libraries::fetch(&client, date_range, &mut cache_manager)
-        .await
-        .unwrap();
-    events_by_category.insert(CATEGORY_BOOKSTORES.to_string(), libraries);
-
-    // Merge custom events with existing categories
-    let custom = custom::fetch("custom_events.toml", &date_range).unwrap();
-    for event in custom {
-        events_by_category
-            .entry(event.category.clone())
-            .or_insert_with(Vec::new)
-            .push(event);
-    }
-
-    let mut categories: Vec<Category> = events_by_category
-        .into_iter()
-        .map(|(name, events)| Category { name, events })
-        .collect();
-    categories.sort_by(|a, b| a.name.cmp(&b.name));
-
-    return categories;
+        }))
+        .with_format(args.cache_format)
+        .with_blocklist(
+            VenueBlocklist::load("blocklist.toml")
+                .inspect_err(|e| error!("Failed to load the venue blocklist: {e}"))
+                .unwrap_or_default(),
+        )
+        .with_profile(profile)
+        .with_max_events(args.max_events)
+        .with_merge(args.merge_cache)
+        .with_dry_run(args.dry_run);
+
+    let settings = ScrapeSettings {
+        merge_theaters: args.merge_theaters,
+        description_merge: args.merge_descriptions,
+        aliases: TitleAliases::load("aliases.toml")
+            .inspect_err(|e| error!("Failed to load the title aliases: {e}"))
+            .unwrap_or_default(),
+        significant_subtitles: args
+            .significant_subtitles
+            .as_ref()
+            .map_or_else(HashSet::new, |list| {
+                list.split_whitespace().map(|s| s.to_string()).collect()
+            }),
+    };
+
+    return Ok(scrape_all(
+        &client,
+        date_range,
+        &mut cache_manager,
+        &settings,
+        |_, _| {},
+    )
+    .await);
 }
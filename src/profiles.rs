@@ -0,0 +1,35 @@
+use crate::venues::VENUES;
+
+/// A city to scrape events for. Each city has its own set of venues, output
+/// title and cache directory, so that the same tool can be run for different
+/// places by swapping profile.
+#[derive(Debug, Clone)]
+pub struct CityProfile {
+    pub name: String,
+    pub title: String,
+    /// The snake_case names of the venues to scrape, as registered in [VENUES].
+    pub venues: Vec<String>,
+    /// Subdirectory of the cache root where this city's venues are cached.
+    pub cache_subdir: String,
+}
+
+impl CityProfile {
+    /// The default profile, scraping every venue in Trieste.
+    pub fn trieste() -> Self {
+        Self {
+            name: "Trieste".to_string(),
+            title: "Questa Settimana a Trieste".to_string(),
+            venues: VENUES.iter().map(|v| v.to_string()).collect(),
+            // Trieste caches at the root for compatibility with existing caches
+            cache_subdir: String::new(),
+        }
+    }
+
+    /// Finds a known profile by case-insensitive city name.
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "trieste" => Some(Self::trieste()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use tracing_chrome::{ChromeLayer, ChromeLayerBuilder, FlushGuard, TraceStyle};
+use tracing_subscriber::Registry;
+
+/// Builds the layer recording spans to a Chrome trace file at `path`, which can be
+/// loaded in about:tracing or any flamegraph viewer supporting the format. Installed
+/// by [crate::logging::init].
+///
+/// The file is completed when the returned guard is dropped.
+pub(crate) fn chrome_layer(path: &Path) -> (ChromeLayer<Registry>, FlushGuard) {
+    return ChromeLayerBuilder::new()
+        .file(path)
+        .include_args(true)
+        // Spans are entered and exited across awaits, possibly on different threads
+        .trace_style(TraceStyle::Async)
+        .build();
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_trace_file_is_written() {
+        let path =
+            std::env::temp_dir().join(format!("scraper-caffe-trace-{}.json", std::process::id()));
+
+        let (layer, guard) = chrome_layer(&path);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("fetch_venue", venue = "verdi").in_scope(|| {});
+        });
+        drop(guard);
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(trace.contains("fetch_venue"));
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashSet;
+
+use crate::utils::slugify;
+
+/// Hands out anchors for events that are unique within a rendered page, so that
+/// each event has a stable permalink (e.g., `#amleto`). Repeated titles are
+/// disambiguated with a numeric suffix (e.g., `#amleto-2`).
+#[derive(Default)]
+pub(super) struct Anchors {
+    used: HashSet<String>,
+}
+
+impl Anchors {
+    /// Returns a new anchor for the title that has not been handed out before.
+    pub(super) fn next(&mut self, title: &str) -> String {
+        let mut slug = slugify(title);
+        if slug.is_empty() {
+            slug = "evento".to_string();
+        }
+
+        let mut anchor = slug.clone();
+        let mut suffix = 1;
+        while self.used.contains(&anchor) {
+            suffix += 1;
+            anchor = format!("{slug}-{suffix}");
+        }
+
+        self.used.insert(anchor.clone());
+        anchor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collisions_get_numeric_suffix() {
+        let mut anchors = Anchors::default();
+
+        assert_eq!(anchors.next("Amleto"), "amleto");
+        assert_eq!(anchors.next("Amleto"), "amleto-2");
+        assert_eq!(anchors.next("AMLETO!"), "amleto-3");
+        assert_eq!(anchors.next("Otello"), "otello");
+    }
+
+    #[test]
+    fn test_suffix_skips_existing_slugs() {
+        let mut anchors = Anchors::default();
+
+        assert_eq!(anchors.next("Amleto 2"), "amleto-2");
+        assert_eq!(anchors.next("Amleto"), "amleto");
+        assert_eq!(anchors.next("Amleto"), "amleto-3");
+    }
+
+    #[test]
+    fn test_empty_slug_falls_back() {
+        let mut anchors = Anchors::default();
+
+        assert_eq!(anchors.next("???"), "evento");
+        assert_eq!(anchors.next(""), "evento-2");
+    }
+}
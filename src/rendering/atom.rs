@@ -0,0 +1,135 @@
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+
+use crate::{
+    dates::DateRange,
+    events::Category,
+    rendering::{
+        TemplateEvent, build_template_data, category_order::CategoryOrder, rss::escape_xml,
+    },
+};
+
+/// Renders a single Atom 1.0 feed containing the events of all categories, as an
+/// alternative to [super::rss::write_rss] for readers that prefer Atom.
+pub fn write_atom(
+    categories: &[Category],
+    date_range: &DateRange,
+    title: &str,
+    order: &CategoryOrder,
+) -> Result<String> {
+    let today = chrono::Local::now().date_naive();
+    let data = build_template_data(categories.to_vec(), date_range, title, today, order);
+    let updated = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let entries: String = data
+        .categories
+        .iter()
+        .flat_map(|c| &c.events)
+        .map(|e| render_entry(e, &updated))
+        .collect();
+
+    let subtitle = format!(
+        "Eventi dal {} al {}",
+        date_range.start.format("%d/%m/%Y"),
+        date_range.end.format("%d/%m/%Y")
+    );
+
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"it\">\n\
+        <id>urn:scraper-caffe:{}</id>\n\
+        <title>{}</title>\n\
+        <subtitle>{}</subtitle>\n\
+        <updated>{updated}</updated>\n\
+        <author><name>Caffè degli Incroci</name></author>\n\
+        {entries}\
+        </feed>\n",
+        date_range.filename(),
+        escape_xml(title),
+        escape_xml(&subtitle),
+    ))
+}
+
+fn render_entry(event: &TemplateEvent, updated: &str) -> String {
+    // The anchor of the event in the HTML page is unique within a run, as in RSS
+    let mut entry = format!(
+        "<entry>\n<id>urn:scraper-caffe:{}</id>\n<title>{}</title>\n<updated>{updated}</updated>\n",
+        escape_xml(&event.anchor),
+        escape_xml(&event.title)
+    );
+
+    if let Some(date) = event.first_date {
+        let date = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+        entry.push_str(&format!(
+            "<published>{}</published>\n",
+            date.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ));
+    }
+
+    let link = event.locations.iter().find_map(|l| l.url.as_ref());
+    if let Some(link) = link {
+        entry.push_str(&format!("<link href=\"{}\"/>\n", escape_xml(link)));
+    }
+
+    if let Some(text) = event.summary.as_ref().or(event.description.as_ref()) {
+        entry.push_str(&format!(
+            "<content type=\"text\">{}</content>\n",
+            escape_xml(text)
+        ));
+    }
+
+    entry.push_str("</entry>\n");
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::{
+        dates::{DateSet, TimeFrame},
+        events::{Event, Location},
+    };
+
+    #[test]
+    fn test_atom_feed_has_required_elements() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let range = DateRange::new(date, date);
+        let location = Location::new("Miela", Some("https://miela.it/?a=1&b=2".to_string()));
+        let categories = vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![
+                Event::new("Amleto & Ofelia", HashSet::from([location]), "Teatri")
+                    .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date]).unwrap())))
+                    .with_summary(Some("Una tragedia.".to_string())),
+                Event::new("Otello", HashSet::new(), "Teatri"),
+            ],
+        }];
+
+        let atom = write_atom(
+            &categories,
+            &range,
+            "Questa Settimana a Trieste",
+            &CategoryOrder::default(),
+        )
+        .unwrap();
+
+        assert!(atom.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\""));
+        assert!(atom.contains("<id>urn:scraper-caffe:2026-01-12_2026-01-12</id>"));
+        assert!(atom.contains("<title>Questa Settimana a Trieste</title>"));
+        assert!(atom.contains("<author><name>"));
+        assert_eq!(atom.matches("<entry>").count(), 2);
+        assert_eq!(atom.matches("</entry>").count(), 2);
+        // Every entry needs an id, a title and an updated date
+        assert_eq!(atom.matches("<id>").count(), 3);
+        assert_eq!(atom.matches("<updated>").count(), 3);
+        assert!(atom.contains("<id>urn:scraper-caffe:amleto-ofelia</id>"));
+        assert!(atom.contains("<title>Amleto &amp; Ofelia</title>"));
+        assert!(atom.contains("<published>2026-01-12T00:00:00Z</published>"));
+        assert!(atom.contains("<link href=\"https://miela.it/?a=1&amp;b=2\"/>"));
+        assert!(atom.contains("<content type=\"text\">Una tragedia.</content>"));
+    }
+}
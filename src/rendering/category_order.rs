@@ -0,0 +1,82 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+/// The order in which the categories are shown on the page and the names they're
+/// shown with, which can differ from the names they have internally.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct CategoryOrder {
+    #[serde(default)]
+    categories: Vec<CategoryName>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CategoryName {
+    /// The internal name of the category, such as "Teatri".
+    key: String,
+    /// The name shown on the page, if different from the internal one.
+    name: Option<String>,
+}
+
+impl CategoryOrder {
+    /// Loads the order from a TOML file listing the categories in the order they
+    /// should be shown. Returns an empty order if the file doesn't exist.
+    pub fn load(filename: &str) -> Result<Self> {
+        if !Path::new(filename).exists() {
+            return Ok(Self::default());
+        }
+        return Self::parse(&fs::read_to_string(filename)?);
+    }
+
+    pub(super) fn parse(content: &str) -> Result<Self> {
+        return Ok(toml::from_str(content)?);
+    }
+
+    /// The position of a category on the page. Categories that aren't listed come
+    /// after all the listed ones.
+    pub fn rank(&self, key: &str) -> usize {
+        return self
+            .categories
+            .iter()
+            .position(|c| c.key == key)
+            .unwrap_or(self.categories.len());
+    }
+
+    /// The name a category is shown with, which is its internal name unless renamed.
+    pub fn display_name<'a>(&'a self, key: &'a str) -> &'a str {
+        return self
+            .categories
+            .iter()
+            .find(|c| c.key == key)
+            .and_then(|c| c.name.as_deref())
+            .unwrap_or(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlisted_categories_come_last() {
+        let order = CategoryOrder::parse(
+            r#"
+            [[categories]]
+            key = "Film"
+            name = "Cinema"
+
+            [[categories]]
+            key = "Teatri"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(order.rank("Film"), 0);
+        assert_eq!(order.rank("Teatri"), 1);
+        assert_eq!(order.rank("Mostre"), 2);
+        assert_eq!(order.display_name("Film"), "Cinema");
+        assert_eq!(order.display_name("Teatri"), "Teatri");
+        assert_eq!(order.display_name("Mostre"), "Mostre");
+    }
+}
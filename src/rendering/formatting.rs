@@ -1,13 +1,17 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use chrono::NaiveDate;
 
 use crate::{
-    dates::TimeFrame,
+    dates::{DateRange, TimeFrame},
     events::{Event, Location},
-    rendering::TemplateEvent,
+    rendering::{TemplateEvent, TemplateRun},
 };
 
+/// Tag shown for the base variant of a film on dates when a 3D variant is also
+/// available, to tell the two apart.
+const BASE_3D_TAG: &str = "2D";
+
 /// Films have multiple variants that are saved as different [Event]s, but should visually
 /// be displayed as the same event. For instance, showings of a movie in 2D, in 3D and
 /// in original language. This function combines similar movie showings into one
@@ -36,25 +40,40 @@ pub(super) fn preprocess_films(events: Vec<Event>) -> Vec<TemplateEvent> {
             .collect();
         all_tags.sort();
 
-        // Aggregate dates
+        // Aggregate dates from all variants, whatever their id
         // Map: Date -> Set of tags available on that date
-        let mut date_map: HashMap<NaiveDate, HashSet<String>> = HashMap::new();
+        let has_3d = all_tags.iter().any(|t| t == "3D");
+        let mut date_map: HashMap<NaiveDate, BTreeSet<String>> = HashMap::new();
         for e in &events {
             if let Some(TimeFrame::Dates(dates)) = &e.time_frame {
                 for d in dates.dates() {
-                    date_map
-                        .entry(*d)
-                        .or_default()
-                        .extend(e.tags.iter().cloned());
+                    let tags = date_map.entry(*d).or_default();
+                    tags.extend(e.tags.iter().cloned());
+                    if e.tags.is_empty() && has_3d {
+                        tags.insert(BASE_3D_TAG.to_string());
+                    }
                 }
             }
         }
 
+        // Drop the base tag on dates when the film is only shown in 2D
+        for tags in date_map.values_mut() {
+            if !tags.contains("3D") {
+                tags.remove(BASE_3D_TAG);
+            }
+        }
+
         // Aggregate locations
         // Map: Location -> Set of tags available at that location
         let mut loc_map: HashMap<Location, HashSet<String>> = HashMap::new();
         for e in &events {
             for loc in &e.locations {
+                // Keys aren't replaced on insertion, so swap in the one with a URL
+                if loc.url.is_some() {
+                    if let Some(tags) = loc_map.remove(loc) {
+                        loc_map.insert(loc.clone(), tags);
+                    }
+                }
                 loc_map
                     .entry(loc.clone())
                     .or_default()
@@ -82,6 +101,7 @@ pub(super) fn preprocess_films(events: Vec<Event>) -> Vec<TemplateEvent> {
         // Result: "il 14/02, 15/02 (anche 3D), 16/02 (anche Originale)"
         let mut sorted_dates: Vec<NaiveDate> = date_map.keys().cloned().collect();
         sorted_dates.sort();
+        let first_date = sorted_dates.first().cloned();
 
         let formatted_time_frame = if sorted_dates.is_empty() {
             None
@@ -117,13 +137,22 @@ pub(super) fn preprocess_films(events: Vec<Event>) -> Vec<TemplateEvent> {
             .find(|e| e.summary.is_some())
             .and_then(|e| e.summary.clone());
 
+        // Metadata is per movie, so variants can share it
+        let metadata = events.iter().flat_map(|e| e.metadata.clone()).collect();
+
         results.push(TemplateEvent {
+            anchor: String::new(),
             title,
             tags: all_tags,
             locations: sorted_locs,
             time_frame: formatted_time_frame,
             description,
             summary,
+            metadata,
+            first_date,
+            is_today: false,
+            related: Vec::new(),
+            runs: Vec::new(),
         });
     }
 
@@ -131,3 +160,169 @@ pub(super) fn preprocess_films(events: Vec<Event>) -> Vec<TemplateEvent> {
     results.sort_by(|a, b| a.title.cmp(&b.title));
     results
 }
+
+/// Shows with the same title that the scrapers kept apart, such as two runs of a show
+/// at the same theater, are displayed as a single event listing each run. The group
+/// takes the description of the earliest run that has one, along with that run's
+/// summary, so that the two always match.
+pub(super) fn preprocess_shows(events: Vec<Event>, window: &DateRange) -> Vec<TemplateEvent> {
+    let mut groups: Vec<Vec<Event>> = Vec::new();
+    let mut by_key: HashMap<String, usize> = HashMap::new();
+    for event in events {
+        let key = event.dedup_key();
+        match by_key.get(&key) {
+            Some(&index) => groups[index].push(event),
+            None => {
+                by_key.insert(key, groups.len());
+                groups.push(vec![event]);
+            }
+        }
+    }
+
+    return groups
+        .into_iter()
+        .map(|mut runs| match runs.len() {
+            1 => TemplateEvent::new(runs.remove(0), window),
+            _ => group_runs(runs, window),
+        })
+        .collect();
+}
+
+fn group_runs(mut runs: Vec<Event>, window: &DateRange) -> TemplateEvent {
+    runs.sort_by_key(|e| e.time_frame.as_ref().map(|tf| tf.as_range().start));
+    let runs: Vec<TemplateEvent> = runs
+        .into_iter()
+        .map(|run| TemplateEvent::new(run, window))
+        .collect();
+
+    let (description, summary) = runs
+        .iter()
+        .find(|run| run.description.is_some())
+        .map(|run| (run.description.clone(), run.summary.clone()))
+        .unwrap_or((None, None));
+
+    let mut tags: Vec<String> = runs.iter().flat_map(|run| run.tags.clone()).collect();
+    tags.sort();
+    tags.dedup();
+
+    let mut locations: Vec<Location> = Vec::new();
+    for location in runs.iter().flat_map(|run| &run.locations) {
+        match locations.iter_mut().find(|l| l.name == location.name) {
+            Some(existing) if existing.url.is_none() => existing.url = location.url.clone(),
+            Some(_) => {}
+            None => locations.push(location.clone()),
+        }
+    }
+    locations.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Earlier runs win on conflicting metadata
+    let metadata = runs
+        .iter()
+        .rev()
+        .flat_map(|run| run.metadata.clone())
+        .collect();
+
+    let first = &runs[0];
+    return TemplateEvent {
+        anchor: String::new(),
+        title: first.title.clone(),
+        tags,
+        locations,
+        time_frame: None,
+        description,
+        summary,
+        metadata,
+        first_date: runs.iter().filter_map(|run| run.first_date).min(),
+        is_today: false,
+        related: Vec::new(),
+        runs: runs
+            .iter()
+            .map(|run| TemplateRun {
+                locations: run
+                    .locations
+                    .iter()
+                    .map(|l| l.name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+                time_frame: run.time_frame.clone(),
+            })
+            .collect(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::DateSet;
+
+    fn showings(tags: &[&str], days: &[u32]) -> Event {
+        let dates = days
+            .iter()
+            .map(|d| NaiveDate::from_ymd_opt(2026, 1, *d).unwrap())
+            .collect();
+        let tags: HashSet<String> = tags.iter().map(|t| t.to_string()).collect();
+        let id = format!("dune {}", tags.iter().cloned().collect::<String>());
+        Event::new("dune", HashSet::new(), "Film")
+            .with_id(id)
+            .with_tags(tags)
+            .with_time_frame(Some(TimeFrame::Dates(DateSet::new(dates).unwrap())))
+    }
+
+    #[test]
+    fn test_variant_dates_are_merged() {
+        // 2D from Monday to Wednesday, 3D from Tuesday to Thursday
+        let events = vec![
+            showings(&[], &[12, 13, 14]),
+            showings(&["3D"], &[13, 14, 15]),
+        ];
+
+        let films = preprocess_films(events);
+
+        assert_eq!(films.len(), 1);
+        let time_frame = films[0].time_frame.as_ref().unwrap();
+        assert!(time_frame.contains("12/01,"));
+        assert!(time_frame.contains("13/01 (2D, 3D)"));
+        assert!(time_frame.contains("14/01 (2D, 3D)"));
+        assert!(time_frame.contains("15/01 (3D)"));
+    }
+
+    #[test]
+    fn test_runs_of_a_show_are_grouped() {
+        let run = |day: u32, description: Option<&str>| {
+            let date = NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+            Event::new(
+                "Amleto",
+                HashSet::from([Location::new("Verdi", None)]),
+                "Teatri",
+            )
+            .with_id(format!("amleto {day}"))
+            .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date]).unwrap())))
+            .with_description(description.map(|d| d.to_string()))
+        };
+        let events = vec![
+            run(20, Some("La replica.")),
+            run(12, None),
+            run(14, Some("La prima.")),
+            Event::new("Otello", HashSet::new(), "Teatri"),
+        ];
+
+        let window = DateRange::new(
+            NaiveDate::from_ymd_opt(2026, 1, 12).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 1, 25).unwrap(),
+        );
+        let shows = preprocess_shows(events, &window);
+
+        assert_eq!(shows.len(), 2);
+        let amleto = &shows[0];
+        let runs: Vec<&str> = amleto
+            .runs
+            .iter()
+            .map(|r| r.time_frame.as_deref().unwrap())
+            .collect();
+        assert_eq!(runs, vec!["il 12/01", "il 14/01", "il 20/01"]);
+        // The earliest run with a description wins
+        assert_eq!(amleto.description.as_deref(), Some("La prima."));
+        assert_eq!(amleto.locations.len(), 1);
+        assert!(shows[1].runs.is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+use chrono::{Days, NaiveDate, Utc};
+
+use crate::{
+    dates::{DateRange, Showtime, TimeFrame},
+    events::{Category, Event},
+    utils::slugify,
+};
+
+/// Reminders to attach to the calendar events, as iCalendar `TRIGGER` durations
+/// relative to the start of each event (e.g., `-PT1H` for an hour before).
+#[derive(Debug, Clone, Default)]
+pub struct Reminders {
+    /// Trigger for events with a known showtime.
+    pub timed: Option<String>,
+    /// Trigger for all-day events. Since these start at midnight, a positive
+    /// duration gives a reminder on the morning of the day (e.g., `PT9H`).
+    pub all_day: Option<String>,
+}
+
+/// Renders the events as an iCalendar file, with one entry per date of each event
+/// within the time window, or a single entry spanning the whole period for events
+/// that run continuously.
+pub fn write_ics(categories: &[Category], date_range: &DateRange, reminders: &Reminders) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Caffe degli Incroci//Questa Settimana//IT".to_string(),
+    ];
+    for event in categories.iter().flat_map(|c| &c.events) {
+        match &event.time_frame {
+            Some(TimeFrame::Dates(set)) => {
+                let Some(set) = set.filter_window(date_range) else {
+                    continue;
+                };
+                for date in set.dates() {
+                    let showtime = event.showtimes.get(date);
+                    lines.extend(render_event(
+                        event, *date, None, showtime, &stamp, reminders,
+                    ));
+                }
+            }
+            Some(TimeFrame::Period(range)) if range.overlaps(date_range) => {
+                lines.extend(render_event(
+                    event,
+                    range.start,
+                    Some(range.end),
+                    None,
+                    &stamp,
+                    reminders,
+                ));
+            }
+            _ => {}
+        }
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    return lines
+        .iter()
+        .map(|line| fold_line(line))
+        .collect::<Vec<String>>()
+        .join("\r\n")
+        + "\r\n";
+}
+
+fn render_event(
+    event: &Event,
+    date: NaiveDate,
+    end_date: Option<NaiveDate>,
+    showtime: Option<&Showtime>,
+    stamp: &str,
+    reminders: &Reminders,
+) -> Vec<String> {
+    let mut lines = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@scraper-caffe", uid(event, date)),
+        format!("DTSTAMP:{stamp}"),
+        format!("SUMMARY:{}", escape_text(&event.title)),
+    ];
+
+    let trigger = match showtime {
+        Some(showtime) => {
+            let start = date.and_time(showtime.start);
+            lines.push(format!("DTSTART:{}", start.format("%Y%m%dT%H%M%S")));
+            if let Some(end) = showtime.end {
+                let end = date.and_time(end);
+                lines.push(format!("DTEND:{}", end.format("%Y%m%dT%H%M%S")));
+            }
+            &reminders.timed
+        }
+        None => {
+            // All-day events end exclusively on the day after the last one
+            let end = end_date.unwrap_or(date) + Days::new(1);
+            lines.push(format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")));
+            lines.push(format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")));
+            &reminders.all_day
+        }
+    };
+
+    let mut locations: Vec<&str> = event.locations.iter().map(|l| l.name.as_str()).collect();
+    locations.sort();
+    if !locations.is_empty() {
+        lines.push(format!("LOCATION:{}", escape_text(&locations.join(", "))));
+    }
+    if let Some(text) = event.summary.as_ref().or(event.description.as_ref()) {
+        lines.push(format!("DESCRIPTION:{}", escape_text(text)));
+    }
+
+    if let Some(trigger) = trigger {
+        lines.extend([
+            "BEGIN:VALARM".to_string(),
+            "ACTION:DISPLAY".to_string(),
+            format!("DESCRIPTION:{}", escape_text(&event.title)),
+            format!("TRIGGER:{trigger}"),
+            "END:VALARM".to_string(),
+        ]);
+    }
+
+    lines.push("END:VEVENT".to_string());
+    return lines;
+}
+
+/// Identifies an entry by the title and date of the event, so that calendars update
+/// it rather than adding a copy when the file is imported again. Variants of a film
+/// share the title, so their tags are part of it too.
+fn uid(event: &Event, date: NaiveDate) -> String {
+    let mut tags: Vec<&str> = event.tags.iter().map(|t| t.as_str()).collect();
+    tags.sort();
+    let mut parts = vec![event.title.as_str()];
+    parts.extend(tags);
+    return format!("{}-{}", slugify(&parts.join(" ")), date.format("%Y%m%d"));
+}
+
+/// Escapes the characters that have a meaning in iCalendar text values.
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Splits lines longer than 75 bytes into continuation lines, as the format requires.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    return folded;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+
+    use chrono::NaiveTime;
+
+    use super::*;
+    use crate::dates::DateSet;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 12, 23).unwrap()
+    }
+
+    fn week() -> DateRange {
+        DateRange::new(date(), date() + Days::new(6))
+    }
+
+    fn category(event: Event) -> Vec<Category> {
+        vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![event],
+        }]
+    }
+
+    #[test]
+    fn test_alarm_on_timed_event() {
+        let showtime = Showtime {
+            start: NaiveTime::from_hms_opt(19, 30, 0).unwrap(),
+            end: None,
+        };
+        let event = Event::new("Amleto", HashSet::new(), "Teatri")
+            .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date()]).unwrap())))
+            .with_showtimes(BTreeMap::from([(date(), showtime)]));
+        let reminders = Reminders {
+            timed: Some("-PT1H".to_string()),
+            all_day: None,
+        };
+
+        let ics = write_ics(&category(event), &week(), &reminders);
+
+        assert!(ics.contains("DTSTART:20251223T193000\r\n"));
+        assert!(ics.contains("BEGIN:VALARM\r\nACTION:DISPLAY\r\nDESCRIPTION:Amleto\r\nTRIGGER:-PT1H\r\nEND:VALARM\r\n"));
+    }
+
+    #[test]
+    fn test_no_alarm_on_all_day_event_by_default() {
+        let event = Event::new("Amleto", HashSet::new(), "Teatri")
+            .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date()]).unwrap())));
+        let reminders = Reminders {
+            timed: Some("-PT1H".to_string()),
+            all_day: None,
+        };
+
+        let ics = write_ics(&category(event), &week(), &reminders);
+
+        assert!(ics.contains("DTSTART;VALUE=DATE:20251223\r\n"));
+        assert!(ics.contains("DTEND;VALUE=DATE:20251224\r\n"));
+        assert!(!ics.contains("VALARM"));
+    }
+
+    #[test]
+    fn test_long_lines_are_folded() {
+        let folded = fold_line(&"a".repeat(100));
+        let lines: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(lines[0].len(), 75);
+        assert_eq!(lines[1], format!(" {}", "a".repeat(25)));
+    }
+
+    #[test]
+    fn test_one_entry_per_scattered_date() {
+        let dates = [date(), date() + Days::new(2), date() + Days::new(30)];
+        let event = Event::new("Amleto", HashSet::new(), "Teatri").with_time_frame(Some(
+            TimeFrame::Dates(DateSet::new(dates.to_vec()).unwrap()),
+        ));
+
+        let ics = write_ics(&category(event.clone()), &week(), &Reminders::default());
+        let uids: Vec<&str> = ics.lines().filter(|l| l.starts_with("UID:")).collect();
+
+        // The last date is outside of the week
+        assert_eq!(
+            uids,
+            vec![
+                "UID:amleto-20251223@scraper-caffe",
+                "UID:amleto-20251225@scraper-caffe"
+            ]
+        );
+        // Identifiers don't change between runs
+        let again = write_ics(&category(event), &week(), &Reminders::default());
+        assert_eq!(again.lines().filter(|l| l.starts_with("UID:")).count(), 2);
+        assert!(again.contains("UID:amleto-20251223@scraper-caffe"));
+    }
+
+    #[test]
+    fn test_period_is_a_single_entry() {
+        let period = DateRange::new(date() - Days::new(10), date() + Days::new(40));
+        let event = Event::new("Mostra", HashSet::new(), "Mostre")
+            .with_time_frame(Some(TimeFrame::Period(period)));
+
+        let ics = write_ics(&category(event), &week(), &Reminders::default());
+
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("DTSTART;VALUE=DATE:20251213\r\n"));
+        assert!(ics.contains("DTEND;VALUE=DATE:20260202\r\n"));
+    }
+}
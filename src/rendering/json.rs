@@ -0,0 +1,198 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dates::DateRange,
+    events::Category,
+    rendering::{TemplateData, TemplateEvent, build_template_data, category_order::CategoryOrder},
+};
+
+/// The scraped events of a run, grouped by category, with everything needed to
+/// render them again later without scraping.
+#[derive(Serialize, Deserialize)]
+pub struct EventsDocument {
+    pub title: String,
+    pub date_range: DateRange,
+    pub categories: Vec<Category>,
+}
+
+/// The JSON output: the events as scraped, which render-from reads back, and the
+/// page data built from them, with time frames formatted as on the page.
+#[derive(Serialize)]
+struct JsonOutput {
+    #[serde(flatten)]
+    document: EventsDocument,
+    page: TemplateData,
+}
+
+pub fn write_json(
+    categories: &[Category],
+    date_range: &DateRange,
+    title: &str,
+    order: &CategoryOrder,
+) -> Result<String> {
+    let today = chrono::Local::now().date_naive();
+    let output = JsonOutput {
+        document: EventsDocument {
+            title: title.to_string(),
+            date_range: date_range.clone(),
+            categories: categories.to_vec(),
+        },
+        page: build_template_data(categories.to_vec(), date_range, title, today, order),
+    };
+    return Ok(serde_json::to_string_pretty(&output)?);
+}
+
+pub fn read_json(json: &str) -> Result<EventsDocument> {
+    return Ok(serde_json::from_str(json)?);
+}
+
+/// A single event as shown on the page, with the context it was listed in.
+#[derive(Serialize)]
+struct EventView<'a> {
+    #[serde(flatten)]
+    event: &'a TemplateEvent,
+    category: &'a str,
+    date_range: &'a DateRange,
+}
+
+/// A rendered JSON file for a single event, along with the name of the file it
+/// should be saved as (e.g., `amleto.json`).
+pub struct EventFile {
+    pub filename: String,
+    pub json: String,
+}
+
+/// Renders one JSON file per event, for pipelines that ingest events one by one.
+/// Files are named after the event anchors, so they match the page permalinks.
+pub fn write_event_json(
+    categories: &[Category],
+    date_range: &DateRange,
+    title: &str,
+    order: &CategoryOrder,
+) -> Result<Vec<EventFile>> {
+    let today = chrono::Local::now().date_naive();
+    let data = build_template_data(categories.to_vec(), date_range, title, today, order);
+
+    let mut files = Vec::new();
+    for category in &data.categories {
+        for event in &category.events {
+            let view = EventView {
+                event,
+                category: &category.name,
+                date_range,
+            };
+            files.push(EventFile {
+                filename: format!("{}.json", event.anchor),
+                json: serde_json::to_string_pretty(&view)?,
+            });
+        }
+    }
+    return Ok(files);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::{
+        dates::{DateSet, TimeFrame},
+        events::{Event, Location},
+        rendering::{DEFAULT_DISCLAIMER, render_to_html},
+    };
+
+    #[test]
+    fn test_render_from_json_round_trip() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let range = DateRange::new(date, date);
+        let location = Location::new("Verdi", Some("https://example.com".to_string()));
+        let categories = vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![
+                Event::new("Amleto", HashSet::from([location]), "Teatri")
+                    .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date]).unwrap())))
+                    .with_summary(Some("Una tragedia.".to_string()))
+                    .with_metadata(HashMap::from([("duration".to_string(), "3h".to_string())])),
+            ],
+        }];
+
+        let json = write_json(&categories, &range, "Titolo", &CategoryOrder::default()).unwrap();
+        let document = read_json(&json).unwrap();
+
+        let render = |categories, range: &DateRange, title: &str| {
+            render_to_html(
+                categories,
+                range,
+                title,
+                None,
+                None,
+                Some(DEFAULT_DISCLAIMER.into()),
+                false,
+                date,
+                None,
+                &CategoryOrder::default(),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            render(document.categories, &document.date_range, &document.title),
+            render(categories, &range, "Titolo")
+        );
+    }
+
+    #[test]
+    fn test_json_has_page_time_frames() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let range = DateRange::new(date, date);
+        let categories = vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![
+                Event::new("Amleto", HashSet::new(), "Teatri")
+                    .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date]).unwrap()))),
+            ],
+        }];
+
+        let json = write_json(&categories, &range, "Titolo", &CategoryOrder::default()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let event = &value["page"]["categories"][0]["events"][0];
+        assert_eq!(event["title"], "Amleto");
+        assert!(event["time_frame"].is_string());
+        assert_eq!(value["categories"][0]["events"][0]["title"], "Amleto");
+    }
+
+    #[test]
+    fn test_one_json_file_per_event() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let range = DateRange::new(date, date);
+        let categories = vec![
+            Category {
+                name: "Teatri".to_string(),
+                events: vec![
+                    Event::new("Amleto", HashSet::new(), "Teatri"),
+                    Event::new("Macbeth", HashSet::new(), "Teatri"),
+                ],
+            },
+            Category {
+                name: "Librerie".to_string(),
+                events: vec![Event::new("Amleto", HashSet::new(), "Librerie")],
+            },
+        ];
+
+        let files =
+            write_event_json(&categories, &range, "Titolo", &CategoryOrder::default()).unwrap();
+
+        let filenames: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(
+            filenames,
+            vec!["amleto.json", "macbeth.json", "amleto-2.json"]
+        );
+        let json: serde_json::Value = serde_json::from_str(&files[2].json).unwrap();
+        assert_eq!(json["title"], "Amleto");
+        assert_eq!(json["category"], "Librerie");
+        assert_eq!(json["date_range"]["start"], "2026-01-12");
+    }
+}
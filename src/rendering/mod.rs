@@ -1,105 +1,366 @@
+mod anchors;
+pub mod atom;
+pub mod category_order;
 mod formatting;
+pub mod ics;
+pub mod json;
+pub mod output;
+pub mod rss;
 
-use anyhow::Result;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use anyhow::{Result, anyhow};
+use chrono::{NaiveDate, NaiveTime, Timelike};
+use clap::ValueEnum;
 use handlebars::{Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext};
 use serde::{Deserialize, Serialize};
+use tracing::info;
 
 use crate::{
-    dates::{DateRange, DateSet, TimeFrame},
-    events::{Category, Event, Location},
-    venues::CATEGORY_MOVIES,
+    dates::{DateRange, DateSet, Showtime, TimeFrame},
+    events::{Category, Event, Location, dedup_key},
+    rendering::category_order::CategoryOrder,
+    venues::{CATEGORY_MOVIES, CATEGORY_THEATRES, theaters::RASSEGNA_KEY},
 };
 
+/// The footer shown in the header of the page unless disabled or customized.
+pub const DEFAULT_DISCLAIMER: &str =
+    "Lista generata automaticamente: potrebbe contenere errori o duplicati.";
+
+/// The default page template, built into the binary so that it runs from any directory.
+const DEFAULT_TEMPLATE: &str = include_str!("template.html");
+
+/// Shown in place of the dates of events whose dates couldn't be found.
+const UNKNOWN_DATES: &str = "data da confermare";
+
+/// The format of the main output file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A web page, rendered from the template.
+    #[default]
+    Html,
+    /// The data of the page as JSON, which can be rendered again with render-from.
+    Json,
+    /// An iCalendar file to import in a calendar app.
+    Ical,
+}
+
 #[derive(Serialize, Deserialize)]
 struct TemplateData {
+    title: String,
+    /// The city of the events, named in the header, if known.
+    #[serde(default)]
+    city: Option<String>,
     start_date: String,
     end_date: String,
     current_date: String,
+    /// An introductory paragraph shown in the header, if any.
+    intro: Option<String>,
+    /// A disclaimer about the contents of the page, if any.
+    disclaimer: Option<String>,
     categories: Vec<TemplateCategory>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct TemplateCategory {
+    name: String,
+    /// The name the category is shown with on the page.
+    #[serde(default)]
+    display_name: String,
+    events: Vec<TemplateEvent>,
+    /// Events of the category grouped by rassegna, when grouping is enabled.
+    #[serde(default)]
+    rassegne: Vec<TemplateRassegna>,
+}
+
+/// A themed season or festival of a theater, with its shows.
+#[derive(Serialize, Deserialize)]
+struct TemplateRassegna {
     name: String,
     events: Vec<TemplateEvent>,
 }
 
-impl From<Category> for TemplateCategory {
-    fn from(cat: Category) -> Self {
+impl TemplateCategory {
+    /// Formats the events of a category shown for the `window` of the page.
+    fn new(cat: Category, window: &DateRange) -> Self {
         let events = match cat.name.as_str() {
             CATEGORY_MOVIES => formatting::preprocess_films(cat.events),
-            _ => cat.events.into_iter().map(TemplateEvent::from).collect(),
+            CATEGORY_THEATRES => formatting::preprocess_shows(cat.events, window),
+            _ => cat
+                .events
+                .into_iter()
+                .map(|e| TemplateEvent::new(e, window))
+                .collect(),
         };
 
         Self {
+            display_name: cat.name.clone(),
             name: cat.name,
             events,
+            rassegne: Vec::new(),
         }
     }
 }
 
 #[derive(Serialize, Deserialize)]
 struct TemplateEvent {
+    /// Unique identifier of the event within the page, for permalinks.
+    pub anchor: String,
     pub title: String,
     pub tags: Vec<String>,
     pub locations: Vec<Location>,
     pub time_frame: Option<String>,
     pub summary: Option<String>,
     pub description: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+    /// The earliest date of the event, if known.
+    pub first_date: Option<NaiveDate>,
+    /// Whether the event happens on the day the page is generated.
+    pub is_today: bool,
+    /// Other entries of the same event that weren't merged with this one.
+    #[serde(default)]
+    pub related: Vec<RelatedEvent>,
+    /// The separate runs of a show grouped in this entry, if there's more than one.
+    #[serde(default)]
+    pub runs: Vec<TemplateRun>,
 }
 
-impl From<Event> for TemplateEvent {
-    fn from(value: Event) -> Self {
+/// One of the runs of a show, such as two stagings at the same theater months apart.
+#[derive(Serialize, Deserialize, Clone)]
+struct TemplateRun {
+    /// The venues of the run, joined for display.
+    pub locations: String,
+    pub time_frame: Option<String>,
+}
+
+/// A pointer to another entry of the same event, such as a show listed in more
+/// than one category.
+#[derive(Serialize, Deserialize, Clone)]
+struct RelatedEvent {
+    pub anchor: String,
+    /// The venues of the entry, joined for display.
+    pub locations: String,
+    pub time_frame: Option<String>,
+}
+
+impl TemplateEvent {
+    /// Formats an event shown for the `window` of the page.
+    fn new(value: Event, window: &DateRange) -> Self {
         let mut tags: Vec<String> = value.tags.into_iter().collect();
         tags.sort();
         let mut locations: Vec<Location> = value.locations.into_iter().collect();
         locations.sort_by(|a, b| a.name.cmp(&b.name));
-        let time_frame = value.time_frame.map(|tf| match tf {
-            TimeFrame::Dates(set) => fmt_date_set(&set),
-            TimeFrame::Period(range) => fmt_date_range(&range),
-        });
+        let first_date = value.time_frame.as_ref().map(|tf| tf.as_range().start);
+        let time_frame = match value.time_frame {
+            Some(TimeFrame::Dates(set)) => fmt_date_set(&set, &value.showtimes),
+            Some(TimeFrame::Period(range)) => fmt_date_range(&range, window),
+            None => UNKNOWN_DATES.to_string(),
+        };
 
         Self {
+            anchor: String::new(),
             title: value.title,
             tags,
             locations,
-            time_frame,
+            time_frame: Some(time_frame),
             summary: value.summary,
             description: value.description,
+            metadata: value.metadata.into_iter().collect(),
+            first_date,
+            is_today: false,
+            related: Vec::new(),
+            runs: Vec::new(),
         }
     }
 }
 
-pub fn render_to_html(categories: Vec<Category>, date_range: &DateRange) -> Result<String> {
-    println!("Converting to HTML...");
-    let data = TemplateData {
+/// Converts the categories to the data shown in the rendered outputs, giving each
+/// event a unique anchor and flagging the ones happening `today`. Categories are put
+/// in `order` first, so that every output gives the same anchors.
+fn build_template_data(
+    categories: Vec<Category>,
+    date_range: &DateRange,
+    title: &str,
+    today: NaiveDate,
+    order: &CategoryOrder,
+) -> TemplateData {
+    // Film variants are merged by title, so flag titles rather than single events
+    let today_titles: HashSet<(String, String)> = categories
+        .iter()
+        .flat_map(|c| c.events.iter().map(move |e| (c, e)))
+        .filter(|(_, e)| e.time_frame.as_ref().is_some_and(|tf| tf.includes(&today)))
+        .map(|(c, e)| (c.name.clone(), e.title.clone()))
+        .collect();
+
+    let mut categories: Vec<TemplateCategory> = categories
+        .into_iter()
+        .map(|c| TemplateCategory::new(c, date_range))
+        .collect();
+    sort_categories(&mut categories, order);
+
+    let mut anchors = anchors::Anchors::default();
+    for category in categories.iter_mut() {
+        for event in category.events.iter_mut() {
+            event.anchor = anchors.next(&event.title);
+            event.is_today = today_titles.contains(&(category.name.clone(), event.title.clone()));
+        }
+    }
+    link_related(&mut categories);
+
+    TemplateData {
+        title: title.to_string(),
+        city: None,
         start_date: date_range.start.format("%d/%m").to_string(),
         end_date: date_range.end.format("%d/%m").to_string(),
-        current_date: chrono::Local::now().format("%d/%m/%Y").to_string(),
-        categories: categories.into_iter().map(|c| c.into()).collect(),
+        current_date: today.format("%d/%m/%Y").to_string(),
+        intro: None,
+        disclaimer: None,
+        categories,
+    }
+}
+
+/// Puts the categories in the configured `order`, keeping the unlisted ones at the
+/// end in their current order, and names them as configured.
+fn sort_categories(categories: &mut [TemplateCategory], order: &CategoryOrder) {
+    categories.sort_by_key(|c| order.rank(&c.name));
+    for category in categories.iter_mut() {
+        category.display_name = order.display_name(&category.name).to_string();
+    }
+}
+
+/// Cross-references the entries of the same event, by [dedup_key], that are still
+/// separate, e.g. because they're in different categories.
+fn link_related(categories: &mut [TemplateCategory]) {
+    let mut by_key: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+    for (c, category) in categories.iter().enumerate() {
+        for (e, event) in category.events.iter().enumerate() {
+            by_key
+                .entry(dedup_key(&event.title))
+                .or_default()
+                .push((c, e));
+        }
+    }
+
+    for entries in by_key.values().filter(|entries| entries.len() > 1) {
+        for &(c, e) in entries {
+            let related: Vec<RelatedEvent> = entries
+                .iter()
+                .filter(|&&other| other != (c, e))
+                .map(|&(other_c, other_e)| {
+                    let other = &categories[other_c].events[other_e];
+                    RelatedEvent {
+                        anchor: other.anchor.clone(),
+                        locations: other
+                            .locations
+                            .iter()
+                            .map(|l| l.name.as_str())
+                            .collect::<Vec<&str>>()
+                            .join(", "),
+                        time_frame: other.time_frame.clone(),
+                    }
+                })
+                .collect();
+            categories[c].events[e].related = related;
+        }
+    }
+}
+
+/// Moves the shows of the theaters category that are part of a rassegna under it,
+/// in order of first appearance. Shows with no rassegna are left as they are.
+fn group_by_rassegna(categories: &mut [TemplateCategory]) {
+    let Some(theaters) = categories.iter_mut().find(|c| c.name == CATEGORY_THEATRES) else {
+        return;
     };
 
+    let mut ungrouped = Vec::new();
+    for event in theaters.events.drain(..) {
+        let Some(name) = event.metadata.get(RASSEGNA_KEY).cloned() else {
+            ungrouped.push(event);
+            continue;
+        };
+        match theaters.rassegne.iter_mut().find(|r| r.name == name) {
+            Some(rassegna) => rassegna.events.push(event),
+            None => theaters.rassegne.push(TemplateRassegna {
+                name,
+                events: vec![event],
+            }),
+        }
+    }
+    theaters.events = ungrouped;
+}
+
+/// Renders the page with the template at `template_path`, or the default one if not given.
+#[allow(clippy::too_many_arguments)]
+pub fn render_to_html(
+    categories: Vec<Category>,
+    date_range: &DateRange,
+    title: &str,
+    city: Option<String>,
+    intro: Option<String>,
+    disclaimer: Option<String>,
+    group_rassegne: bool,
+    today: NaiveDate,
+    template_path: Option<&Path>,
+    order: &CategoryOrder,
+) -> Result<String> {
+    info!("Converting to HTML...");
+    let mut data = TemplateData {
+        city,
+        intro,
+        disclaimer,
+        ..build_template_data(categories, date_range, title, today, order)
+    };
+    if group_rassegne {
+        group_by_rassegna(&mut data.categories);
+    }
+
+    return render_template(&data, template_path);
+}
+
+fn render_template(data: &TemplateData, template_path: Option<&Path>) -> Result<String> {
+    let template = match template_path {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read template {}: {e}", path.display()))?,
+        None => DEFAULT_TEMPLATE.to_string(),
+    };
     let mut handlebars = Handlebars::new();
-    handlebars.register_template_file("qsat", "src/rendering/template.html")?;
+    handlebars.register_template_string("qsat", template)?;
     handlebars.register_helper("uppercase", Box::new(Uppercase));
     handlebars.register_helper("join", Box::new(Join));
 
-    let html = handlebars.render("qsat", &data)?;
+    let html = handlebars.render("qsat", data)?;
 
     Ok(html)
 }
 
-fn fmt_date_set(set: &DateSet) -> String {
+/// Formats the dates of a set along with their showtime, if known, e.g.
+/// "il 22/12 e 23/12 alle 19.30".
+fn fmt_date_set(set: &DateSet, showtimes: &BTreeMap<NaiveDate, Showtime>) -> String {
     let parts: Vec<String> = set
         .dates()
         .iter()
-        .map(|d| d.format("%d/%m").to_string())
+        .map(|d| match showtimes.get(d) {
+            Some(showtime) => format!(
+                "{} alle {}",
+                d.format("%d/%m"),
+                fmt_time(showtime.start, Locale::Italian)
+            ),
+            None => d.format("%d/%m").to_string(),
+        })
         .collect();
 
     fmt_date_parts(parts)
 }
 
-fn fmt_date_range(range: &DateRange) -> String {
+/// Formats a period such as the run of an exhibition. Periods that began before the
+/// `window` are shown as ongoing, since only their end matters to the reader.
+fn fmt_date_range(range: &DateRange, window: &DateRange) -> String {
+    if range.start < window.start {
+        return format!("in corso, fino al {}", range.end.format("%d/%m/%Y"));
+    }
     format!(
         "dal {} al {}",
         range.start.format("%d/%m/%Y"),
@@ -107,6 +368,22 @@ fn fmt_date_range(range: &DateRange) -> String {
     )
 }
 
+/// Conventions to follow when formatting text for display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Locale {
+    Italian,
+    English,
+}
+
+/// Formats a time of day according to the conventions of the locale. Italian
+/// uses a dot separator and no leading zero on the hour (e.g., "9.30", "21.00").
+pub fn fmt_time(time: NaiveTime, locale: Locale) -> String {
+    match locale {
+        Locale::Italian => format!("{}.{:02}", time.hour(), time.minute()),
+        Locale::English => time.format("%H:%M").to_string(),
+    }
+}
+
 /// Helper to format a list of strings into an Italian enumeration (e.g., "il A, B e C")
 fn fmt_date_parts(mut parts: Vec<String>) -> String {
     if parts.is_empty() {
@@ -140,6 +417,8 @@ impl HelperDef for Uppercase {
     }
 }
 
+/// Joins an array with the separator given as second parameter, or ", " if none.
+/// Objects in the array, such as locations, are joined by their name.
 struct Join;
 impl HelperDef for Join {
     fn call<'reg: 'rc, 'rc>(
@@ -151,12 +430,12 @@ impl HelperDef for Join {
         out: &mut dyn Output,
     ) -> HelperResult {
         let array = h.param(0).unwrap().value().as_array();
-        let sep = h.param(0).and_then(|v| v.value().as_str()).unwrap_or(", ");
+        let sep = h.param(1).and_then(|v| v.value().as_str()).unwrap_or(", ");
 
         if let Some(vec) = array {
             let strings: Vec<String> = vec
                 .iter()
-                .filter_map(|v| v.as_str())
+                .filter_map(|v| v.as_str().or_else(|| v["name"].as_str()))
                 .map(|s| s.to_string())
                 .collect();
             out.write(&strings.join(sep))?;
@@ -165,3 +444,340 @@ impl HelperDef for Join {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_fmt_time_italian() {
+        assert_eq!(fmt_time(time(21, 0), Locale::Italian), "21.00");
+        assert_eq!(fmt_time(time(9, 30), Locale::Italian), "9.30");
+        assert_eq!(fmt_time(time(0, 5), Locale::Italian), "0.05");
+        assert_eq!(fmt_time(time(18, 45), Locale::Italian), "18.45");
+    }
+
+    #[test]
+    fn test_metadata_in_json_output() {
+        let metadata = HashMap::from([("director".to_string(), "Denis Villeneuve".to_string())]);
+        let event = Event::new("Dune", HashSet::new(), "Film").with_metadata(metadata);
+
+        let today = NaiveDate::from_ymd_opt(2026, 1, 14).unwrap();
+        let window = DateRange::new(today, today);
+        let json = serde_json::to_value(TemplateEvent::new(event, &window)).unwrap();
+
+        assert_eq!(json["metadata"]["director"], "Denis Villeneuve");
+    }
+
+    #[test]
+    fn test_showtimes_in_time_frame() {
+        let date = |day| NaiveDate::from_ymd_opt(2025, 12, day).unwrap();
+        let set = DateSet::new(vec![date(22), date(23)]).unwrap();
+        let showtime = Showtime {
+            start: time(19, 30),
+            end: None,
+        };
+
+        let with_time = fmt_date_set(&set, &BTreeMap::from([(date(23), showtime)]));
+        let without_time = fmt_date_set(&set, &BTreeMap::new());
+
+        assert_eq!(with_time, "il 22/12 e 23/12 alle 19.30");
+        assert_eq!(without_time, "il 22/12 e 23/12");
+    }
+
+    #[test]
+    fn test_ongoing_period_shows_only_its_end() {
+        let date = |month, day| NaiveDate::from_ymd_opt(2026, month, day).unwrap();
+        let week = DateRange::new(date(1, 12), date(1, 18));
+
+        let ongoing = DateRange::new(date(1, 2), date(1, 15));
+        let starting = DateRange::new(date(1, 14), date(2, 28));
+
+        assert_eq!(
+            fmt_date_range(&ongoing, &week),
+            "in corso, fino al 15/01/2026"
+        );
+        assert_eq!(
+            fmt_date_range(&starting, &week),
+            "dal 14/01/2026 al 28/02/2026"
+        );
+    }
+
+    #[test]
+    fn test_custom_template() {
+        let today = NaiveDate::from_ymd_opt(2026, 1, 14).unwrap();
+        let data = build_template_data(
+            vec![],
+            &DateRange::new(today, today),
+            "Titolo",
+            today,
+            &CategoryOrder::default(),
+        );
+        let path = std::env::temp_dir().join(format!("template_{}.html", std::process::id()));
+        std::fs::write(&path, "<h1>{{uppercase title}}</h1>").unwrap();
+
+        let html = render_template(&data, Some(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(html, "<h1>TITOLO</h1>");
+        assert!(render_template(&data, Some(&path)).is_err());
+    }
+
+    #[test]
+    fn test_join_separator() {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("join", Box::new(Join));
+        let data = serde_json::json!({
+            "tags": ["3D", "Originale"],
+            "locations": [Location::new("Ariston", None), Location::new("Nazionale", None)],
+        });
+
+        let render = |template: &str| handlebars.render_template(template, &data).unwrap();
+
+        assert_eq!(render(r#"{{join locations "; "}}"#), "Ariston; Nazionale");
+        assert_eq!(render("{{join tags}}"), "3D, Originale");
+    }
+
+    #[test]
+    fn test_fmt_time_english() {
+        assert_eq!(fmt_time(time(9, 30), Locale::English), "09:30");
+    }
+
+    #[test]
+    fn test_disclaimer_can_be_disabled() {
+        let today = chrono::Local::now().date_naive();
+        let range = DateRange::new(today, today);
+
+        let with = TemplateData {
+            disclaimer: Some(DEFAULT_DISCLAIMER.to_string()),
+            ..build_template_data(vec![], &range, "Titolo", today, &CategoryOrder::default())
+        };
+        let without =
+            build_template_data(vec![], &range, "Titolo", today, &CategoryOrder::default());
+
+        let with = render_template(&with, None).unwrap();
+        let without = render_template(&without, None).unwrap();
+        assert!(with.contains(DEFAULT_DISCLAIMER));
+        assert!(!without.contains(DEFAULT_DISCLAIMER));
+        assert!(!without.contains("class=\"disclaimer\""));
+    }
+
+    #[test]
+    fn test_events_happening_today_are_flagged() {
+        let date = |day| NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+        let today = date(14);
+        let event = |title: &str, time_frame| {
+            Event::new(title, HashSet::new(), "Teatri").with_time_frame(Some(time_frame))
+        };
+        let categories = vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![
+                event(
+                    "Amleto",
+                    TimeFrame::Dates(DateSet::new(vec![date(13), date(14)]).unwrap()),
+                ),
+                event(
+                    "Macbeth",
+                    TimeFrame::Dates(DateSet::new(vec![date(13), date(15)]).unwrap()),
+                ),
+                event(
+                    "Mostra",
+                    TimeFrame::Period(DateRange::new(date(10), date(20))),
+                ),
+                event(
+                    "Otello",
+                    TimeFrame::Period(DateRange::new(date(15), date(20))),
+                ),
+            ],
+        }];
+
+        let data = build_template_data(
+            categories,
+            &DateRange::new(date(12), date(18)),
+            "Titolo",
+            today,
+            &CategoryOrder::default(),
+        );
+
+        let flags: Vec<(&str, bool)> = data.categories[0]
+            .events
+            .iter()
+            .map(|e| (e.title.as_str(), e.is_today))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![
+                ("Amleto", true),
+                ("Macbeth", false),
+                ("Mostra", true),
+                ("Otello", false)
+            ]
+        );
+        assert!(
+            render_template(&data, None)
+                .unwrap()
+                .contains("class=\"today\"")
+        );
+    }
+
+    #[test]
+    fn test_unknown_dates_are_to_be_confirmed() {
+        let today = NaiveDate::from_ymd_opt(2026, 1, 14).unwrap();
+        let categories = vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![Event::new("Amleto", HashSet::new(), "Teatri")],
+        }];
+
+        let data = build_template_data(
+            categories,
+            &DateRange::new(today, today),
+            "Titolo",
+            today,
+            &CategoryOrder::default(),
+        );
+
+        let event = &data.categories[0].events[0];
+        assert_eq!(event.time_frame.as_deref(), Some(UNKNOWN_DATES));
+        assert!(!event.is_today);
+        assert!(
+            render_template(&data, None)
+                .unwrap()
+                .contains(UNKNOWN_DATES)
+        );
+    }
+
+    #[test]
+    fn test_same_event_in_different_categories_is_cross_referenced() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 14).unwrap();
+        let event = |title: &str, venue: &str, category: &str| {
+            Event::new(title, HashSet::from([Location::new(venue, None)]), category)
+                .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date]).unwrap())))
+        };
+        let categories = vec![
+            Category {
+                name: "Teatri".to_string(),
+                events: vec![
+                    event("Il Flauto Magico", "Verdi", "Teatri"),
+                    event("Amleto", "Rossetti", "Teatri"),
+                ],
+            },
+            Category {
+                name: "Musica".to_string(),
+                events: vec![event("Il flauto magico!", "Miela", "Musica")],
+            },
+        ];
+
+        let data = build_template_data(
+            categories,
+            &DateRange::new(date, date),
+            "Titolo",
+            date,
+            &CategoryOrder::default(),
+        );
+
+        let theater = &data.categories[0].events[0];
+        let music = &data.categories[1].events[0];
+        assert_eq!(theater.related.len(), 1);
+        assert_eq!(theater.related[0].anchor, music.anchor);
+        assert_eq!(theater.related[0].locations, "Miela");
+        assert_eq!(music.related.len(), 1);
+        assert_eq!(music.related[0].anchor, theater.anchor);
+        assert!(data.categories[0].events[1].related.is_empty());
+        assert!(render_template(&data, None).unwrap().contains("anche a:"));
+    }
+
+    #[test]
+    fn test_theaters_are_clustered_by_rassegna() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 14).unwrap();
+        let show = |title: &str, rassegna: Option<&str>| {
+            let metadata = rassegna
+                .map(|r| (RASSEGNA_KEY.to_string(), r.to_string()))
+                .into_iter()
+                .collect();
+            Event::new(title, HashSet::new(), CATEGORY_THEATRES).with_metadata(metadata)
+        };
+        let categories = vec![Category {
+            name: CATEGORY_THEATRES.to_string(),
+            events: vec![
+                show("Amleto", None),
+                show("Pinocchio", Some("Teatro Ragazzi")),
+                show("Cenerentola", Some("Teatro Ragazzi")),
+                show("Otello", Some("Shakespeare Festival")),
+            ],
+        }];
+
+        let mut data = build_template_data(
+            categories,
+            &DateRange::new(date, date),
+            "Titolo",
+            date,
+            &CategoryOrder::default(),
+        );
+        group_by_rassegna(&mut data.categories);
+
+        let theaters = &data.categories[0];
+        assert_eq!(theaters.events.len(), 1);
+        assert_eq!(theaters.events[0].title, "Amleto");
+        let rassegne: Vec<(&str, Vec<&str>)> = theaters
+            .rassegne
+            .iter()
+            .map(|r| {
+                (
+                    r.name.as_str(),
+                    r.events.iter().map(|e| e.title.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rassegne,
+            vec![
+                ("Teatro Ragazzi", vec!["Pinocchio", "Cenerentola"]),
+                ("Shakespeare Festival", vec!["Otello"]),
+            ]
+        );
+
+        let html = render_template(&data, None).unwrap();
+        assert!(html.contains("<h3 class=\"rassegna-header\">Teatro Ragazzi</h3>"));
+        assert!(html.contains("Cenerentola"));
+    }
+
+    #[test]
+    fn test_categories_follow_the_configured_order() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 14).unwrap();
+        let category = |name: &str| Category {
+            name: name.to_string(),
+            events: vec![Event::new("Evento", HashSet::new(), name)],
+        };
+        let categories = ["Film", "Librerie", "Mostre", "Teatri"]
+            .map(category)
+            .to_vec();
+        let order = CategoryOrder::parse(
+            r#"
+            [[categories]]
+            key = "Teatri"
+            name = "Teatro"
+
+            [[categories]]
+            key = "Film"
+            "#,
+        )
+        .unwrap();
+
+        let data = build_template_data(
+            categories,
+            &DateRange::new(date, date),
+            "Titolo",
+            date,
+            &order,
+        );
+
+        let names: Vec<&str> = data.categories.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Teatri", "Film", "Librerie", "Mostre"]);
+        assert!(render_template(&data, None).unwrap().contains("TEATRO"));
+    }
+}
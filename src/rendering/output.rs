@@ -0,0 +1,109 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+
+/// The directory the generated files are written to unless another one is given.
+pub const DEFAULT_OUTPUT_DIR: &str = "qsat";
+
+/// The UTF-8 byte order mark, which some Windows programs need to detect the encoding.
+const BOM: &str = "\u{feff}";
+
+/// The line endings of the written files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LineEnding {
+    /// Unix line endings. Formats that mandate CRLF, like iCalendar, keep them.
+    #[default]
+    Lf,
+    /// Windows line endings.
+    Crlf,
+}
+
+/// How the generated files are written to disk. They're always encoded as UTF-8.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputOptions {
+    pub line_ending: LineEnding,
+    /// Whether to start the files with a byte order mark.
+    pub bom: bool,
+}
+
+impl OutputOptions {
+    /// Applies the options to the contents of a file.
+    pub fn encode(&self, content: &str) -> String {
+        let content = match self.line_ending {
+            LineEnding::Lf => content.to_string(),
+            LineEnding::Crlf => content.replace("\r\n", "\n").replace('\n', "\r\n"),
+        };
+        return match self.bom {
+            true => format!("{BOM}{content}"),
+            false => content,
+        };
+    }
+
+    /// Writes the contents to a file, applying the options. Creates the directories
+    /// leading to the file if they're missing.
+    pub fn write(&self, path: impl AsRef<Path>, content: &str) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                anyhow!(
+                    "Failed to create output directory {}: {e}",
+                    parent.display()
+                )
+            })?;
+        }
+        std::fs::write(path, self.encode(content))
+            .map_err(|e| anyhow!("Failed to write {}: {e}", path.display()))?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crlf_line_endings_in_written_file() {
+        let path = std::env::temp_dir().join(format!("output_crlf_{}.html", std::process::id()));
+        let options = OutputOptions {
+            line_ending: LineEnding::Crlf,
+            bom: false,
+        };
+
+        // Lines already ending in CRLF aren't doubled up
+        options.write(&path, "<p>\n  Amleto\r\n</p>\n").unwrap();
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(written, b"<p>\r\n  Amleto\r\n</p>\r\n");
+    }
+
+    #[test]
+    fn test_missing_directories_are_created() {
+        let dir = std::env::temp_dir().join(format!("output_dirs_{}", std::process::id()));
+        let path = dir.join("feeds").join("film.xml");
+
+        OutputOptions::default().write(&path, "<rss/>").unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(written, "<rss/>");
+    }
+
+    #[test]
+    fn test_default_is_lf_without_bom() {
+        let options = OutputOptions::default();
+        assert_eq!(options.encode("a\nb\n"), "a\nb\n");
+
+        let options = OutputOptions {
+            bom: true,
+            ..options
+        };
+        assert!(
+            options
+                .encode("a\n")
+                .as_bytes()
+                .starts_with(&[0xEF, 0xBB, 0xBF])
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::Result;
+
+use crate::{
+    dates::DateRange,
+    events::Category,
+    rendering::{TemplateEvent, build_template_data, category_order::CategoryOrder},
+    utils::slugify,
+};
+
+/// A rendered RSS feed for a single category, along with the name of the file
+/// it should be saved as (e.g., `film.xml`).
+pub struct CategoryFeed {
+    pub filename: String,
+    pub xml: String,
+}
+
+/// Renders a single RSS 2.0 feed containing the events of all categories.
+pub fn write_rss(
+    categories: &[Category],
+    date_range: &DateRange,
+    title: &str,
+    order: &CategoryOrder,
+) -> Result<String> {
+    let today = chrono::Local::now().date_naive();
+    let data = build_template_data(categories.to_vec(), date_range, title, today, order);
+    let events: Vec<&TemplateEvent> = data.categories.iter().flat_map(|c| &c.events).collect();
+    Ok(render_channel(title, &events, date_range))
+}
+
+/// Renders one RSS 2.0 feed per category, so that subscribers can follow
+/// only the categories they care about.
+pub fn write_category_rss(
+    categories: &[Category],
+    date_range: &DateRange,
+    title: &str,
+    order: &CategoryOrder,
+) -> Result<Vec<CategoryFeed>> {
+    // Build the data for all categories at once so that anchors match the ones
+    // in the combined feed and in the HTML page
+    let today = chrono::Local::now().date_naive();
+    let data = build_template_data(categories.to_vec(), date_range, title, today, order);
+    let feeds = data
+        .categories
+        .iter()
+        .map(|cat| {
+            let feed_title = format!("{title} · {}", cat.name);
+            let events: Vec<&TemplateEvent> = cat.events.iter().collect();
+            CategoryFeed {
+                filename: format!("{}.xml", slugify(&cat.name)),
+                xml: render_channel(&feed_title, &events, date_range),
+            }
+        })
+        .collect();
+
+    Ok(feeds)
+}
+
+fn render_channel(title: &str, events: &[&TemplateEvent], date_range: &DateRange) -> String {
+    // Feed readers show items newest first, so the latest events go on top
+    let mut events = events.to_vec();
+    events.sort_by(|a, b| b.first_date.cmp(&a.first_date));
+    let items: String = events.iter().map(|e| render_item(e)).collect();
+
+    let description = format!(
+        "Eventi dal {} al {}",
+        date_range.start.format("%d/%m/%Y"),
+        date_range.end.format("%d/%m/%Y")
+    );
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <rss version=\"2.0\">\n\
+        <channel>\n\
+        <title>{}</title>\n\
+        <description>{}</description>\n\
+        <language>it</language>\n\
+        {items}\
+        </channel>\n\
+        </rss>\n",
+        escape_xml(title),
+        escape_xml(&description),
+    )
+}
+
+fn render_item(event: &TemplateEvent) -> String {
+    let mut item = format!("<item>\n<title>{}</title>\n", escape_xml(&event.title));
+
+    if let Some(text) = event.summary.as_ref().or(event.description.as_ref()) {
+        item.push_str(&format!(
+            "<description>{}</description>\n",
+            escape_xml(text)
+        ));
+    }
+
+    let link = event.locations.iter().find_map(|l| l.url.as_ref());
+    if let Some(link) = link {
+        item.push_str(&format!("<link>{}</link>\n", escape_xml(link)));
+    }
+
+    // The anchor of the event in the HTML page is unique within a run
+    item.push_str(&format!(
+        "<guid isPermaLink=\"false\">{}</guid>\n",
+        escape_xml(&event.anchor)
+    ));
+
+    if let Some(date) = event.first_date {
+        let date = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+        item.push_str(&format!("<pubDate>{}</pubDate>\n", date.to_rfc2822()));
+    }
+
+    item.push_str("</item>\n");
+    item
+}
+
+/// Escapes the five predefined XML entities.
+pub(super) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::{
+        dates::{DateSet, TimeFrame},
+        events::Event,
+    };
+
+    const TITLE: &str = "Questa Settimana a Trieste";
+
+    fn category(name: &str, titles: &[&str]) -> Category {
+        Category {
+            name: name.to_string(),
+            events: titles
+                .iter()
+                .map(|t| Event::new(t, HashSet::new(), name))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_category_feeds_contain_only_their_items() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let range = DateRange::new(date, date);
+        let categories = vec![
+            category("Film", &["Dune"]),
+            category("Teatri", &["Amleto", "Otello"]),
+        ];
+
+        let feeds =
+            write_category_rss(&categories, &range, TITLE, &CategoryOrder::default()).unwrap();
+
+        assert_eq!(feeds.len(), 2);
+        assert_eq!(feeds[0].filename, "film.xml");
+        assert!(feeds[0].xml.contains("<title>Dune</title>"));
+        assert!(!feeds[0].xml.contains("Amleto"));
+        assert_eq!(feeds[1].filename, "teatri.xml");
+        assert!(feeds[1].xml.contains("<title>Amleto</title>"));
+        assert!(feeds[1].xml.contains("<title>Otello</title>"));
+        assert!(!feeds[1].xml.contains("Dune"));
+        assert!(feeds[1].xml.contains("Questa Settimana a Trieste · Teatri"));
+    }
+
+    #[test]
+    fn test_guid_is_event_anchor() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let range = DateRange::new(date, date);
+        let categories = vec![
+            category("Librerie", &["Incontro con l'autore"]),
+            category("Teatri", &["Incontro con l'autore"]),
+        ];
+
+        let rss = write_rss(&categories, &range, TITLE, &CategoryOrder::default()).unwrap();
+
+        assert!(rss.contains("<guid isPermaLink=\"false\">incontro-con-l-autore</guid>"));
+        assert!(rss.contains("<guid isPermaLink=\"false\">incontro-con-l-autore-2</guid>"));
+    }
+
+    #[test]
+    fn test_items_sorted_newest_first_and_escaped() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let range = DateRange::new(date, date + chrono::Days::new(6));
+        let event = |title: &str, day: u64| {
+            let dates = DateSet::new(vec![date + chrono::Days::new(day)]).unwrap();
+            Event::new(title, HashSet::new(), "Teatri")
+                .with_time_frame(Some(TimeFrame::Dates(dates)))
+        };
+        let categories = vec![Category {
+            name: "Teatri".to_string(),
+            events: vec![event("Amleto", 1), event("Romeo & Giulietta", 4)],
+        }];
+
+        let rss = write_rss(&categories, &range, TITLE, &CategoryOrder::default()).unwrap();
+
+        let romeo = rss.find("<title>Romeo &amp; Giulietta</title>").unwrap();
+        let amleto = rss.find("<title>Amleto</title>").unwrap();
+        assert!(romeo < amleto);
+        assert!(rss.contains("<pubDate>Fri, 16 Jan 2026 00:00:00 +0000</pubDate>"));
+    }
+}
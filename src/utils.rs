@@ -1 +1,120 @@
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
 pub const PROGRESS_BAR_TEMPLATE: &str = "{msg:<30} [{elapsed_precise}] {bar} [{pos}/{len}]";
+
+/// Converts text to a lowercase, hyphen-separated string safe for filenames and URLs.
+pub fn slugify(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+/// Characters that take no space but still end up in scraped text, splitting words
+/// where there's nothing to split.
+const ZERO_WIDTH_CHARS: &[char] = &['\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}'];
+
+/// Replaces every run of unicode whitespace, such as non-breaking spaces, with a
+/// single regular space and removes zero-width characters, so that scraped text can
+/// be parsed as if it had been typed with plain spaces.
+pub fn normalize_whitespace(text: &str) -> String {
+    text.replace(ZERO_WIDTH_CHARS, "")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Decodes a response body into UTF-8 text. The encoding is taken from the charset of
+/// the `Content-Type` header if present, otherwise from a `<meta charset>` tag in the
+/// page itself. Bodies with no declared encoding that aren't valid UTF-8 are assumed
+/// to be Windows-1252, the usual culprit for mojibake on older sites.
+pub fn decode_body(bytes: &[u8], content_type: Option<&str>) -> String {
+    let declared = content_type
+        .and_then(charset_from_content_type)
+        .or_else(|| sniff_meta_charset(bytes))
+        .and_then(|label| Encoding::for_label(label.as_bytes()));
+
+    let encoding = match declared {
+        Some(encoding) => encoding,
+        None if std::str::from_utf8(bytes).is_ok() => UTF_8,
+        None => WINDOWS_1252,
+    };
+
+    // decode also handles byte order marks, which take precedence
+    let (text, _, _) = encoding.decode(bytes);
+    text.into_owned()
+}
+
+fn charset_from_content_type(content_type: &str) -> Option<String> {
+    content_type.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        if key.trim().eq_ignore_ascii_case("charset") {
+            Some(value.trim().trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Looks for a charset declaration in the first kilobyte of an HTML page.
+fn sniff_meta_charset(bytes: &[u8]) -> Option<String> {
+    let head = &bytes[..bytes.len().min(1024)];
+    let head = String::from_utf8_lossy(head).to_lowercase();
+    let start = head.find("charset=")? + "charset=".len();
+    let label: String = head[start..]
+        .trim_start_matches(['"', '\''])
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+
+    if label.is_empty() { None } else { Some(label) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "Caffè degli Incroci" in Latin-1
+    const LATIN_1_BODY: &[u8] = b"<p>Caff\xe8 degli Incroci</p>";
+
+    #[test]
+    fn test_decode_latin1_from_header() {
+        let text = decode_body(LATIN_1_BODY, Some("text/html; charset=ISO-8859-1"));
+        assert_eq!(text, "<p>Caffè degli Incroci</p>");
+    }
+
+    #[test]
+    fn test_decode_latin1_from_meta_tag() {
+        let mut body = b"<html><head><meta charset=\"iso-8859-1\"></head>".to_vec();
+        body.extend_from_slice(LATIN_1_BODY);
+        let text = decode_body(&body, Some("text/html"));
+        assert!(text.ends_with("<p>Caffè degli Incroci</p>"));
+    }
+
+    #[test]
+    fn test_decode_undeclared_latin1() {
+        let text = decode_body(LATIN_1_BODY, None);
+        assert_eq!(text, "<p>Caffè degli Incroci</p>");
+    }
+
+    #[test]
+    fn test_decode_utf8() {
+        let text = decode_body("<p>Caffè</p>".as_bytes(), Some("text/html; charset=utf-8"));
+        assert_eq!(text, "<p>Caffè</p>");
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Film"), "film");
+        assert_eq!(slugify("Mostre & Musei"), "mostre-musei");
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert_eq!(
+            normalize_whitespace(" 23\u{00A0}dicembre\u{200B}\t 2025\n"),
+            "23 dicembre 2025"
+        );
+    }
+}
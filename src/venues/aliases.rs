@@ -0,0 +1,61 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+
+use crate::utils::normalize_whitespace;
+
+/// Canonical titles for films that cinemas spell differently (e.g., "Il Gladiatore 2"
+/// and "Gladiatore II"), so that their screenings end up in the same group.
+#[derive(Default)]
+pub struct TitleAliases {
+    /// Canonical titles by the normalized variant title.
+    aliases: HashMap<String, String>,
+}
+
+impl TitleAliases {
+    /// Loads the aliases from a TOML file mapping each variant title to its canonical
+    /// title. Returns no aliases if the file doesn't exist.
+    pub fn load(filename: &str) -> Result<Self> {
+        if !Path::new(filename).exists() {
+            return Ok(Self::default());
+        }
+        return Self::parse(&fs::read_to_string(filename)?);
+    }
+
+    pub(super) fn parse(content: &str) -> Result<Self> {
+        let table: HashMap<String, String> = toml::from_str(content)?;
+        let aliases = table
+            .into_iter()
+            .map(|(variant, canonical)| (normalize(&variant), canonical))
+            .collect();
+        return Ok(Self { aliases });
+    }
+
+    /// The canonical form of a title, if it's a known variant. Titles are compared
+    /// case-insensitively.
+    pub fn canonical(&self, title: &str) -> Option<&str> {
+        return self.aliases.get(&normalize(title)).map(|t| t.as_str());
+    }
+}
+
+fn normalize(title: &str) -> String {
+    return normalize_whitespace(title).to_lowercase();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aliases_ignore_case_and_spacing() {
+        let aliases = TitleAliases::parse(
+            r#"
+            "Il Gladiatore 2" = "Gladiatore II"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(aliases.canonical("il  gladiatore 2"), Some("Gladiatore II"));
+        assert_eq!(aliases.canonical("Gladiatore II"), None);
+    }
+}
@@ -0,0 +1,96 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Result;
+use fancy_regex::Regex;
+
+use crate::events::Event;
+
+/// A pattern matching event titles. Patterns between slashes are regular
+/// expressions, anything else is matched as a case-insensitive substring.
+enum TitlePattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl TitlePattern {
+    fn parse(pattern: &str) -> Result<Self> {
+        let regex = pattern
+            .strip_prefix('/')
+            .and_then(|p| p.strip_suffix('/'))
+            .filter(|p| !p.is_empty());
+        return match regex {
+            Some(regex) => Ok(Self::Regex(Regex::new(regex)?)),
+            None => Ok(Self::Substring(pattern.to_lowercase())),
+        };
+    }
+
+    fn matches(&self, title: &str) -> bool {
+        match self {
+            Self::Substring(substring) => title.to_lowercase().contains(substring),
+            Self::Regex(regex) => regex.is_match(title).unwrap_or(false),
+        }
+    }
+}
+
+/// Titles to drop from specific venues, for rows that a venue lists but that aren't
+/// actual events, such as a cinema's placeholders for film festivals.
+#[derive(Default)]
+pub struct VenueBlocklist {
+    patterns: HashMap<String, Vec<TitlePattern>>,
+}
+
+impl VenueBlocklist {
+    /// Loads the blocklist from a TOML file mapping each venue's snake_case name to
+    /// a list of patterns. Returns an empty blocklist if the file doesn't exist.
+    pub fn load(filename: &str) -> Result<Self> {
+        if !Path::new(filename).exists() {
+            return Ok(Self::default());
+        }
+        return Self::parse(&fs::read_to_string(filename)?);
+    }
+
+    pub(super) fn parse(content: &str) -> Result<Self> {
+        let table: HashMap<String, Vec<String>> = toml::from_str(content)?;
+        let mut patterns = HashMap::new();
+        for (venue, venue_patterns) in table {
+            let venue_patterns = venue_patterns
+                .iter()
+                .map(|p| TitlePattern::parse(p))
+                .collect::<Result<Vec<TitlePattern>>>()?;
+            patterns.insert(venue, venue_patterns);
+        }
+        return Ok(Self { patterns });
+    }
+
+    /// Whether the event should be dropped from the venue's events.
+    pub fn is_blocked(&self, venue_name: &str, event: &Event) -> bool {
+        return self
+            .patterns
+            .get(venue_name)
+            .is_some_and(|patterns| patterns.iter().any(|p| p.matches(&event.title)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_patterns_only_apply_to_their_venue() {
+        let blocklist = VenueBlocklist::parse(
+            r#"
+            triestecinema = ["rassegna", "/^Evento speciale/"]
+            "#,
+        )
+        .unwrap();
+        let event = |title| Event::new(title, HashSet::new(), "Film");
+
+        assert!(blocklist.is_blocked("triestecinema", &event("Rassegna di Primavera")));
+        assert!(blocklist.is_blocked("triestecinema", &event("Evento speciale")));
+        assert!(!blocklist.is_blocked("triestecinema", &event("Un evento speciale")));
+        assert!(!blocklist.is_blocked("triestecinema", &event("Dune")));
+        assert!(!blocklist.is_blocked("the_space", &event("Rassegna di Primavera")));
+    }
+}
@@ -1,5 +1,5 @@
 mod the_space;
-mod triestecinema;
+pub(super) mod triestecinema;
 
 use std::collections::{HashMap, HashSet};
 
@@ -8,20 +8,70 @@ use fancy_regex::Regex;
 use lazy_static::lazy_static;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use tracing::error;
 
-use crate::{dates::DateRange, events::Event, venues::CacheManager};
+use crate::{
+    dates::DateRange,
+    events::{Event, Location, merge_dates, merge_locations},
+    venues::{CacheManager, ScrapeSettings, VenueEvents, aliases::TitleAliases},
+};
 
 lazy_static! {
-    static ref UPPERCASE_MATCHER: Regex = Regex::new(r"^[a-z]*([^a-z]+)\b").unwrap();
     static ref ORIGINAL_LANG: Regex = Regex::new(r"(?i)In [\w\d ]+ Con S\.+t\.+ Italiani").unwrap();
     static ref ORIGINAL_LANG_2: Regex = Regex::new(r"(?i)(: )?lingua originale").unwrap();
     static ref HYPHENS: Regex = Regex::new(r" *\- +").unwrap();
     static ref PERIODS: Regex = Regex::new(r"(\b| +)\. +").unwrap();
     static ref SPACE_NUKE: Regex = Regex::new(r"(\s){2,}").unwrap();
     static ref PUNCTUATION_NUKE: Regex = Regex::new(r"[.,;:]").unwrap();
-    static ref SUBTITLE_STRIPPER: Regex = Regex::new(r":\s+.*$").unwrap();
+    static ref SUBTITLE_STRIPPER: Regex = Regex::new(r"(:|\s[-–])\s+.*$").unwrap();
 }
 
+/// A cinema of the TriesteCinema circuit, which lists several of them on one page.
+pub(super) struct KnownCinema {
+    /// The name of the cinema, as in the headers of the listing.
+    name: &'static str,
+    address: &'static str,
+    /// The website of the cinema, linked instead of the page of the film if set.
+    url: Option<&'static str>,
+}
+
+/// The cinemas listed by TriesteCinema whose address is known.
+const KNOWN_CINEMAS: &[KnownCinema] = &[
+    KnownCinema {
+        name: "Ariston",
+        address: "Viale Romolo Gessi 14, Trieste",
+        url: None,
+    },
+    KnownCinema {
+        name: "Nazionale",
+        address: "Viale XX Settembre 30, Trieste",
+        url: None,
+    },
+    KnownCinema {
+        name: "Giotto",
+        address: "Via Giotto 8, Trieste",
+        url: None,
+    },
+];
+
+/// The location of a film screened at `cinema`, linking to the page of the film at
+/// `film_url`. Known cinemas get their address, and their website if they have one,
+/// while the others are just named.
+pub(super) fn cinema_location(cinema: &str, film_url: String) -> Location {
+    let Some(known) = KNOWN_CINEMAS
+        .iter()
+        .find(|c| c.name.eq_ignore_ascii_case(cinema.trim()))
+    else {
+        return Location::new(cinema, Some(film_url));
+    };
+    let url = known.url.map_or(film_url, |url| url.to_string());
+    return Location::new(cinema, Some(url)).with_address(Some(known.address.to_string()));
+}
+
+/// Metadata key of the genres of a film, joined for display (e.g., "Azione, Commedia").
+/// Genres are kept out of the tags, which tell the variants of a film apart.
+pub const GENRE_KEY: &str = "genre";
+
 /// A set of movie [Event]s to handle multiple variants of the same movie. For instance,
 /// a movie could be screened normally, in original language, in 3D, etc. These are different
 /// events, but all the same movie.
@@ -32,10 +82,50 @@ pub(super) struct MovieGroup {
     movies: HashSet<Event>,
 }
 
+impl VenueEvents for Vec<MovieGroup> {
+    fn event_count(&self) -> usize {
+        self.iter().map(|group| group.movies.len()).sum()
+    }
+
+    fn events(&self) -> Vec<Event> {
+        self.iter()
+            .flat_map(|group| group.movies.iter().cloned())
+            .collect()
+    }
+
+    fn retain_events(&mut self, keep: &dyn Fn(&Event) -> bool) {
+        for group in self.iter_mut() {
+            group.movies.retain(|movie| keep(movie));
+        }
+        self.retain(|group| !group.movies.is_empty());
+    }
+
+    fn merge_cached(&mut self, cached: Self) {
+        for cached_group in cached {
+            let Some(group) = self.iter_mut().find(|g| g.title == cached_group.title) else {
+                self.push(cached_group);
+                continue;
+            };
+            for cached_movie in cached_group.movies {
+                match group.movies.take(&cached_movie) {
+                    Some(mut movie) => {
+                        merge_dates(&mut movie, cached_movie);
+                        group.movies.insert(movie);
+                    }
+                    None => drop(group.movies.insert(cached_movie)),
+                }
+            }
+        }
+    }
+}
+
 impl MovieGroup {
     fn add_movie(&mut self, movie: Event) {
         if let Some(mut ext_movie) = self.movies.take(&movie) {
-            ext_movie.locations.extend(movie.locations);
+            merge_locations(&mut ext_movie.locations, movie.locations);
+            for (key, value) in movie.metadata {
+                ext_movie.metadata.entry(key).or_insert(value);
+            }
 
             if let Some(old_tf) = movie.time_frame {
                 if let Some(ext_tf) = ext_movie.time_frame {
@@ -55,25 +145,49 @@ pub async fn fetch(
     client: &Client,
     date_range: &DateRange,
     cache_manager: &mut CacheManager,
+    settings: &ScrapeSettings,
 ) -> Result<Vec<Event>> {
     cache_manager.set_category("cinema");
     let triestecinema = cache_manager
-        .get_or_fetch("triestecinema", async || {
-            triestecinema::fetch(client, date_range).await
+        .get_or_fetch("triestecinema", async |max_events| {
+            let significant_subtitles = Cinema::TriesteCinema.significant_subtitles(settings);
+            triestecinema::fetch(client, date_range, max_events, significant_subtitles).await
         })
-        .await?
+        .await
+        .inspect_err(|e| error!("Failed to fetch TriesteCinema: {e}"))
+        .ok()
+        .flatten()
         .unwrap_or_else(Vec::new);
 
     let the_space = cache_manager
-        .get_or_fetch("the_space", async || the_space::fetch(date_range).await)
-        .await?
+        .get_or_fetch("the_space", async |_| {
+            let significant_subtitles = Cinema::TheSpace.significant_subtitles(settings);
+            the_space::fetch(date_range, significant_subtitles).await
+        })
+        .await
+        .inspect_err(|e| error!("Failed to fetch The Space: {e}"))
+        .ok()
+        .flatten()
         .unwrap_or_else(Vec::new);
 
+    return Ok(combine_groups(
+        [triestecinema, the_space],
+        &settings.aliases,
+    ));
+}
+
+/// Combines the groups of the same movie from all cinemas, sorted by title with the
+/// base variant of each movie first. Titles with an alias are renamed beforehand.
+fn combine_groups<const N: usize>(
+    cinemas: [Vec<MovieGroup>; N],
+    aliases: &TitleAliases,
+) -> Vec<Event> {
     // Combine identical movies in a single list
     let mut movie_groups: HashMap<String, MovieGroup> = HashMap::new();
 
-    for groups in [triestecinema, the_space] {
+    for groups in cinemas {
         for group in groups {
+            let group = apply_alias(group, aliases);
             movie_groups
                 .entry(group.title.clone())
                 .and_modify(|ext_group| {
@@ -103,8 +217,26 @@ pub async fn fetch(
     // Order groups alphabetically
     movies_by_group.sort_by(|a, b| a[0].title.cmp(&b[0].title));
 
-    let movies: Vec<Event> = movies_by_group.into_iter().flatten().collect();
-    return Ok(movies);
+    return movies_by_group.into_iter().flatten().collect();
+}
+
+/// Renames a group and its variants to the canonical title, if its title is an alias.
+fn apply_alias(group: MovieGroup, aliases: &TitleAliases) -> MovieGroup {
+    let Some(canonical) = aliases.canonical(&group.title) else {
+        return group;
+    };
+    let title = canonical.to_lowercase();
+    let mut renamed = MovieGroup {
+        title: title.clone(),
+        description: group.description,
+        movies: HashSet::new(),
+    };
+    for mut movie in group.movies {
+        movie.id = make_id(&title, &movie.tags);
+        movie.title = canonical.to_string();
+        renamed.add_movie(movie);
+    }
+    return renamed;
 }
 
 pub(super) enum Cinema {
@@ -112,19 +244,37 @@ pub(super) enum Cinema {
     TheSpace,
 }
 
-pub(super) fn clean_title(title: &str, cinema: Cinema) -> (String, String, HashSet<String>) {
+impl Cinema {
+    /// The name of the cinema as a venue, as in [crate::venues::VENUES].
+    fn venue_name(&self) -> &'static str {
+        match self {
+            Cinema::TriesteCinema => "triestecinema",
+            Cinema::TheSpace => "the_space",
+        }
+    }
+
+    /// Whether the part of a title after the colon tells apart different films, as in
+    /// series (e.g., "Rassegna X: Film 1" and "Rassegna X: Film 2"), rather than being
+    /// a subtitle that can be dropped to match the same film across cinemas.
+    fn significant_subtitles(&self, settings: &ScrapeSettings) -> bool {
+        return settings.significant_subtitles.contains(self.venue_name());
+    }
+}
+
+/// Cleans up the title of a film, returning it along with the base title its
+/// variants are grouped by and its tags. The base title keeps the subtitle only if
+/// `significant_subtitles`, see [Cinema::significant_subtitles].
+pub(super) fn clean_title(
+    title: &str,
+    cinema: Cinema,
+    significant_subtitles: bool,
+) -> (String, String, HashSet<String>) {
     let mut new_title = title.to_string();
 
     // Annoyances
     match cinema {
         Cinema::TriesteCinema => {
             new_title = new_title.replace("/", "").replace("4K", "");
-            new_title = UPPERCASE_MATCHER
-                .captures(&new_title)
-                .ok()
-                .flatten()
-                .map(|m| m.get(1).unwrap().as_str().to_string())
-                .unwrap_or(new_title);
         }
         Cinema::TheSpace => {}
     }
@@ -162,14 +312,20 @@ pub(super) fn clean_title(title: &str, cinema: Cinema) -> (String, String, HashS
     };
     new_title = extract_re(&new_title, &ORIGINAL_LANG, "Originale");
 
-    // Base title without subtitle
-    let base_title = SUBTITLE_STRIPPER.replace_all(&new_title, "");
+    let base_title = base_title(&new_title, significant_subtitles);
+
+    return (new_title.trim().to_string(), base_title, tags);
+}
+
+/// The title used to group variants of the same film. This is the title without its
+/// subtitle, unless subtitles are significant, in which case the whole title is kept
+/// so that films that only differ after the colon are not merged.
+fn base_title(title: &str, significant_subtitles: bool) -> String {
+    if significant_subtitles {
+        return title.trim().to_string();
+    }
 
-    return (
-        new_title.trim().to_string(),
-        base_title.trim().to_string(),
-        tags,
-    );
+    return SUBTITLE_STRIPPER.replace_all(title, "").trim().to_string();
 }
 
 /// Make an identifier that's inclusive of tags to differentiate the same movie
@@ -195,3 +351,163 @@ pub(super) fn make_id(base_title: &str, tags: &HashSet<String>) -> String {
 
     return id;
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::dates::{DateSet, TimeFrame};
+
+    #[test]
+    fn test_known_cinemas_have_an_address() {
+        let film_url = "https://www.triestecinema.it/film.php?id=1".to_string();
+
+        let ariston = cinema_location("ARISTON", film_url.clone());
+        assert_eq!(ariston.name, "ARISTON");
+        assert_eq!(ariston.url.as_ref(), Some(&film_url));
+        assert_eq!(
+            ariston.address.as_deref(),
+            Some("Viale Romolo Gessi 14, Trieste")
+        );
+
+        let unknown = cinema_location("Cinema Nuovo", film_url.clone());
+        assert_eq!(unknown.url, Some(film_url));
+        assert_eq!(unknown.address, None);
+    }
+
+    #[test]
+    fn test_triestecinema_titles_are_kept_whole() {
+        let clean = |title: &str| clean_title(title, Cinema::TriesteCinema, false).0;
+
+        assert_eq!(clean("DUNE PARTE DUE"), "dune parte due");
+        assert_eq!(clean("Dune Parte Due"), "dune parte due");
+        assert_eq!(clean("F1 IL FILM"), "f1 il film");
+        assert_eq!(clean("F1 Il film"), "f1 il film");
+        assert_eq!(clean("AVATAR 3 4K"), "avatar 3");
+        assert_eq!(clean("Anche al Nazionale"), "anche al nazionale");
+
+        let (title, base_title, tags) =
+            clean_title("DUNE PARTE DUE in 3D", Cinema::TriesteCinema, false);
+        assert_eq!(title, "dune parte due");
+        assert_eq!(base_title, "dune parte due");
+        assert!(tags.contains("3D"));
+    }
+
+    #[test]
+    fn test_subtitles_are_stripped_by_default() {
+        let part_1 = base_title("rassegna x: part 1", false);
+        let part_2 = base_title("rassegna x: part 2", false);
+
+        assert_eq!(part_1, "rassegna x");
+        assert_eq!(part_1, part_2);
+    }
+
+    #[test]
+    fn test_significant_subtitles_are_not_merged() {
+        let part_1 = base_title("rassegna x: part 1", true);
+        let part_2 = base_title("rassegna x: part 2", true);
+
+        assert_eq!(part_1, "rassegna x: part 1");
+        assert_ne!(
+            make_id(&part_1, &HashSet::new()),
+            make_id(&part_2, &HashSet::new())
+        );
+    }
+
+    #[test]
+    fn test_subtitles_after_dashes_are_stripped() {
+        assert_eq!(base_title("rassegna x - part 1", false), "rassegna x");
+        assert_eq!(base_title("rassegna x – part 1", false), "rassegna x");
+        assert_eq!(base_title("spider-man: no way home", false), "spider-man");
+    }
+
+    #[test]
+    fn test_subtitles_through_clean_title() {
+        let base =
+            |title: &str, significant| clean_title(title, Cinema::TriesteCinema, significant).1;
+
+        // Subtitles are dropped by default, so variants of the same film are grouped
+        assert_eq!(base("Dune – Parte Due", false), "dune");
+        assert_eq!(base("Base: Part 1", false), base("Base: Part 2", false));
+        // When they're significant, the films of a rassegna stay apart
+        assert_eq!(base("Rassegna X - Film 1", true), "rassegna x: film 1");
+        assert_ne!(
+            base("Rassegna X - Film 1", true),
+            base("Rassegna X - Film 2", true)
+        );
+    }
+
+    #[test]
+    fn test_subtitled_film_grouped_across_cinemas_by_default() {
+        let group = |title: &str, cinema: Cinema, location: &str| {
+            let significant = cinema.significant_subtitles(&ScrapeSettings::default());
+            let (title, base_title, tags) = clean_title(title, cinema, significant);
+            let movie = Event::new(
+                &title,
+                HashSet::from([Location::new(location, None)]),
+                "Film",
+            )
+            .with_id(make_id(&base_title, &tags));
+            MovieGroup {
+                title: base_title,
+                description: None,
+                movies: HashSet::from([movie]),
+            }
+        };
+
+        let movies = combine_groups(
+            [
+                vec![group("DUNE: PARTE DUE", Cinema::TriesteCinema, "Ariston")],
+                vec![group("Dune", Cinema::TheSpace, "The Space")],
+            ],
+            &TitleAliases::default(),
+        );
+
+        assert_eq!(movies.len(), 1);
+        assert_eq!(movies[0].locations.len(), 2);
+    }
+
+    #[test]
+    fn test_aliased_titles_are_grouped() {
+        let group = |title: &str, cinema: &str, day: u32| {
+            let (title, base_title, tags) = clean_title(title, Cinema::TheSpace, false);
+            let date = NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+            let movie = Event::new(&title, HashSet::from([Location::new(cinema, None)]), "Film")
+                .with_id(make_id(&base_title, &tags))
+                .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date]).unwrap())));
+            MovieGroup {
+                title: base_title,
+                description: None,
+                movies: HashSet::from([movie]),
+            }
+        };
+        let aliases = TitleAliases::parse(r#""Il Gladiatore 2" = "Gladiatore II""#).unwrap();
+
+        let movies = combine_groups(
+            [
+                vec![group("IL GLADIATORE 2", "Ariston", 12)],
+                vec![group("Gladiatore II", "The Space", 13)],
+            ],
+            &aliases,
+        );
+
+        assert_eq!(movies.len(), 1);
+        assert_eq!(movies[0].title, "Gladiatore II");
+        assert_eq!(movies[0].locations.len(), 2);
+        match &movies[0].time_frame {
+            Some(TimeFrame::Dates(set)) => assert_eq!(set.dates().len(), 2),
+            other => panic!("unexpected time frame {other:?}"),
+        }
+
+        // Without the alias they're two different films
+        let movies = combine_groups(
+            [
+                vec![group("IL GLADIATORE 2", "Ariston", 12)],
+                vec![group("Gladiatore II", "The Space", 13)],
+            ],
+            &TitleAliases::default(),
+        );
+        assert_eq!(movies.len(), 2);
+    }
+}
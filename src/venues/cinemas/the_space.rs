@@ -3,12 +3,14 @@ use std::{
     time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
+use chrono::NaiveDate;
 use convert_case::Case;
 use headless_chrome::{Browser, LaunchOptions};
 use indicatif::{ProgressBar, ProgressStyle};
 use scraper::{Html, Selector};
 use serde_json::Value;
+use tracing::warn;
 
 use crate::{
     dates::{DateRange, DateSet, TimeFrame},
@@ -16,11 +18,12 @@ use crate::{
     utils::PROGRESS_BAR_TEMPLATE,
     venues::{
         CATEGORY_MOVIES, StandardCasing,
-        cinemas::{Cinema, MovieGroup},
+        cinemas::{Cinema, GENRE_KEY, MovieGroup},
+        request_pause,
     },
 };
 
-pub async fn fetch(date_range: &DateRange) -> Result<Vec<MovieGroup>> {
+pub async fn fetch(date_range: &DateRange, significant_subtitles: bool) -> Result<Vec<MovieGroup>> {
     // The Space's website is a Next.js app and contains absolutely zero functional
     // HTML without JavaScript. It does contain a JSON object that contains a bunch of content,
     // but only a few movies. Thankfully, the movies are taken from an server API route that
@@ -33,82 +36,45 @@ pub async fn fetch(date_range: &DateRange) -> Result<Vec<MovieGroup>> {
     // We need a proper browser here because the API function isn't really meant to be
     // accessed from code, so it seems to check for fresh session cookies
     let browser =
-        headless_chrome::Browser::new(LaunchOptions::default_builder().path(None).build().unwrap())
-            .unwrap();
+        headless_chrome::Browser::new(LaunchOptions::default_builder().path(None).build()?)?;
 
     let mut movie_groups: HashMap<String, MovieGroup> = HashMap::new();
     for day in date_range.iter_days() {
-        let url = format!(
-            "https://www.thespacecinema.it/api/microservice/showings/cinemas/1011/films?showingDate={}&minEmbargoLevel=3&includesSession=true&includeSessionAttributes=true",
-            day.format("%Y-%m-%dT00:00:00")
-        );
+        let url = films_url(day);
 
-        let mut listings: Vec<Value> = Vec::new();
+        let mut json = Value::Null;
         let mut attempt = 1;
         while attempt <= 3 {
             match call_api(&browser, &url).await {
-                Ok(json) => {
-                    listings = json["result"].as_array().unwrap().to_vec();
+                Ok(value) => {
+                    json = value;
                     break;
                 }
                 Err(e) => {
-                    eprintln!("Error: {e}. Attempt: {attempt} of 3. Retrying in 5 seconds...");
+                    warn!("Error: {e}. Attempt: {attempt} of 3. Retrying in 5 seconds...");
                     attempt += 1;
                     tokio::time::sleep(Duration::from_secs(5)).await;
                 }
             }
         }
 
+        let listings = parse_listings(&json, day, significant_subtitles);
         progress.inc_length(listings.len() as u64);
 
-        for listing in listings.iter() {
-            let title = listing["filmTitle"].as_str().unwrap();
-            let (title, base_title, _) = super::clean_title(title, Cinema::TheSpace);
-            let description = listing["synopsisShort"].as_str().unwrap();
-
-            // To determine the tags, we need to look at the individual movie showings for the day
-            // The showings are put in showingGroups. Each group is a day's worth of movies. Since
-            // we're using the per-day API there's always one and only one. Each group contains the
-            // showings in the sessions array. Each session has attributes that explain what variant
-            // it is. We create a separate event for each distinct variant.
-            let sessions = listing["showingGroups"].as_array().unwrap()[0]["sessions"]
-                .as_array()
-                .unwrap();
-            for session in sessions {
-                let mut tags = HashSet::new();
-                for attr in session["attributes"].as_array().unwrap() {
-                    match attr["name"].as_str().unwrap() {
-                        "3D" => drop(tags.insert("3D".to_string())),
-                        "LINGUA ORIGINALE" => drop(tags.insert("Originale".to_string())),
-                        _ => {}
-                    }
-                }
-
-                let id = super::make_id(&base_title, &tags);
-                let dates = DateSet::new(vec![day]).unwrap();
-                let location = Location::new(
-                    "The Space",
-                    listing["filmUrl"].as_str().map(|s| s.to_string()),
-                );
-                let movie = Event::new(
-                    &title.standardize_case(Some(Case::Sentence)),
-                    HashSet::from_iter([location]),
-                    CATEGORY_MOVIES,
-                )
-                .with_id(id)
-                .with_tags(tags)
-                .with_time_frame(Some(TimeFrame::Dates(dates)));
-
+        for listing in listings {
+            for movie in listing.movies {
                 movie_groups
-                    .entry(base_title.clone())
+                    .entry(listing.base_title.clone())
                     .and_modify(|group| {
                         group.add_movie(movie.clone());
                         // Prioritize The Space descriptions
-                        group.description = Some(description.to_string());
+                        if listing.description.is_some() {
+                            group.description = listing.description.clone();
+                        }
                     })
                     .or_insert_with(|| MovieGroup {
-                        title: base_title.clone(),
-                        description: Some(description.to_string()),
+                        title: listing.base_title.clone(),
+                        description: listing.description.clone(),
                         movies: HashSet::from([movie]),
                     });
             }
@@ -117,7 +83,7 @@ pub async fn fetch(date_range: &DateRange) -> Result<Vec<MovieGroup>> {
         }
 
         // Await to not send too many requests too fast
-        tokio::time::sleep(Duration::from_millis(20)).await;
+        request_pause("the_space").await;
     }
 
     progress.finish();
@@ -125,17 +91,138 @@ pub async fn fetch(date_range: &DateRange) -> Result<Vec<MovieGroup>> {
     return Ok(movie_groups.into_values().collect());
 }
 
+/// Builds the URL of the API endpoint listing the films showing on a given day.
+/// The generic films endpoint has no per-day info, so we query each day separately.
+fn films_url(day: NaiveDate) -> String {
+    format!(
+        "https://www.thespacecinema.it/api/microservice/showings/cinemas/1011/films?showingDate={}&minEmbargoLevel=3&includesSession=true&includeSessionAttributes=true",
+        day.format("%Y-%m-%dT00:00:00")
+    )
+}
+
+/// A film from The Space's API, with one [Event] for each variant screened on the day.
+struct Listing {
+    base_title: String,
+    description: Option<String>,
+    movies: Vec<Event>,
+}
+
+/// Parse the per-day API response into a list of films. Malformed listings are
+/// skipped rather than aborting the whole day, since the API schema is not ours.
+/// Films are grouped by their full title if `significant_subtitles`.
+fn parse_listings(json: &Value, day: NaiveDate, significant_subtitles: bool) -> Vec<Listing> {
+    let Some(results) = json["result"].as_array() else {
+        warn!("The Space returned no listings for {day}");
+        return Vec::new();
+    };
+
+    let listings: Vec<Listing> = results
+        .iter()
+        .filter_map(|listing| parse_listing(listing, day, significant_subtitles))
+        .collect();
+
+    let skipped = results.len() - listings.len();
+    if skipped > 0 {
+        warn!("Skipped {skipped} malformed listings from The Space for {day}");
+    }
+
+    return listings;
+}
+
+fn parse_listing(listing: &Value, day: NaiveDate, significant_subtitles: bool) -> Option<Listing> {
+    let title = listing["filmTitle"].as_str()?;
+    let (title, base_title, _) = super::clean_title(title, Cinema::TheSpace, significant_subtitles);
+    let description = listing["synopsisShort"].as_str().map(|s| s.to_string());
+    let metadata: HashMap<String, String> = parse_genres(listing)
+        .map(|genres| (GENRE_KEY.to_string(), genres))
+        .into_iter()
+        .collect();
+
+    // To determine the tags, we need to look at the individual movie showings for the day
+    // The showings are put in showingGroups. Each group is a day's worth of movies. Since
+    // we're using the per-day API there's always one and only one. Each group contains the
+    // showings in the sessions array. Each session has attributes that explain what variant
+    // it is. We create a separate event for each distinct variant. Films without
+    // sessions, such as previews, get a single event for the plain 2D variant.
+    let sessions = listing["showingGroups"]
+        .as_array()
+        .and_then(|groups| groups.first())
+        .and_then(|group| group["sessions"].as_array());
+    let variants: Vec<HashSet<String>> = match sessions {
+        Some(sessions) => sessions.iter().map(session_tags).collect(),
+        None => vec![HashSet::new()],
+    };
+
+    let mut movies = Vec::new();
+    for tags in variants {
+        let id = super::make_id(&base_title, &tags);
+        let dates = DateSet::new(vec![day]).unwrap();
+        let location = Location::new(
+            "The Space",
+            listing["filmUrl"].as_str().map(|s| s.to_string()),
+        );
+        let movie = Event::new(
+            &title.standardize_case(Some(Case::Sentence)),
+            HashSet::from_iter([location]),
+            CATEGORY_MOVIES,
+        )
+        .with_id(id)
+        .with_tags(tags)
+        .with_time_frame(Some(TimeFrame::Dates(dates)))
+        .with_metadata(metadata.clone());
+
+        movies.push(movie);
+    }
+
+    return Some(Listing {
+        base_title,
+        description,
+        movies,
+    });
+}
+
+/// The tags of the variant screened in a session, from its attributes. Sessions
+/// without attributes are of the plain 2D variant, which has no tags.
+fn session_tags(session: &Value) -> HashSet<String> {
+    let mut tags = HashSet::new();
+    let attributes = session["attributes"].as_array().map(Vec::as_slice);
+    for attr in attributes.unwrap_or_default() {
+        match attr["name"].as_str() {
+            Some("3D") => drop(tags.insert("3D".to_string())),
+            Some("LINGUA ORIGINALE") => drop(tags.insert("Originale".to_string())),
+            _ => {}
+        }
+    }
+    return tags;
+}
+
+/// Joins the genres of a film, which the API lists either as names or as objects
+/// with a name. Returns `None` if the film has none.
+fn parse_genres(listing: &Value) -> Option<String> {
+    let genres: Vec<&str> = listing["genres"]
+        .as_array()?
+        .iter()
+        .filter_map(|genre| genre.as_str().or_else(|| genre["name"].as_str()))
+        .map(str::trim)
+        .filter(|genre| !genre.is_empty())
+        .collect();
+    if genres.is_empty() {
+        return None;
+    }
+    return Some(genres.join(", "));
+}
+
 async fn call_api(browser: &Browser, url: &str) -> Result<Value> {
     // Navigate to the proper page to create session cookies
     let main_page = "https://www.thespacecinema.it/cinema/trieste/al-cinema";
-    let tab = browser.new_tab().unwrap();
-    tab.navigate_to(main_page).unwrap();
-    tab.wait_until_navigated().unwrap();
+    let tab = browser.new_tab()?;
+    tab.navigate_to(main_page)?;
+    tab.wait_until_navigated()?;
 
     // Call the API URL
-    tab.navigate_to(url).unwrap();
-    tab.wait_until_navigated().unwrap();
-    let content = tab.get_content().unwrap();
+    tab.navigate_to(url)?;
+    tab.wait_until_navigated()?;
+    let content = tab.get_content()?;
 
     // Extract the JSON from the response
     let doc = Html::parse_document(&content);
@@ -143,9 +230,149 @@ async fn call_api(browser: &Browser, url: &str) -> Result<Value> {
         .select(&Selector::parse("pre").unwrap())
         .next()
         .and_then(|el| el.text().next())
-        .unwrap();
+        .ok_or_else(|| anyhow!("No JSON found in API response"))?;
 
     let value = serde_json::from_str(json)?;
 
-    return Ok(value);
+    return check_response(value);
+}
+
+/// Rejects answers without listings, so that they're retried. The API sometimes
+/// answers with a 401 error object even with fresh session cookies, then works on
+/// the next try.
+fn check_response(json: Value) -> Result<Value> {
+    if !json["result"].is_array() {
+        let error = json["error"].as_str().unwrap_or("no listings");
+        bail!("The Space API answered without listings: {error}");
+    }
+    return Ok(json);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed_listing_is_skipped() {
+        let json: Value = serde_json::from_str(
+            r#"{
+                "result": [
+                    {
+                        "filmTitle": "Dune",
+                        "synopsisShort": "Un film nel deserto.",
+                        "filmUrl": "https://www.thespacecinema.it/film/dune",
+                        "showingGroups": [
+                            { "sessions": [
+                                { "attributes": [] },
+                                { "attributes": [{ "name": "3D" }] }
+                            ] }
+                        ]
+                    },
+                    { "synopsisShort": "Manca il titolo", "showingGroups": [] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+
+        let listings = parse_listings(&json, day, false);
+
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].base_title, "dune");
+        assert_eq!(listings[0].movies.len(), 2);
+        assert_eq!(
+            listings[0].description.as_deref(),
+            Some("Un film nel deserto.")
+        );
+    }
+
+    #[test]
+    fn test_listing_without_sessions_is_plain_2d() {
+        let json: Value = serde_json::from_str(
+            r#"{
+                "result": [
+                    { "filmTitle": "Anteprima" },
+                    { "filmTitle": "Dune", "showingGroups": [{ "sessions": [{}] }] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+
+        let listings = parse_listings(&json, day, false);
+
+        assert_eq!(listings.len(), 2);
+        for listing in &listings {
+            assert_eq!(listing.description, None);
+            assert_eq!(listing.movies.len(), 1);
+            assert!(listing.movies[0].tags.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_genres_are_metadata_not_tags() {
+        let json: Value = serde_json::from_str(
+            r#"{
+                "result": [
+                    {
+                        "filmTitle": "Dune",
+                        "genres": [{ "name": "Azione" }, "Fantascienza", { "id": 3 }],
+                        "showingGroups": [
+                            { "sessions": [
+                                { "attributes": [] },
+                                { "attributes": [{ "name": "3D" }] }
+                            ] }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+
+        let listings = parse_listings(&json, day, false);
+
+        let mut ids: Vec<&str> = listings[0].movies.iter().map(|m| m.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["dune", "dune_3d"]);
+        for movie in &listings[0].movies {
+            assert_eq!(movie.metadata[GENRE_KEY], "Azione, Fantascienza");
+            assert!(!movie.tags.contains("Azione"));
+        }
+    }
+
+    #[test]
+    fn test_films_url_per_day() {
+        let range = DateRange::new(
+            NaiveDate::from_ymd_opt(2026, 1, 31).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 2, 1).unwrap(),
+        );
+
+        let urls: Vec<String> = range.iter_days().map(films_url).collect();
+
+        assert_eq!(urls.len(), 2);
+        assert!(urls[0].contains("showingDate=2026-01-31T00:00:00&"));
+        assert!(urls[1].contains("showingDate=2026-02-01T00:00:00&"));
+        assert!(urls[0].starts_with(
+            "https://www.thespacecinema.it/api/microservice/showings/cinemas/1011/films?"
+        ));
+    }
+
+    #[test]
+    fn test_unauthorized_response_is_retried() {
+        let unauthorized: Value = serde_json::from_str(r#"{ "error": "unauthorized" }"#).unwrap();
+        let listings: Value = serde_json::from_str(r#"{ "result": [] }"#).unwrap();
+
+        let err = check_response(unauthorized).unwrap_err();
+        assert!(err.to_string().contains("unauthorized"));
+        assert!(check_response(listings).is_ok());
+    }
+
+    #[test]
+    fn test_missing_result_array() {
+        let json: Value = serde_json::from_str(r#"{ "error": "unauthorized" }"#).unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+
+        assert!(parse_listings(&json, day, false).is_empty());
+    }
 }
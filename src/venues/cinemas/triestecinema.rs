@@ -1,69 +1,83 @@
-use std::{
-    collections::{HashMap, HashSet},
-    time::Duration,
-};
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Result;
+use chrono::NaiveDate;
 use convert_case::Case;
 use indicatif::{ProgressBar, ProgressStyle};
 use reqwest::Client;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
+use tracing::warn;
 
 use crate::{
     dates::{DateRange, DateSet, TimeFrame},
-    events::{Event, Location},
+    events::Event,
     utils::PROGRESS_BAR_TEMPLATE,
     venues::{
-        CATEGORY_MOVIES, StandardCasing,
+        CATEGORY_MOVIES, StandardCasing, check_match_count,
         cinemas::{Cinema, MovieGroup, SPACE_NUKE},
+        fetch_document, request_pause,
     },
 };
 
-pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<MovieGroup>> {
+/// How many days ahead TriesteCinema publishes its schedule. Pages further ahead
+/// are empty or repeat the last published day, so they're not worth requesting.
+const MAX_LOOKAHEAD_DAYS: usize = 7;
+
+/// The page with the schedule of today. Other days are at a `delta` from it.
+pub(crate) const LISTING_URL: &str = "https://www.triestecinema.it/index.php?pag=orari";
+/// Matches each film in a schedule page.
+pub(crate) const LISTING_SELECTOR: &str = "div.media-body";
+
+pub async fn fetch(
+    client: &Client,
+    date_range: &DateRange,
+    max_events: usize,
+    significant_subtitles: bool,
+) -> Result<Vec<MovieGroup>> {
     let progress = ProgressBar::new(0)
         .with_style(ProgressStyle::with_template(PROGRESS_BAR_TEMPLATE).unwrap())
         .with_message("Fetching TriesteCinema");
 
     let mut movie_groups: HashMap<String, MovieGroup> = HashMap::new();
+    // The page of a film to retry, by the base title of the films whose page couldn't be fetched
+    let mut failed: HashMap<String, String> = HashMap::new();
 
-    let movie_list_sel = Selector::parse("div.media-body").unwrap();
+    let movie_list_sel = Selector::parse(LISTING_SELECTOR).unwrap();
     let cinema_sel = Selector::parse("h3.media-heading").unwrap();
     let title_sel = Selector::parse("a.oggi").unwrap();
 
     // Fetch movies from TriesteCinema for each request day
-    for curr_date in date_range.iter_days() {
-        let delta = (curr_date - date_range.start).num_days();
-        let cinema_url = format!("https://www.triestecinema.it/index.php?pag=orari&delta={delta}");
-        let html_body = client
-            .get(cinema_url)
-            .send()
-            .await
-            .inspect_err(|e| println!("GET request failed: {e}"))?
-            .text()
-            .await?;
-
-        let document = Html::parse_document(&html_body);
+    let today = chrono::Local::now().date_naive();
+    for (curr_date, cinema_url) in schedule_urls(date_range, today) {
+        let document = fetch_document(client, &cinema_url).await?;
         let movie_count = document
             .select(&movie_list_sel)
             .fold(0, |acc, list| acc + list.select(&title_sel).count());
+        // Each page has the films of a single day, so one with more than a venue may
+        // return is broken, and none of its films are worth fetching
+        check_match_count(&cinema_url, movie_count, max_events)?;
         progress.inc_length(movie_count as u64);
 
         for movie_list in document.select(&movie_list_sel) {
             // All text here is in UPPERCASE
-            let cinema = movie_list
+            let Some(cinema) = movie_list
                 .select(&cinema_sel)
                 .next()
                 .and_then(|e| e.text().next())
                 .map(|s| s.trim().standardize_case(Some(Case::Upper)))
-                .expect("Missing cinema header");
+            else {
+                warn!("Skipping TriesteCinema movie list without a cinema header");
+                continue;
+            };
 
             let links: Vec<(&str, &str)> = movie_list
                 .select(&title_sel)
-                .map(|a| (a.text().next().unwrap(), a.attr("href").unwrap()))
+                .filter_map(|a| Some((a.text().next()?, a.attr("href")?)))
                 .collect();
 
             for (title, href) in links {
-                let (title, base_title, tags) = super::clean_title(title, Cinema::TriesteCinema);
+                let (title, base_title, tags) =
+                    super::clean_title(title, Cinema::TriesteCinema, significant_subtitles);
                 if title.starts_with("anche al") {
                     continue;
                 }
@@ -79,13 +93,21 @@ pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<MovieG
                 {
                     description = None;
                 } else {
-                    description = get_description(client, &movie_url).await?;
+                    description = match get_description(client, &movie_url).await {
+                        Ok(description) => description,
+                        Err(_) => {
+                            failed
+                                .entry(base_title.clone())
+                                .or_insert(movie_url.clone());
+                            None
+                        }
+                    };
                     // Await to not send too many requests too fast
-                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    request_pause("triestecinema").await;
                 }
 
                 let dates = DateSet::new(vec![curr_date]).unwrap();
-                let location = Location::new(&cinema, Some(movie_url));
+                let location = super::cinema_location(&cinema, movie_url);
                 let movie = Event::new(
                     &title.standardize_case(Some(Case::Upper)),
                     HashSet::from_iter([location]),
@@ -119,48 +141,190 @@ pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<MovieG
 
     progress.finish();
 
+    // Retry the films whose page couldn't be fetched, unless another variant had a description
+    for (base_title, movie_url) in failed {
+        let Some(group) = movie_groups.get_mut(&base_title) else {
+            continue;
+        };
+        if group.description.is_some() {
+            continue;
+        }
+        match get_description(client, &movie_url).await {
+            Ok(description) => group.description = description,
+            Err(e) => warn!("Failed to get description of {base_title}: {e}"),
+        }
+    }
+
     return Ok(movie_groups.into_values().collect());
 }
 
-async fn get_description(client: &Client, url: &str) -> Result<Option<String>> {
-    let desc_sel = Selector::parse("div.col-md-5.wow.fadeIn").unwrap();
-
-    let movie_page = client.get(url).send().await?.text().await?;
-    let desc_doc = Html::parse_document(&movie_page);
-    let description_el = desc_doc.select(&desc_sel).skip(1).next().unwrap();
-
-    // The description page layout is incredibly inconsistent and sometimes does not have
-    // a description. As a heuristic, the page has a description if it has at least 6 HTML
-    // elements in the selector, in which case the description is inside the element with the
-    // longest text content
-    if description_el.child_elements().count() < 6 {
-        return Ok(None);
+/// Tunable parameters of the heuristic used to find the description in a movie page.
+///
+/// The description page layout is incredibly inconsistent and sometimes does not have
+/// a description. The page is assumed to have one if the description container has
+/// enough child elements, in which case the description is the child with the best score.
+struct DescriptionHeuristic {
+    /// Containers with fewer child elements than this have no description.
+    min_children: usize,
+    /// Leading child elements that hold metadata (director, cast, ...) and are never
+    /// the description.
+    skip_children: usize,
+    /// Candidates with less text than this are probably not the description.
+    min_length: usize,
+    /// Score candidates only by the text inside paragraphs, rather than all their text.
+    prefer_paragraphs: bool,
+}
+
+const DESCRIPTION_HEURISTIC: DescriptionHeuristic = DescriptionHeuristic {
+    min_children: 6,
+    skip_children: 5,
+    min_length: 50,
+    prefer_paragraphs: false,
+};
+
+impl DescriptionHeuristic {
+    fn score(&self, el: &ElementRef) -> usize {
+        if self.prefer_paragraphs && el.value().name() != "p" {
+            let p_sel = Selector::parse("p").unwrap();
+            return el
+                .select(&p_sel)
+                .map(|p| p.text().map(str::len).sum::<usize>())
+                .sum();
+        }
+
+        el.text().map(str::len).sum()
     }
 
-    let description = description_el
-        .child_elements()
-        .skip(5) // Skip the first 5
-        .max_by(|el1, el2| {
-            // Find the element with the most text
-            let size1 = el1.text().fold(0, |acc, t| acc + t.len());
-            let size2 = el2.text().fold(0, |acc, t| acc + t.len());
-            size1.cmp(&size2)
-        })
-        .and_then(|el| {
-            // Fold it in a string
-            let desc = el
-                .text()
-                .fold(String::new(), |acc, t| format!("{acc}\n{t}"));
-            Some(desc)
+    /// Finds the description in a movie page, if there is one.
+    fn extract(&self, document: &Html) -> Option<String> {
+        let desc_sel = Selector::parse("div.col-md-5.wow.fadeIn").unwrap();
+        let description_el = document.select(&desc_sel).nth(1)?;
+
+        if description_el.child_elements().count() < self.min_children {
+            return None;
+        }
+
+        let description = description_el
+            .child_elements()
+            .skip(self.skip_children)
+            .max_by_key(|el| self.score(el))
+            .map(|el| {
+                // Fold it in a string
+                el.text()
+                    .fold(String::new(), |acc, t| format!("{acc}\n{t}"))
+            })
+            .unwrap_or_default();
+
+        if description.len() < self.min_length {
+            return None;
+        }
+
+        return Some(SPACE_NUKE.replace_all(&description, "$1").trim().into());
+    }
+}
+
+async fn get_description(client: &Client, url: &str) -> Result<Option<String>> {
+    let desc_doc = fetch_document(client, url).await?;
+
+    return Ok(DESCRIPTION_HEURISTIC.extract(&desc_doc));
+}
+
+/// The URLs of the daily schedules within the range that the cinema publishes, from
+/// `today` up to the furthest day it publishes.
+fn schedule_urls(date_range: &DateRange, today: NaiveDate) -> Vec<(NaiveDate, String)> {
+    return date_range
+        .iter_days()
+        .filter_map(|day| {
+            // The site counts the days from today, whose schedule is at delta 0
+            let delta = (day - today).num_days();
+            if delta < 0 || delta >= MAX_LOOKAHEAD_DAYS as i64 {
+                return None;
+            }
+            let url = format!("{LISTING_URL}&delta={delta}");
+            Some((day, url))
         })
-        .unwrap_or_default();
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WITH_DESCRIPTION: &str = r#"
+        <div class="col-md-5 wow fadeIn"><img src="locandina.jpg"></div>
+        <div class="col-md-5 wow fadeIn">
+            <h2>DUNE - PARTE DUE</h2>
+            <p>Regia: Denis Villeneuve</p>
+            <p>Cast: Timothée Chalamet, Zendaya</p>
+            <p>Durata: 166'</p>
+            <p>Genere: Fantascienza</p>
+            <p>Paul Atreides si unisce a Chani e ai Fremen mentre medita vendetta contro i cospiratori che hanno distrutto la sua famiglia.</p>
+            <p>Trailer</p>
+        </div>
+    "#;
 
-    // Drop really short strings as they are probably not the description
-    if description.len() < 50 {
-        return Ok(None);
+    const WITHOUT_DESCRIPTION: &str = r#"
+        <div class="col-md-5 wow fadeIn"><img src="locandina.jpg"></div>
+        <div class="col-md-5 wow fadeIn">
+            <h2>DUNE - PARTE DUE IN 3D</h2>
+            <p>Regia: Denis Villeneuve</p>
+            <p>Durata: 166'</p>
+        </div>
+    "#;
+
+    #[test]
+    fn test_page_with_description() {
+        let document = Html::parse_document(WITH_DESCRIPTION);
+        let description = DESCRIPTION_HEURISTIC.extract(&document).unwrap();
+        assert!(description.starts_with("Paul Atreides si unisce"));
+    }
+
+    #[test]
+    fn test_page_without_description() {
+        let document = Html::parse_document(WITHOUT_DESCRIPTION);
+        assert_eq!(DESCRIPTION_HEURISTIC.extract(&document), None);
     }
 
-    return Ok(Some(
-        SPACE_NUKE.replace_all(&description, "$1").trim().into(),
-    ));
+    #[test]
+    fn test_min_length_is_tunable() {
+        let document = Html::parse_document(WITH_DESCRIPTION);
+        let strict = DescriptionHeuristic {
+            min_length: 500,
+            ..DESCRIPTION_HEURISTIC
+        };
+        assert_eq!(strict.extract(&document), None);
+    }
+
+    #[test]
+    fn test_requests_capped_at_lookahead() {
+        let start = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let short = DateRange::new(start, start + chrono::Days::new(2));
+        let long = DateRange::new(start, start + chrono::Days::new(13));
+
+        assert_eq!(schedule_urls(&short, start).len(), 3);
+        let urls = schedule_urls(&long, start);
+        assert_eq!(urls.len(), MAX_LOOKAHEAD_DAYS);
+        assert!(urls.last().unwrap().1.ends_with("delta=6"));
+    }
+
+    #[test]
+    fn test_deltas_count_from_today() {
+        let today = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        // From two days ago to ten days ahead
+        let window = DateRange::new(today - chrono::Days::new(2), today + chrono::Days::new(10));
+        let urls = schedule_urls(&window, today);
+
+        assert_eq!(urls.len(), MAX_LOOKAHEAD_DAYS);
+        assert_eq!(urls[0].0, today);
+        assert!(urls[0].1.ends_with("delta=0"));
+        assert_eq!(urls[6].0, today + chrono::Days::new(6));
+        assert!(urls[6].1.ends_with("delta=6"));
+
+        // A window starting later than today is still offset from today
+        let later = DateRange::new(today + chrono::Days::new(3), today + chrono::Days::new(4));
+        let urls = schedule_urls(&later, today);
+        assert_eq!(urls.len(), 2);
+        assert!(urls[0].1.ends_with("delta=3"));
+        assert!(urls[1].1.ends_with("delta=4"));
+    }
 }
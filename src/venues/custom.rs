@@ -1,6 +1,10 @@
 use anyhow::Result;
 use chrono::NaiveDate;
-use std::{collections::HashSet, fs, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
 use toml::{Table, Value};
 
 use crate::{
@@ -17,7 +21,7 @@ pub fn fetch(filename: &str, date_range: &DateRange) -> Result<Vec<Event>> {
         .filter(|e| {
             e.time_frame
                 .as_ref()
-                .map(|d| d.as_range().overlaps(&date_range))
+                .map(|d| d.overlaps(&date_range))
                 .unwrap_or(false)
         })
         .collect();
@@ -78,9 +82,21 @@ fn parse_event_table(table: &Value) -> Result<Option<Event>> {
 
     let time_frame = table.get("date").and_then(|date| parse_date(date));
 
+    let metadata: HashMap<String, String> = table
+        .get("metadata")
+        .and_then(Value::as_table)
+        .map(|meta| {
+            meta.iter()
+                .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+
     if let Some(title) = title {
         let locs = HashSet::from_iter(locations);
-        let event = Event::new(&title, locs, &category).with_time_frame(time_frame);
+        let event = Event::new(&title, locs, &category)
+            .with_time_frame(time_frame)
+            .with_metadata(metadata);
         return Ok(Some(event));
     }
 
@@ -0,0 +1,256 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use convert_case::Case;
+use indicatif::{ProgressBar, ProgressFinish, ProgressIterator, ProgressStyle};
+use reqwest::Client;
+use scraper::{Html, Selector};
+use tracing::{debug, warn};
+
+use crate::{
+    INFERENCE_SERVICE,
+    dates::{DateParseError, DateRange, TimeFrame, make_date, parse_month, parse_number},
+    events::{Event, Location},
+    utils::{PROGRESS_BAR_TEMPLATE, normalize_whitespace},
+    venues::{
+        CATEGORY_EXHIBITIONS, StandardCasing, fetch_document, fetch_listing, retry_descriptions,
+    },
+};
+
+/// The page listing the exhibitions.
+pub(crate) const LISTING_URL: &str = "https://www.magazzinodelleidee.it/mostre/";
+/// Matches the card of each exhibition in the listing page.
+pub(crate) const LISTING_SELECTOR: &str = "article.mostra";
+
+pub async fn fetch(
+    client: &Client,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
+    let card_sel = Selector::parse(LISTING_SELECTOR).unwrap();
+    let document = fetch_listing(client, LISTING_URL, &card_sel, max_events).await?;
+
+    let exhibitions = parse_exhibitions(&document, date_range);
+
+    let progress = ProgressBar::new(exhibitions.len() as u64)
+        .with_style(ProgressStyle::with_template(PROGRESS_BAR_TEMPLATE).unwrap())
+        .with_message("Fetching Magazzino delle Idee")
+        .with_finish(ProgressFinish::AndLeave);
+
+    let mut events: Vec<Event> = Vec::new();
+    let mut failed = HashSet::new();
+    for event in exhibitions.into_iter().progress_with(progress) {
+        let event_url = event.locations.iter().find_map(|l| l.url.clone());
+        let Some(url) = event_url else {
+            events.push(event);
+            continue;
+        };
+        match get_description(client, &url).await {
+            Ok((description, summary)) => {
+                events.push(event.with_description(description).with_summary(summary))
+            }
+            Err(_) => {
+                failed.insert(event.id.clone());
+                events.push(event);
+            }
+        }
+    }
+
+    retry_descriptions(&mut events, &failed, async |url, _| {
+        get_description(client, url).await
+    })
+    .await;
+
+    Ok(events)
+}
+
+/// Extract the exhibitions running during the given date range from the
+/// exhibition list page. Descriptions are fetched separately.
+fn parse_exhibitions(document: &Html, date_range: &DateRange) -> Vec<Event> {
+    let card_sel = Selector::parse(LISTING_SELECTOR).unwrap();
+    let link_sel = Selector::parse("h2.mostra__title > a").unwrap();
+    let date_sel = Selector::parse("p.mostra__date").unwrap();
+
+    let mut events: HashSet<Event> = HashSet::new();
+    for card in document.select(&card_sel) {
+        let link_el = card.select(&link_sel).next();
+        let date_el = card.select(&date_sel).next();
+        let (Some(link_el), Some(date_el)) = (link_el, date_el) else {
+            continue;
+        };
+
+        let date_str = date_el.text().collect::<String>();
+        let run = match parse_date_range(&date_str) {
+            Ok(run) => run,
+            Err(err) => {
+                warn!("Skipping exhibition with unparsable dates: {err}");
+                continue;
+            }
+        };
+        // Skip exhibitions not running in the current week
+        if !run.overlaps(date_range) {
+            continue;
+        }
+
+        let title = link_el
+            .text()
+            .collect::<String>()
+            .trim()
+            .standardize_case(Some(Case::Title));
+        let event_url = link_el.attr("href").map(|href| href.to_string());
+        let location = Location::new("Magazzino delle Idee", event_url);
+
+        let event = Event::new(&title, HashSet::from_iter([location]), CATEGORY_EXHIBITIONS)
+            .with_time_frame(Some(TimeFrame::Period(run)));
+        events.insert(event);
+    }
+
+    let mut events: Vec<Event> = events.into_iter().collect();
+    events.sort();
+    events
+}
+
+/// Parse the run of an exhibition into a [DateRange].
+///
+/// This function handles these formats:
+/// - Full dates: "dal 12 dicembre 2025 al 6 aprile 2026", "12 dicembre 2025 - 6 aprile 2026"
+/// - Year only at the end: "dal 12 dicembre al 6 aprile 2026"
+/// - Month only at the end: "dal 3 al 28 giugno 2026"
+fn parse_date_range(date_str: &str) -> Result<DateRange, DateParseError> {
+    let normalized = normalize_whitespace(date_str)
+        .to_lowercase()
+        .replace(['–', '—'], "-");
+    let parts: Vec<&str> = normalized
+        .split_whitespace()
+        .filter(|p| !["dal", "al", "-"].contains(p))
+        .collect();
+    if parts.is_empty() {
+        return Err(DateParseError::Empty);
+    }
+
+    // Expected format: [start_day] ([start_month] ([start_year])) [end_day] [end_month] [end_year]
+    let (start, end) = match parts.len() {
+        6 => (&parts[0..3], &parts[3..6]),
+        5 => (&parts[0..2], &parts[2..5]),
+        4 => (&parts[0..1], &parts[1..4]),
+        _ => {
+            return Err(DateParseError::WrongArity {
+                expected: 6,
+                found: parts.len(),
+            });
+        }
+    };
+
+    let end_day = parse_number::<u32>(end[0])?;
+    let end_month = parse_month(end[1])?;
+    let end_year = parse_number::<i32>(end[2])?;
+    let end_date = make_date(end_year, end_month, end_day)?;
+
+    let start_day = parse_number::<u32>(start[0])?;
+    let start_month = match start.get(1) {
+        Some(month) => parse_month(month)?,
+        None => end_month,
+    };
+    let start_year = match start.get(2) {
+        Some(year) => parse_number::<i32>(year)?,
+        // A run like "dal 12 dicembre al 6 aprile 2026" started the year before
+        None if start_month > end_month => end_year - 1,
+        None => end_year,
+    };
+    let start_date = make_date(start_year, start_month, start_day)?;
+
+    if start_date > end_date {
+        return Err(DateParseError::InvalidDate(date_str.to_string()));
+    }
+
+    return Ok(DateRange::new(start_date, end_date));
+}
+
+async fn get_description(client: &Client, url: &str) -> Result<(Option<String>, Option<String>)> {
+    let desc_sel = Selector::parse("div.entry-content p").unwrap();
+
+    let document = fetch_document(client, url).await?;
+    let desc_els = document.select(&desc_sel);
+
+    if desc_els.clone().count() == 0 {
+        debug!("No desc_els");
+        return Ok((None, None));
+    }
+
+    let description = desc_els
+        .map(|el| el.text().collect::<String>())
+        .fold(String::new(), |acc, t| format!("{acc} {t}"))
+        .trim()
+        .to_string();
+
+    let summary = INFERENCE_SERVICE.summarize_description(&description).await;
+
+    return Ok((Some(description), summary));
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, NaiveDate};
+
+    use super::*;
+
+    const LIST_FIXTURE: &str = r#"
+        <main>
+            <article class="mostra">
+                <h2 class="mostra__title"><a href="https://www.magazzinodelleidee.it/mostre/luce-del-nord/">LUCE DEL NORD</a></h2>
+                <p class="mostra__date">dal 12 dicembre 2025 al 6 aprile 2026</p>
+            </article>
+            <article class="mostra">
+                <h2 class="mostra__title"><a href="https://www.magazzinodelleidee.it/mostre/estate/">ESTATE</a></h2>
+                <p class="mostra__date">dal 3 al 28 giugno 2026</p>
+            </article>
+            <article class="mostra">
+                <h2 class="mostra__title"><a href="https://www.magazzinodelleidee.it/mostre/senza-date/">SENZA DATE</a></h2>
+            </article>
+        </main>
+    "#;
+
+    #[test]
+    fn test_full_range() {
+        let range = parse_date_range("dal 12 dicembre 2025 al 6 aprile 2026").unwrap();
+        assert_eq!(range.start, NaiveDate::from_ymd_opt(2025, 12, 12).unwrap());
+        assert_eq!(range.end, NaiveDate::from_ymd_opt(2026, 4, 6).unwrap());
+    }
+
+    #[test]
+    fn test_dashed_range() {
+        let range = parse_date_range("12 Dicembre 2025 – 6 Aprile 2026").unwrap();
+        assert_eq!(range.start.day(), 12);
+        assert_eq!(range.end.month(), 4);
+    }
+
+    #[test]
+    fn test_range_with_shared_year() {
+        let range = parse_date_range("dal 12 dicembre al 6 aprile 2026").unwrap();
+        assert_eq!(range.start, NaiveDate::from_ymd_opt(2025, 12, 12).unwrap());
+        assert_eq!(range.end, NaiveDate::from_ymd_opt(2026, 4, 6).unwrap());
+    }
+
+    #[test]
+    fn test_range_with_shared_month() {
+        let range = parse_date_range("dal 3 al 28 giugno 2026").unwrap();
+        assert_eq!(range.start, NaiveDate::from_ymd_opt(2026, 6, 3).unwrap());
+        assert_eq!(range.end, NaiveDate::from_ymd_opt(2026, 6, 28).unwrap());
+    }
+
+    #[test]
+    fn test_parse_exhibitions_fixture() {
+        let document = Html::parse_document(LIST_FIXTURE);
+        let week = DateRange::new(
+            NaiveDate::from_ymd_opt(2026, 1, 12).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 1, 18).unwrap(),
+        );
+
+        let events = parse_exhibitions(&document, &week);
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].title, "Luce del Nord");
+        assert_eq!(events[0].category, CATEGORY_EXHIBITIONS);
+        assert!(matches!(events[0].time_frame, Some(TimeFrame::Period(_))));
+    }
+}
@@ -0,0 +1,32 @@
+pub(super) mod magazzino;
+use anyhow::Result;
+use reqwest::Client;
+use tracing::error;
+
+use crate::{
+    dates::DateRange,
+    events::Event,
+    venues::{CATEGORY_EXHIBITIONS, CacheManager},
+};
+
+pub async fn fetch(
+    client: &Client,
+    date_range: &DateRange,
+    cache_manager: &mut CacheManager,
+) -> Result<Vec<Event>> {
+    cache_manager.set_category(&CATEGORY_EXHIBITIONS.to_lowercase());
+    let magazzino = cache_manager
+        .get_or_fetch("magazzino", async |max_events| {
+            magazzino::fetch(client, date_range, max_events).await
+        })
+        .await
+        .inspect_err(|e| error!("Failed to fetch Magazzino delle Idee: {e}"))
+        .ok()
+        .flatten()
+        .unwrap_or_else(Vec::new);
+
+    let mut events: Vec<Event> = [magazzino].concat();
+    events.sort();
+
+    Ok(events)
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Result, bail};
+use chrono::{Days, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use ical::{IcalParser, parser::ical::component::IcalEvent};
+use reqwest::Client;
+use serde::Deserialize;
+use tracing::error;
+
+use crate::{
+    dates::{DateRange, DateSet, Showtime, TimeFrame},
+    events::merge_dates,
+    events::{Event, Location},
+    venues::{CacheManager, ScraperError, fetch_page},
+};
+
+/// A venue that publishes its calendar as an iCalendar feed rather than a web page.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IcalSource {
+    /// The snake_case name of the venue, as used with --skip-venues.
+    pub name: String,
+    /// The name shown as the location of events that don't set their own.
+    pub location: String,
+    pub url: String,
+    #[serde(default = "default_category")]
+    pub category: String,
+    /// The city profiles the source belongs to, compared case-insensitively. Sources
+    /// that don't list any belong to every city.
+    #[serde(default)]
+    pub cities: Vec<String>,
+}
+
+impl IcalSource {
+    /// Whether the source belongs to the profile of `city`, or to the run if there's
+    /// no profile.
+    fn belongs_to(&self, city: Option<&str>) -> bool {
+        let Some(city) = city else {
+            return true;
+        };
+        return self.cities.is_empty() || self.cities.iter().any(|c| c.eq_ignore_ascii_case(city));
+    }
+}
+
+fn default_category() -> String {
+    "Altro".to_string()
+}
+
+#[derive(Deserialize)]
+struct IcalSourcesFile {
+    #[serde(default)]
+    sources: Vec<IcalSource>,
+}
+
+/// Loads the iCalendar sources from a TOML file, if it exists.
+pub fn load_sources(filename: &str) -> Result<Vec<IcalSource>> {
+    if !Path::new(filename).exists() {
+        return Ok(Vec::new());
+    }
+    let file: IcalSourcesFile = toml::from_str(&fs::read_to_string(filename)?)?;
+    return Ok(file.sources);
+}
+
+/// Fetches the events of every iCalendar source listed in `filename` that belongs
+/// to the city, cached like any other venue. Sources that fail are reported and skipped.
+pub async fn fetch_all(
+    client: &Client,
+    filename: &str,
+    date_range: &DateRange,
+    cache_manager: &mut CacheManager,
+) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    cache_manager.set_category("ical");
+    for source in load_sources(filename)? {
+        if source.belongs_to(cache_manager.city.as_deref()) {
+            cache_manager.allow_venue(&source.name);
+        }
+        let result = cache_manager
+            .get_or_fetch(&source.name, async |_| {
+                fetch(client, &source, date_range).await
+            })
+            .await;
+        match result {
+            Ok(source_events) => events.extend(source_events.unwrap_or_default()),
+            Err(e) => error!("Failed to fetch {}: {e}", source.name),
+        }
+    }
+    return Ok(events);
+}
+
+pub async fn fetch(
+    client: &Client,
+    source: &IcalSource,
+    date_range: &DateRange,
+) -> Result<Vec<Event>> {
+    let (status, body) = fetch_page(client, &source.url).await?;
+    if !status.is_success() {
+        bail!(ScraperError::UnexpectedPage {
+            url: source.url.clone(),
+            status,
+        });
+    }
+    return parse_calendar(&body, source, date_range);
+}
+
+/// Parses the events of an iCalendar feed that happen within the date range. The
+/// occurrences of a recurring event, which share their title, are merged into a
+/// single event with all of their dates.
+fn parse_calendar(ics: &str, source: &IcalSource, date_range: &DateRange) -> Result<Vec<Event>> {
+    let mut events: HashMap<String, Event> = HashMap::new();
+    for calendar in IcalParser::new(ics.as_bytes()) {
+        for ical_event in calendar?.events {
+            let Some(event) = parse_event(&ical_event, source) else {
+                continue;
+            };
+            let in_range = event
+                .time_frame
+                .as_ref()
+                .is_some_and(|tf| tf.overlaps(date_range));
+            if !in_range {
+                continue;
+            }
+            match events.get_mut(&event.id) {
+                Some(existing) => merge_dates(existing, event),
+                None => drop(events.insert(event.id.clone(), event)),
+            }
+        }
+    }
+
+    let mut events: Vec<Event> = events.into_values().collect();
+    events.sort();
+    return Ok(events);
+}
+
+fn parse_event(ical_event: &IcalEvent, source: &IcalSource) -> Option<Event> {
+    let property = |name: &str| {
+        ical_event
+            .properties
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.value.as_deref())
+            .map(unescape)
+    };
+
+    let title = property("SUMMARY")?;
+    let (start_date, start_time) = parse_date_time(&property("DTSTART")?)?;
+    let end = property("DTEND").and_then(|end| parse_date_time(&end));
+
+    let mut showtimes = Vec::new();
+    let time_frame = match (start_time, end) {
+        // All-day events end exclusively on the day after the last one
+        (None, Some((end_date, None))) if end_date > start_date + Days::new(1) => {
+            TimeFrame::Period(DateRange::new(start_date, end_date - Days::new(1)))
+        }
+        (Some(_), Some((end_date, _))) if end_date > start_date => {
+            TimeFrame::Period(DateRange::new(start_date, end_date))
+        }
+        (start_time, end) => {
+            if let Some(start) = start_time {
+                let end = end.and_then(|(_, time)| time);
+                showtimes.push((start_date, Showtime { start, end }));
+            }
+            TimeFrame::Dates(DateSet::new(vec![start_date]).unwrap())
+        }
+    };
+
+    let location_name = property("LOCATION").unwrap_or_else(|| source.location.clone());
+    let location = Location::new(&location_name, property("URL"));
+
+    let event = Event::new(title.trim(), HashSet::from([location]), &source.category)
+        .with_time_frame(Some(time_frame))
+        .with_description(property("DESCRIPTION"))
+        .with_showtimes(showtimes.into_iter().collect());
+    return Some(event);
+}
+
+/// Parses an iCalendar date (`20260114`) or date-time (`20260114T203000`, with a
+/// trailing `Z` for UTC). UTC times are converted to local time.
+fn parse_date_time(value: &str) -> Option<(NaiveDate, Option<NaiveTime>)> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        let date_time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        let local = Utc.from_utc_datetime(&date_time).with_timezone(&Local);
+        return Some((local.date_naive(), Some(local.time())));
+    }
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Some((date_time.date(), Some(date_time.time())));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+    return Some((date, None));
+}
+
+/// Reverts the escaping of iCalendar text values.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    return unescaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Datelike;
+
+    use super::*;
+
+    const FIXTURE: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//Circolo//Eventi//IT\r
+BEGIN:VEVENT\r
+UID:1@circolo\r
+SUMMARY:Serata di poesia\r
+DESCRIPTION:Letture\\, musica e\\ndiscussione.\r
+DTSTART:20260114T203000\r
+DTEND:20260114T223000\r
+LOCATION:Sala grande\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:2@circolo\r
+SUMMARY:Mostra fotografica\r
+DTSTART;VALUE=DATE:20260110\r
+DTEND;VALUE=DATE:20260121\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:3@circolo\r
+SUMMARY:Assemblea dei soci\r
+DTSTART:20260201T180000\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn source() -> IcalSource {
+        IcalSource {
+            name: "circolo".to_string(),
+            location: "Circolo".to_string(),
+            url: "https://example.com/eventi.ics".to_string(),
+            category: "Incontri".to_string(),
+            cities: Vec::new(),
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 1, day).unwrap()
+    }
+
+    #[test]
+    fn test_parse_calendar_fixture() {
+        let week = DateRange::new(date(12), date(18));
+        let events = parse_calendar(FIXTURE, &source(), &week).unwrap();
+
+        // The assembly is outside of the week
+        assert_eq!(events.len(), 2);
+
+        let exhibition = &events[0];
+        assert_eq!(exhibition.title, "Mostra fotografica");
+        assert_eq!(exhibition.category, "Incontri");
+        assert_eq!(exhibition.locations.iter().next().unwrap().name, "Circolo");
+        let range = exhibition.time_frame.as_ref().unwrap().as_range();
+        assert_eq!((range.start, range.end), (date(10), date(20)));
+
+        let reading = &events[1];
+        assert_eq!(reading.title, "Serata di poesia");
+        assert_eq!(
+            reading.description.as_deref(),
+            Some("Letture, musica e\ndiscussione.")
+        );
+        assert_eq!(reading.locations.iter().next().unwrap().name, "Sala grande");
+        let showtime = reading.showtimes[&date(14)];
+        assert_eq!(showtime.start, NaiveTime::from_hms_opt(20, 30, 0).unwrap());
+        assert_eq!(showtime.end, NaiveTime::from_hms_opt(22, 30, 0));
+        assert_eq!(
+            reading.time_frame.as_ref().unwrap().as_range().start.day(),
+            14
+        );
+    }
+
+    #[test]
+    fn test_recurring_event_keeps_every_occurrence() {
+        let ics = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:1@circolo\r
+SUMMARY:Corso di scacchi\r
+DTSTART:20260113T180000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:2@circolo\r
+SUMMARY:Corso di scacchi\r
+DTSTART:20260115T190000\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let week = DateRange::new(date(12), date(18));
+        let events = parse_calendar(ics, &source(), &week).unwrap();
+
+        assert_eq!(events.len(), 1);
+        match &events[0].time_frame {
+            Some(TimeFrame::Dates(set)) => assert_eq!(set.dates(), &vec![date(13), date(15)]),
+            other => panic!("unexpected time frame {other:?}"),
+        }
+        assert_eq!(events[0].showtimes.len(), 2);
+        assert_eq!(
+            events[0].showtimes[&date(15)].start,
+            NaiveTime::from_hms_opt(19, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sources_belong_to_their_cities() {
+        let everywhere = source();
+        let udine = IcalSource {
+            cities: vec!["Udine".to_string()],
+            ..source()
+        };
+
+        assert!(everywhere.belongs_to(Some("Trieste")));
+        assert!(!udine.belongs_to(Some("Trieste")));
+        assert!(udine.belongs_to(Some("udine")));
+        assert!(udine.belongs_to(None));
+    }
+}
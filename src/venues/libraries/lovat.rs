@@ -1,40 +1,46 @@
 use std::collections::HashSet;
 
 use anyhow::Result;
-use chrono::NaiveDate;
 use convert_case::Case;
 use indicatif::{ProgressBar, ProgressFinish, ProgressIterator, ProgressStyle};
 use reqwest::Client;
-use scraper::{Html, Selector};
+use scraper::Selector;
+use tracing::warn;
 
 use crate::{
     INFERENCE_SERVICE,
-    dates::{DateRange, DateSet, TimeFrame},
+    dates::{DateParseError, DateRange, DateSet, TimeFrame, expect_parts, make_date, parse_number},
     events::{Event, Location},
-    inference::SUMMARY_PROMPT,
-    utils::PROGRESS_BAR_TEMPLATE,
-    venues::{CATEGORY_BOOKSTORES, StandardCasing},
+    utils::{PROGRESS_BAR_TEMPLATE, normalize_whitespace},
+    venues::{
+        CATEGORY_BOOKSTORES, StandardCasing, fetch_document, fetch_listing, retry_descriptions,
+    },
 };
 
-pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>> {
+/// The page listing the upcoming events.
+pub(crate) const LISTING_URL: &str = "https://www.librerielovat.com/eventi/";
+/// Matches each event in the listing page.
+pub(crate) const LISTING_SELECTOR: &str = "div.media.calendarize-item";
+/// Matches the list of upcoming events in the listing page, whose events are fetched.
+const NEXT_EVENTS_SELECTOR: &str = "div#c233 > div.calendarize";
+
+pub async fn fetch(
+    client: &Client,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
     let mut events: HashSet<Event> = HashSet::new();
+    let mut failed = HashSet::new();
 
-    let url = "https://www.librerielovat.com/eventi/";
-    let html_body = client
-        .get(url)
-        .send()
-        .await
-        .inspect_err(|e| println!("GET request failed: {e}"))?
-        .text()
-        .await?;
-
-    let document = Html::parse_document(&html_body);
-    let next_events_sel = Selector::parse("div#c233 > div.calendarize").unwrap();
-    let event_sel = Selector::parse("div.media.calendarize-item").unwrap();
+    let url = LISTING_URL;
+    let next_events_sel = Selector::parse(NEXT_EVENTS_SELECTOR).unwrap();
+    let event_sel = Selector::parse(LISTING_SELECTOR).unwrap();
     let link_sel = Selector::parse("a.stretched-link").unwrap();
     let category_sel = Selector::parse("span.category span.label").unwrap();
     let date_sel = Selector::parse("h4").unwrap();
 
+    let document = fetch_listing(client, url, &next_events_sel, max_events).await?;
+
     let next_events_el = document.select(&next_events_sel).next().unwrap();
 
     let event_count = next_events_el.select(&event_sel).count();
@@ -55,65 +61,73 @@ pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>
             continue;
         }
 
-        let link_el = event_el
-            .select(&link_sel)
-            .next()
-            .expect("Each event card should have a link");
-        let title = link_el
-            .text()
-            .next()
-            .map(|t| t.trim().standardize_case(Some(Case::Title)))
-            .expect("Each event link should have a title");
-        let href = link_el.attr("href").unwrap();
+        let Some(link_el) = event_el.select(&link_sel).next() else {
+            warn!("Skipping Lovat event card without a link");
+            continue;
+        };
+        let (Some(title), Some(href)) = (link_el.text().next(), link_el.attr("href")) else {
+            warn!("Skipping Lovat event link without a title or address");
+            continue;
+        };
+        let title = title.trim().standardize_case(Some(Case::Title));
         let event_url = format!("https://www.librerielovat.com{href}");
         let location = Location::new("Lovat", Some(event_url.clone()));
         let locations = HashSet::from_iter([location]);
-        let date = event_el
+        let date_str = event_el
             .select(&date_sel)
             .next()
             .and_then(|el| el.text().next())
-            .and_then(|t| parse_date(t))
-            .unwrap();
-        if !date.as_range().overlaps(date_range) {
+            .unwrap_or_default();
+        let date = match parse_date(date_str) {
+            Ok(date) => date,
+            Err(err) => {
+                warn!("Skipping Lovat event {title} with unparsable date: {err}");
+                continue;
+            }
+        };
+        if !date.overlaps(date_range) {
             continue;
         }
         let time_frame = TimeFrame::Dates(date);
-        let (description, summary) = get_description(client, &event_url, &title)
-            .await
-            .unwrap_or((None, None));
-
-        let event = Event::new(&title, locations, CATEGORY_BOOKSTORES)
-            .with_time_frame(Some(time_frame))
-            .with_description(description)
-            .with_summary(summary);
+        let event =
+            Event::new(&title, locations, CATEGORY_BOOKSTORES).with_time_frame(Some(time_frame));
+        let event = match get_description(client, &event_url, &title).await {
+            Ok((description, summary)) => event.with_description(description).with_summary(summary),
+            Err(_) => {
+                failed.insert(event.id.clone());
+                event
+            }
+        };
 
         events.insert(event);
     }
 
-    Ok(events.into_iter().collect())
+    let mut events: Vec<Event> = events.into_iter().collect();
+    retry_descriptions(&mut events, &failed, async |url, title| {
+        get_description(client, url, title).await
+    })
+    .await;
+
+    Ok(events)
 }
 
 /// Parses a date string from Lovat data and return a DateSet.
 /// This function handles the format: "Ven 30/01/2026" (weekday DD/MM/YY).
-fn parse_date(date_str: &str) -> Option<DateSet> {
+fn parse_date(date_str: &str) -> Result<DateSet, DateParseError> {
+    let date_str = normalize_whitespace(date_str);
     let parts: Vec<&str> = date_str.split_whitespace().collect();
-    if parts.len() != 2 {
-        return None;
-    }
+    expect_parts(&parts, 2)?;
     // parts[0] is the weekday, which we don't care about
     // parts[1] is the actual date
-    let date_parts: Vec<u32> = parts[1]
-        .split("/")
-        .filter_map(|p| p.parse::<u32>().ok())
-        .collect();
-    if date_parts.len() != 3 {
-        return None;
-    }
+    let date_parts: Vec<&str> = parts[1].split("/").collect();
+    expect_parts(&date_parts, 3)?;
     // date is in DD/MM/YY so 0 is day, 1 is month, 2 is year
-    let date =
-        NaiveDate::from_ymd_opt((2000 + date_parts[2]) as i32, date_parts[1], date_parts[0])?;
+    let day = parse_number::<u32>(date_parts[0])?;
+    let month = parse_number::<u32>(date_parts[1])?;
+    let year = parse_number::<i32>(date_parts[2])?;
+    let date = make_date(2000 + year, month, day)?;
 
-    return Some(DateSet::new(vec![date]).unwrap());
+    return Ok(DateSet::new(vec![date]).unwrap());
 }
 
 async fn get_description(
@@ -121,15 +135,7 @@ async fn get_description(
     url: &str,
     title: &str,
 ) -> Result<(Option<String>, Option<String>)> {
-    let html_body = client
-        .get(url)
-        .send()
-        .await
-        .inspect_err(|e| println!("GET request failed: {e}"))?
-        .text()
-        .await?;
-
-    let document = Html::parse_document(&html_body);
+    let document = fetch_document(client, url).await?;
     let desc_sel = Selector::parse("div.text").unwrap();
     let description = document.select(&desc_sel).next().map(|el| {
         // The title is the author, which is important for the description to make sense
@@ -144,12 +150,40 @@ async fn get_description(
     }
 
     let description = description.unwrap();
-    let prompt = format!("{SUMMARY_PROMPT}\n\n{description}");
-    let summary = INFERENCE_SERVICE
-        .infer(&prompt)
-        .await
-        .inspect_err(|err| eprintln!("Failed to generate summary: {err}"))
-        .ok();
+    let summary = INFERENCE_SERVICE.summarize_description(&description).await;
 
     return Ok((Some(description), summary));
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::Datelike;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_lovat_date() {
+        let set = parse_date("Ven 30/01/26").unwrap();
+        assert_eq!(set.first().day(), 30);
+        assert_eq!(set.first().month(), 1);
+        assert_eq!(set.first().year(), 2026);
+    }
+
+    #[test]
+    fn test_error_wrong_arity() {
+        assert_eq!(
+            parse_date("30/01/26").unwrap_err(),
+            DateParseError::WrongArity {
+                expected: 2,
+                found: 1
+            }
+        );
+        assert_eq!(
+            parse_date("Ven 30/01").unwrap_err(),
+            DateParseError::WrongArity {
+                expected: 3,
+                found: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use convert_case::Case;
+use indicatif::{ProgressBar, ProgressFinish, ProgressIterator, ProgressStyle};
+use reqwest::{Client, Url};
+use scraper::Selector;
+use tracing::warn;
+
+use crate::{
+    INFERENCE_SERVICE,
+    dates::{
+        DateParseError, DateRange, DateSet, Showtime, TimeFrame, is_italian_weekday, make_date,
+        parse_month, parse_number, parse_showtime,
+    },
+    events::{Event, Location},
+    utils::{PROGRESS_BAR_TEMPLATE, normalize_whitespace},
+    venues::{
+        CATEGORY_BOOKSTORES, StandardCasing, fetch_document, fetch_listing, request_pause,
+        retry_descriptions,
+    },
+};
+
+/// The page listing the upcoming events.
+pub(crate) const LISTING_URL: &str = "https://www.libreriaminerva.it/eventi/";
+/// Matches each event in the listing page.
+pub(crate) const LISTING_SELECTOR: &str = "article.evento";
+
+pub async fn fetch(
+    client: &Client,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
+    return fetch_from(client, LISTING_URL, date_range, max_events).await;
+}
+
+/// Fetches the events from the listing at `url`, which tests point at saved pages.
+pub async fn fetch_from(
+    client: &Client,
+    url: &str,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
+    let mut events: HashSet<Event> = HashSet::new();
+    let mut failed = HashSet::new();
+
+    let event_sel = Selector::parse(LISTING_SELECTOR).unwrap();
+    let link_sel = Selector::parse("h2.entry-title > a").unwrap();
+    let date_sel = Selector::parse(".data-evento").unwrap();
+
+    let document = fetch_listing(client, url, &event_sel, max_events).await?;
+
+    let event_count = document.select(&event_sel).count();
+    let progress = ProgressBar::new(event_count as u64)
+        .with_style(ProgressStyle::with_template(PROGRESS_BAR_TEMPLATE).unwrap())
+        .with_message("Fetching Minerva")
+        .with_finish(ProgressFinish::AndLeave);
+
+    for event_el in document.select(&event_sel).progress_with(progress) {
+        let Some(link_el) = event_el.select(&link_sel).next() else {
+            warn!("Skipping Minerva event without a link");
+            continue;
+        };
+        let (Some(title), Some(event_url)) = (link_el.text().next(), link_el.attr("href")) else {
+            warn!("Skipping Minerva event link without a title or address");
+            continue;
+        };
+        let title = title.trim().standardize_case(Some(Case::Upper));
+        let Ok(event_url) = Url::parse(url).and_then(|base| base.join(event_url)) else {
+            warn!("Skipping Minerva event {title} with invalid address {event_url}");
+            continue;
+        };
+        let event_url = event_url.to_string();
+        let date_str = event_el
+            .select(&date_sel)
+            .next()
+            .map(|el| el.text().collect::<String>())
+            .unwrap_or_default();
+        let (date, showtime) = match parse_date(&date_str) {
+            Ok(date) => date,
+            Err(err) => {
+                warn!("Skipping Minerva event {title} with unparsable date: {err}");
+                continue;
+            }
+        };
+        let dates = DateSet::new(vec![date]).unwrap();
+        if !dates.overlaps(date_range) {
+            continue;
+        }
+
+        let location = Location::new("Minerva", Some(event_url.clone()));
+        let event = Event::new(&title, HashSet::from([location]), CATEGORY_BOOKSTORES)
+            .with_time_frame(Some(TimeFrame::Dates(dates)))
+            .with_showtimes(BTreeMap::from_iter(showtime.map(|s| (date, s))));
+        let event = match get_description(client, &event_url).await {
+            Ok((description, summary)) => event.with_description(description).with_summary(summary),
+            Err(_) => {
+                failed.insert(event.id.clone());
+                event
+            }
+        };
+
+        events.insert(event);
+        request_pause("minerva").await;
+    }
+
+    let mut events: Vec<Event> = events.into_iter().collect();
+    retry_descriptions(&mut events, &failed, async |url, _| {
+        get_description(client, url).await
+    })
+    .await;
+
+    Ok(events)
+}
+
+/// Parses a date such as "Venerdì 30 gennaio 2026, ore 18.00", along with its
+/// time if any. The weekday is optional.
+fn parse_date(date_str: &str) -> Result<(NaiveDate, Option<Showtime>), DateParseError> {
+    let date_str = normalize_whitespace(&date_str.replace(',', " "));
+    if date_str.is_empty() {
+        return Err(DateParseError::Empty);
+    }
+    let mut split: Vec<&str> = date_str.split_whitespace().collect();
+    if split.first().is_some_and(|word| is_italian_weekday(word)) {
+        split.remove(0);
+    }
+    if split.len() < 3 {
+        return Err(DateParseError::WrongArity {
+            expected: 3,
+            found: split.len(),
+        });
+    }
+    let day = parse_number::<u32>(split[0])?;
+    let month = parse_month(split[1])?;
+    let year = parse_number::<i32>(split[2])?;
+    let showtime = parse_showtime(&split[3..].join(" "))?;
+
+    return Ok((make_date(year, month, day)?, showtime));
+}
+
+async fn get_description(client: &Client, url: &str) -> Result<(Option<String>, Option<String>)> {
+    let document = fetch_document(client, url).await?;
+    let desc_sel = Selector::parse("div.entry-content p").unwrap();
+    let description = document
+        .select(&desc_sel)
+        .map(|el| normalize_whitespace(&el.text().collect::<String>()))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    if description.is_empty() {
+        return Ok((None, None));
+    }
+
+    let summary = INFERENCE_SERVICE.summarize_description(&description).await;
+
+    return Ok((Some(description), summary));
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_minerva_date() {
+        let (date, showtime) = parse_date("Venerdì 30 gennaio 2026, ore 18.00").unwrap();
+        assert_eq!(date, NaiveDate::from_ymd_opt(2026, 1, 30).unwrap());
+        assert_eq!(
+            showtime.unwrap().start,
+            NaiveTime::from_hms_opt(18, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_date_without_weekday_or_time() {
+        let (date, showtime) = parse_date("7 febbraio 2026").unwrap();
+        assert_eq!(date, NaiveDate::from_ymd_opt(2026, 2, 7).unwrap());
+        assert_eq!(showtime, None);
+    }
+
+    #[test]
+    fn test_error_bad_dates() {
+        assert_eq!(parse_date("  ").unwrap_err(), DateParseError::Empty);
+        assert_eq!(
+            parse_date("Venerdì 30 gennaio").unwrap_err(),
+            DateParseError::WrongArity {
+                expected: 3,
+                found: 2
+            }
+        );
+        assert_eq!(
+            parse_date("30 genaio 2026").unwrap_err(),
+            DateParseError::UnknownMonth("genaio".into())
+        );
+    }
+}
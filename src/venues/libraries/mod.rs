@@ -1,6 +1,8 @@
-mod lovat;
+pub mod lovat;
+pub mod minerva;
 use anyhow::Result;
 use reqwest::Client;
+use tracing::error;
 
 use crate::{
     dates::DateRange,
@@ -15,11 +17,26 @@ pub async fn fetch(
 ) -> Result<Vec<Event>> {
     cache_manager.set_category(&CATEGORY_BOOKSTORES.to_lowercase());
     let lovat = cache_manager
-        .get_or_fetch("lovat", async || lovat::fetch(client, date_range).await)
-        .await?
+        .get_or_fetch("lovat", async |max_events| {
+            lovat::fetch(client, date_range, max_events).await
+        })
+        .await
+        .inspect_err(|e| error!("Failed to fetch Lovat: {e}"))
+        .ok()
+        .flatten()
         .unwrap_or_else(Vec::new);
 
-    let mut events: Vec<Event> = [lovat].concat();
+    let minerva = cache_manager
+        .get_or_fetch("minerva", async |max_events| {
+            minerva::fetch(client, date_range, max_events).await
+        })
+        .await
+        .inspect_err(|e| error!("Failed to fetch Minerva: {e}"))
+        .ok()
+        .flatten()
+        .unwrap_or_else(Vec::new);
+
+    let mut events: Vec<Event> = [lovat, minerva].concat();
     events.sort();
 
     Ok(events)
@@ -1,93 +1,1115 @@
+pub mod aliases;
+pub mod blocklist;
 pub mod cinemas;
 pub mod custom;
+pub mod exhibitions;
+pub mod ical_source;
 pub mod libraries;
 pub mod theaters;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
+use chrono::NaiveDate;
+use clap::ValueEnum;
 use convert_case::{Case, Casing};
 use fancy_regex::{Captures, Regex};
 use lazy_static::lazy_static;
+use reqwest::{
+    Client, Proxy, StatusCode, Url,
+    header::{CONTENT_TYPE, HeaderMap, RETRY_AFTER},
+};
+use scraper::{Html, Selector, selector::ToCss};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{Instrument, error, info, info_span, warn};
+
+use crate::{
+    dates::{DateRange, TimeFrame},
+    events::{Category, Event, merge_dates},
+    history::{HISTORY_FILE, SeenHistory},
+    profiles::CityProfile,
+    utils::decode_body,
+    venues::{aliases::TitleAliases, blocklist::VenueBlocklist},
+};
 
 pub const CATEGORY_MOVIES: &str = "Film";
 pub const CATEGORY_THEATRES: &str = "Teatri";
 pub const CATEGORY_BOOKSTORES: &str = "Librerie";
+pub const CATEGORY_EXHIBITIONS: &str = "Mostre";
+
+/// Directory where venue caches are stored, one subdirectory per category.
+pub const CACHE_ROOT: &str = "cache";
+
+/// The snake_case names of all venues that can be scraped.
+pub const VENUES: &[&str] = &[
+    "triestecinema",
+    "the_space",
+    "hangarteatri",
+    "miela",
+    "rossetti",
+    "verdi",
+    "lovat",
+    "minerva",
+    "magazzino",
+];
+
+/// Default maximum number of events a single venue may return before it's
+/// considered broken (e.g., a selector matching every list item on the site).
+pub const DEFAULT_MAX_EVENTS_PER_VENUE: usize = 500;
+
+/// Errors that signal that a venue's website is not what the scraper expects.
+#[derive(Debug)]
+pub enum ScraperError {
+    /// The page answered with a non-success status code.
+    UnexpectedPage { url: String, status: StatusCode },
+    /// The page loaded, but the main selector matched nothing in it. This usually
+    /// means the venue moved its calendar or changed its markup.
+    NoMatches { url: String, selector: String },
+    /// The page lists more entries than a venue may return, which usually means the
+    /// main selector now matches more than the events.
+    TooManyMatches {
+        url: String,
+        count: usize,
+        max: usize,
+    },
+}
+
+impl Display for ScraperError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedPage { url, status } => {
+                write!(f, "{url} answered with unexpected status {status}")
+            }
+            Self::NoMatches { url, selector } => {
+                write!(f, "{url} has no elements matching `{selector}`")
+            }
+            Self::TooManyMatches { url, count, max } => {
+                write!(
+                    f,
+                    "{url} lists {count} entries, more than the maximum of {max}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScraperError {}
+
+/// Number of times a page is requested before giving up on it.
+const FETCH_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry of a page, doubled on each later retry.
+const FETCH_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest `Retry-After` we're willing to wait, so that a misbehaving site can't
+/// stall the whole run.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Default time to wait for a venue's site to accept a connection, or to send more
+/// of a page, before giving up on the request.
+pub const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The browser the scrapers identify as, since some sites turn away unknown clients.
+const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:146.0) Gecko/20100101 Firefox/146.0";
+
+/// Builds the client the scrapers request pages with. `timeout` applies separately
+/// to connecting and to each read, so that a site hanging on TLS or mid-page can't
+/// stall the whole run. `proxy` is an HTTP(S) proxy URL used for every request.
+pub fn build_client(timeout: Duration, proxy: Option<&str>) -> Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(timeout)
+        .read_timeout(timeout);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    return Ok(builder.build()?);
+}
+
+/// Default pause between the pages a scraper requests from a venue, to go easy on its site.
+pub const DEFAULT_REQUEST_DELAY: Duration = Duration::from_millis(20);
+
+/// How long scrapers pause between the pages they request from each venue.
+#[derive(Debug, Clone, Default)]
+pub struct RequestDelays {
+    /// The pause for venues without their own, instead of [DEFAULT_REQUEST_DELAY].
+    pub global: Option<Duration>,
+    /// Pauses for single venues, by their snake_case name.
+    pub venues: HashMap<String, Duration>,
+}
+
+impl RequestDelays {
+    pub fn delay_for(&self, venue: &str) -> Duration {
+        return self
+            .venues
+            .get(venue)
+            .copied()
+            .or(self.global)
+            .unwrap_or(DEFAULT_REQUEST_DELAY);
+    }
+}
+
+/// Sets the pauses between requests for the rest of the run.
+pub fn set_request_delays(delays: RequestDelays) {
+    *REQUEST_DELAYS.lock().unwrap() = delays;
+}
+
+/// Waits the configured pause before requesting another page from `venue`.
+pub async fn request_pause(venue: &str) {
+    let delay = REQUEST_DELAYS.lock().unwrap().delay_for(venue);
+    tokio::time::sleep(delay).await;
+}
+
+lazy_static! {
+    static ref REQUEST_DELAYS: Mutex<RequestDelays> = Mutex::new(RequestDelays::default());
+
+    /// The delay each host asked for through `Retry-After`, which is then waited
+    /// before every following request to it for the rest of the run.
+    static ref CRAWL_DELAYS: Mutex<HashMap<String, Duration>> = Mutex::new(HashMap::new());
+}
+
+/// Returns the delay that `url`'s host asked to be left between requests, if any.
+pub fn crawl_delay(url: &str) -> Option<Duration> {
+    let host = host_of(url)?;
+    return CRAWL_DELAYS.lock().unwrap().get(&host).copied();
+}
+
+fn set_crawl_delay(url: &str, delay: Duration) {
+    if let Some(host) = host_of(url) {
+        CRAWL_DELAYS.lock().unwrap().insert(host, delay);
+    }
+}
+
+/// The host and port of a URL, to tell apart the sites we're crawling.
+fn host_of(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    return Some(format!(
+        "{}:{}",
+        url.host_str()?,
+        url.port_or_known_default()?
+    ));
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let delay = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+            (date.to_utc() - chrono::Utc::now())
+                .to_std()
+                .unwrap_or(Duration::ZERO)
+        }
+    };
+    return Some(delay.min(MAX_RETRY_AFTER));
+}
+
+/// Fetches a page and parses it into a document, decoding it with its declared charset.
+/// Requests that fail or answer with a server error are retried a few times.
+pub async fn fetch_document(client: &Client, url: &str) -> Result<Html> {
+    let (status, html_body) = fetch_page(client, url)
+        .instrument(info_span!("fetch_document", url))
+        .await?;
+    if !status.is_success() {
+        bail!(ScraperError::UnexpectedPage {
+            url: url.to_string(),
+            status,
+        });
+    }
+
+    return Ok(Html::parse_document(&html_body));
+}
+
+/// Fetches a venue's listing page and makes sure it's the page we expect, that is,
+/// that it loaded successfully and that `selector` matches at least one element and
+/// no more than `max_matches`. The cap is checked here so that a broken listing is
+/// rejected before any detail page is fetched or summarized.
+pub async fn fetch_listing(
+    client: &Client,
+    url: &str,
+    selector: &Selector,
+    max_matches: usize,
+) -> Result<Html> {
+    let (status, html_body) = fetch_page(client, url)
+        .instrument(info_span!("fetch_listing", url))
+        .await?;
+    let document = check_listing(url, status, &html_body, selector)?;
+    check_match_count(url, document.select(selector).count(), max_matches)?;
+    Ok(document)
+}
+
+/// Fails if a listing page has more entries than a venue may return.
+pub fn check_match_count(url: &str, count: usize, max_matches: usize) -> Result<(), ScraperError> {
+    if count > max_matches {
+        return Err(ScraperError::TooManyMatches {
+            url: url.to_string(),
+            count,
+            max: max_matches,
+        });
+    }
+    return Ok(());
+}
+
+/// How a venue's listing page fared in [check_listings].
+pub struct ListingCheck {
+    pub venue: &'static str,
+    pub url: String,
+    /// How many elements the venue's main selector matched, or why the page couldn't
+    /// be checked.
+    pub matches: Result<usize>,
+}
+
+/// The listing page of each venue with the selector of its events, by venue name.
+/// The Space is left out, as its films come from an API that needs a browser.
+fn listing_pages() -> Vec<(&'static str, String, &'static str)> {
+    return vec![
+        (
+            "triestecinema",
+            cinemas::triestecinema::LISTING_URL.to_string(),
+            cinemas::triestecinema::LISTING_SELECTOR,
+        ),
+        (
+            "hangarteatri",
+            theaters::hangarteatri::LISTING_URL.to_string(),
+            theaters::hangarteatri::LISTING_SELECTOR,
+        ),
+        (
+            "miela",
+            theaters::miela::LISTING_URL.to_string(),
+            theaters::miela::LISTING_SELECTOR,
+        ),
+        (
+            "rossetti",
+            format!(
+                "{}{}",
+                theaters::rossetti::BASE_URL,
+                theaters::rossetti::LISTING_PATH
+            ),
+            theaters::rossetti::LISTING_SELECTOR,
+        ),
+        (
+            "verdi",
+            theaters::verdi::LISTING_URL.to_string(),
+            theaters::verdi::LISTING_SELECTOR,
+        ),
+        (
+            "lovat",
+            libraries::lovat::LISTING_URL.to_string(),
+            libraries::lovat::LISTING_SELECTOR,
+        ),
+        (
+            "minerva",
+            libraries::minerva::LISTING_URL.to_string(),
+            libraries::minerva::LISTING_SELECTOR,
+        ),
+        (
+            "magazzino",
+            exhibitions::magazzino::LISTING_URL.to_string(),
+            exhibitions::magazzino::LISTING_SELECTOR,
+        ),
+    ];
+}
+
+/// Fetches the listing page of every venue and counts the matches of its main
+/// selector, to find stale selectors without scraping. Dates aren't parsed and
+/// detail pages aren't fetched.
+pub async fn check_listings(client: &Client) -> Vec<ListingCheck> {
+    let mut checks = Vec::new();
+    for (venue, url, selector) in listing_pages() {
+        let matches = count_matches(client, &url, selector).await;
+        checks.push(ListingCheck {
+            venue,
+            url,
+            matches,
+        });
+    }
+    return checks;
+}
+
+/// Counts the elements matched by `selector` in the page at `url`.
+pub async fn count_matches(client: &Client, url: &str, selector: &str) -> Result<usize> {
+    let selector =
+        Selector::parse(selector).map_err(|e| anyhow!("Invalid selector {selector}: {e}"))?;
+    let (status, html_body) = fetch_page(client, url).await?;
+    if !status.is_success() {
+        return Err(ScraperError::UnexpectedPage {
+            url: url.to_string(),
+            status,
+        }
+        .into());
+    }
+    let document = Html::parse_document(&html_body);
+    return Ok(document.select(&selector).count());
+}
+
+/// Gets the status and decoded body of a page, retrying on connection and server errors.
+/// Sites that ask to slow down with `Retry-After` are waited for, on this and on any
+/// later request.
+async fn fetch_page(client: &Client, url: &str) -> Result<(StatusCode, String)> {
+    return fetch_page_with_retry(client, url, FETCH_ATTEMPTS, FETCH_BACKOFF).await;
+}
+
+/// Like [fetch_page], making up to `attempts` requests with an exponential backoff
+/// starting at `backoff` between them.
+async fn fetch_page_with_retry(
+    client: &Client,
+    url: &str,
+    attempts: u32,
+    backoff: Duration,
+) -> Result<(StatusCode, String)> {
+    let mut attempt = 1;
+    loop {
+        if let Some(delay) = crawl_delay(url) {
+            tokio::time::sleep(delay).await;
+        }
+
+        let response = match client.get(url).send().await {
+            Ok(response) => response,
+            Err(e) if attempt < attempts => {
+                warn!("GET request failed, retrying: {e}");
+                tokio::time::sleep(backoff * 2u32.pow(attempt - 1)).await;
+                attempt += 1;
+                continue;
+            }
+            Err(e) => {
+                warn!("GET request failed: {e}");
+                return Err(e.into());
+            }
+        };
+
+        let status = response.status();
+        let retry_after = parse_retry_after(response.headers());
+        if let Some(delay) = retry_after {
+            info!("{url} asked to wait {}s between requests", delay.as_secs());
+            set_crawl_delay(url, delay);
+        }
+        let should_retry = status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+        if should_retry && attempt < attempts {
+            // The crawl delay is waited at the top of the loop when there is one
+            if retry_after.is_none() {
+                tokio::time::sleep(backoff * 2u32.pow(attempt - 1)).await;
+            }
+            attempt += 1;
+            continue;
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let bytes = response.bytes().await?;
+        return Ok((status, decode_body(&bytes, content_type.as_deref())));
+    }
+}
+
+fn check_listing(
+    url: &str,
+    status: StatusCode,
+    html_body: &str,
+    selector: &Selector,
+) -> Result<Html, ScraperError> {
+    if !status.is_success() {
+        return Err(ScraperError::UnexpectedPage {
+            url: url.to_string(),
+            status,
+        });
+    }
+
+    let document = Html::parse_document(html_body);
+    if document.select(selector).next().is_none() {
+        return Err(ScraperError::NoMatches {
+            url: url.to_string(),
+            selector: selector.to_css_string(),
+        });
+    }
+
+    Ok(document)
+}
+
+/// Fetches once more the descriptions that failed during a venue's main loop, since
+/// most failures are transient. `failed` holds the ids of the events to retry, and
+/// `fetcher` gets the description and summary from an event's url and title.
+pub async fn retry_descriptions(
+    events: &mut [Event],
+    failed: &HashSet<String>,
+    fetcher: impl AsyncFn(&str, &str) -> Result<(Option<String>, Option<String>)>,
+) {
+    retry_details(events, failed, async |url, event| {
+        let (description, summary) = fetcher(url, &event.title).await?;
+        event.description = description;
+        event.summary = summary;
+        return Ok(());
+    })
+    .await;
+}
+
+/// Like [retry_descriptions], for venues whose detail pages give more than the
+/// description, such as the dates of a show. `fetcher` gets an event's url and fills
+/// in the event with the details.
+pub async fn retry_details(
+    events: &mut [Event],
+    failed: &HashSet<String>,
+    fetcher: impl AsyncFn(&str, &mut Event) -> Result<()>,
+) {
+    for event in events.iter_mut().filter(|e| failed.contains(&e.id)) {
+        let Some(url) = event.locations.iter().find_map(|l| l.url.clone()) else {
+            continue;
+        };
+        if let Err(e) = fetcher(&url, event).await {
+            warn!("Failed to fetch details of {}: {e}", event.title);
+        }
+    }
+}
+
+/// Same-titled events of a venue further apart than this many days are different
+/// events rather than one run, for venues that tell them apart with [add_instance].
+pub const MAX_RUN_GAP_DAYS: i64 = 14;
+
+/// Adds an event of a venue, merging its dates into the event with the same title.
+/// With a `max_gap`, only events within that many days of each other are merged, so
+/// that unrelated events sharing a title, such as two "Concerto di Capodanno" a year
+/// apart, stay separate while the dates of a run are still merged. Returns the id
+/// the event ended up under.
+pub fn add_instance(events: &mut HashSet<Event>, event: Event, max_gap: Option<i64>) -> String {
+    let Some(max_gap) = max_gap else {
+        return merge_instance(events, event.id.clone(), event);
+    };
+
+    let range = event.time_frame.as_ref().map(|tf| tf.as_range());
+    let same_run = events.iter().find(|other| {
+        let other_range = other.time_frame.as_ref().map(|tf| tf.as_range());
+        let close = match (&range, other_range) {
+            (Some(a), Some(b)) => {
+                (a.start - b.end)
+                    .num_days()
+                    .max((b.start - a.end).num_days())
+                    <= max_gap
+            }
+            _ => true,
+        };
+        other.title == event.title && !other.locations.is_disjoint(&event.locations) && close
+    });
+    let id = match (same_run, &range) {
+        (Some(other), _) => other.id.clone(),
+        (None, Some(range)) if events.iter().any(|other| other.id == event.id) => {
+            format!("{}@{}", event.id, range.start)
+        }
+        (None, _) => event.id.clone(),
+    };
+    return merge_instance(events, id, event);
+}
+
+/// Merges the dates of an event into the event with the given id, or adds it
+/// under that id if there's none.
+fn merge_instance(events: &mut HashSet<Event>, id: String, event: Event) -> String {
+    let event = event.with_id(id.clone());
+    match events.take(&event) {
+        Some(mut existing) => {
+            existing.time_frame = match (existing.time_frame.take(), event.time_frame) {
+                (Some(a), Some(b)) => Some(a.merge(b)),
+                (a, b) => a.or(b),
+            };
+            events.insert(existing);
+        }
+        None => {
+            events.insert(event);
+        }
+    }
+    return id;
+}
+
+/// Data returned by a venue scraper, which is made up of events.
+pub trait VenueEvents {
+    fn event_count(&self) -> usize;
+    fn events(&self) -> Vec<Event>;
+    /// Drops the events for which `keep` returns false.
+    fn retain_events(&mut self, keep: &dyn Fn(&Event) -> bool);
+    /// Adds the events of an older cache of the same venue. Events in both, by id,
+    /// are kept as fetched with the dates of both.
+    fn merge_cached(&mut self, cached: Self);
+}
+
+impl VenueEvents for Vec<Event> {
+    fn event_count(&self) -> usize {
+        self.len()
+    }
+
+    fn events(&self) -> Vec<Event> {
+        self.clone()
+    }
+
+    fn retain_events(&mut self, keep: &dyn Fn(&Event) -> bool) {
+        self.retain(|event| keep(event));
+    }
+
+    fn merge_cached(&mut self, cached: Self) {
+        for cached_event in cached {
+            match self.iter_mut().find(|e| e.id == cached_event.id) {
+                Some(event) => merge_dates(event, cached_event),
+                None => self.push(cached_event),
+            }
+        }
+    }
+}
+
+/// How venue caches are stored. Each format has its own file extension, so that
+/// runs using different formats never misread each other's caches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum CacheFormat {
+    /// Human-readable JSON, which is easy to inspect and edit by hand.
+    #[default]
+    Json,
+    /// Compact binary encoding, which is smaller and faster to parse.
+    Binary,
+}
+
+impl CacheFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CacheFormat::Json => "json",
+            CacheFormat::Binary => "bin",
+        }
+    }
+
+    /// Finds the format of a cache file from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?;
+        return [CacheFormat::Json, CacheFormat::Binary]
+            .into_iter()
+            .find(|format| extension == format.extension());
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        return match self {
+            CacheFormat::Json => Ok(serde_json::to_vec(value)?),
+            CacheFormat::Binary => Ok(bincode::serialize(value)?),
+        };
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        return match self {
+            CacheFormat::Json => Ok(serde_json::from_slice(bytes)?),
+            CacheFormat::Binary => Ok(bincode::deserialize(bytes)?),
+        };
+    }
+}
+
+/// Callback invoked with the name and events of each venue as soon as it's done.
+pub type VenueDoneCallback = Box<dyn FnMut(&str, &[Event])>;
 
 /// Generic cache manager for venue data
 pub struct CacheManager {
+    cache_root: PathBuf,
     cache_dir: PathBuf,
+    venues_to_fetch: Option<Vec<String>>,
+    /// The name of the city profile being fetched, if any.
+    city: Option<String>,
     cache: bool,
     rebuild: bool,
     venues_to_rebuild: Vec<String>,
     venues_to_skip: Vec<String>,
+    max_events: usize,
+    format: CacheFormat,
+    blocklist: VenueBlocklist,
+    /// Caches older than this are ignored, as if they didn't exist.
+    max_age: Option<Duration>,
+    /// Only report what would be done with each venue, without fetching or loading it.
+    dry_run: bool,
+    /// Add freshly fetched events to the existing caches rather than replacing them.
+    merge: bool,
+    /// The first day of the window being fetched. Merged caches drop the events the
+    /// venue no longer lists that ended before it.
+    window_start: Option<NaiveDate>,
+    on_venue_done: Option<VenueDoneCallback>,
+}
+
+/// By default, the cache manager fetches every venue without caching, and stores
+/// caches in [CACHE_ROOT] when caching is enabled.
+impl Default for CacheManager {
+    fn default() -> Self {
+        Self {
+            cache_root: PathBuf::from(CACHE_ROOT),
+            cache_dir: PathBuf::from(CACHE_ROOT),
+            venues_to_fetch: None,
+            city: None,
+            cache: false,
+            rebuild: false,
+            venues_to_rebuild: Vec::new(),
+            venues_to_skip: Vec::new(),
+            max_events: DEFAULT_MAX_EVENTS_PER_VENUE,
+            format: CacheFormat::default(),
+            blocklist: VenueBlocklist::default(),
+            max_age: None,
+            dry_run: false,
+            merge: false,
+            window_start: None,
+            on_venue_done: None,
+        }
+    }
 }
 
 impl CacheManager {
-    /// Create a new CacheManager for a category (e.g., "cinema", "theater")
-    pub fn new(
-        category: &str,
-        cache: bool,
-        rebuild: bool,
-        venues_to_rebuild: Vec<String>,
-        venues_to_skip: Vec<String>,
-    ) -> Self {
+    /// Reuse cached venue data when available, and cache freshly fetched data.
+    pub fn with_cache(self, cache: bool) -> Self {
+        Self { cache, ..self }
+    }
+
+    /// Ignore all existing caches and fetch every venue again. Does nothing
+    /// without caching.
+    pub fn with_rebuild(self, rebuild: bool) -> Self {
+        Self { rebuild, ..self }
+    }
+
+    /// Ignore the existing caches of these venues and fetch them again. Does
+    /// nothing without caching.
+    pub fn with_venues_to_rebuild(self, venues_to_rebuild: Vec<String>) -> Self {
         Self {
-            cache_dir: PathBuf::from(format!("cache/{category}")),
-            cache,
-            rebuild,
             venues_to_rebuild,
+            ..self
+        }
+    }
+
+    /// Never fetch these venues.
+    pub fn with_venues_to_skip(self, venues_to_skip: Vec<String>) -> Self {
+        Self {
             venues_to_skip,
+            ..self
+        }
+    }
+
+    /// Set the maximum number of events a venue may return. Venues that go over
+    /// the cap are aborted with an error instead of being cached and rendered.
+    pub fn with_max_events(self, max_events: usize) -> Self {
+        Self { max_events, ..self }
+    }
+
+    /// Set the format caches are read and written in. Caches written in another
+    /// format are ignored, as if they didn't exist.
+    pub fn with_format(self, format: CacheFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    /// Set the titles to drop from specific venues, both when fetching and when
+    /// loading from cache.
+    pub fn with_blocklist(self, blocklist: VenueBlocklist) -> Self {
+        Self { blocklist, ..self }
+    }
+
+    /// Fetch venues again when their cache was last written more than `max_age`
+    /// ago. Without a maximum age, caches are used however old they are.
+    pub fn with_max_age(self, max_age: Option<Duration>) -> Self {
+        Self { max_age, ..self }
+    }
+
+    /// Merge freshly fetched events into the existing cache of each venue instead of
+    /// replacing it, so that events the venue no longer lists are kept. Does nothing
+    /// without caching.
+    pub fn with_merge(self, merge: bool) -> Self {
+        Self { merge, ..self }
+    }
+
+    /// Print whether each venue would be fetched, loaded from cache or skipped,
+    /// without touching the network or the cache.
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        Self { dry_run, ..self }
+    }
+
+    /// Restrict fetching to the venues of a city profile and cache them in
+    /// the profile's own subdirectory.
+    pub fn with_profile(self, profile: &CityProfile) -> Self {
+        let cache_root = self.cache_root.join(&profile.cache_subdir);
+        Self {
+            cache_dir: cache_root.clone(),
+            cache_root,
+            venues_to_fetch: Some(profile.venues.clone()),
+            city: Some(profile.name.clone()),
+            ..self
+        }
+    }
+
+    /// Set a callback to be notified of the events of each venue as soon as they're
+    /// loaded, rather than waiting for all venues to be done.
+    pub fn set_on_venue_done(&mut self, callback: VenueDoneCallback) {
+        self.on_venue_done = Some(callback);
+    }
+
+    /// Notifies the venue callback, if any, that a venue is done.
+    pub fn venue_done(&mut self, venue_name: &str, events: &[Event]) {
+        if let Some(callback) = self.on_venue_done.as_mut() {
+            callback(venue_name, events);
+        }
+    }
+
+    /// Lets a venue that isn't one of the scrapers of the profile be fetched, such as
+    /// an iCalendar source of the city.
+    fn allow_venue(&mut self, venue_name: &str) {
+        if let Some(venues) = &mut self.venues_to_fetch {
+            if !venues.iter().any(|v| v == venue_name) {
+                venues.push(venue_name.to_string());
+            }
         }
     }
 
     pub fn set_category(&mut self, category: &str) {
-        self.cache_dir = PathBuf::from(format!("cache/{category}"));
+        self.cache_dir = self.cache_root.join(category);
+    }
+
+    /// What [Self::get_or_fetch] would do with a venue that isn't skipped.
+    fn dry_run_decision(&self, venue_name: &str, cache_path: &Path) -> Result<&'static str> {
+        if !self.cache {
+            return Ok("would fetch, caching is off");
+        }
+        if self.rebuild || self.venues_to_rebuild.contains(&venue_name.to_string()) {
+            return Ok("would fetch, rebuilding the cache");
+        }
+        if !fs::exists(cache_path)? {
+            return Ok("would fetch, no cache");
+        }
+        if self.is_stale(cache_path)? {
+            return Ok("would fetch, cache is stale");
+        }
+        return Ok("cache hit");
+    }
+
+    /// Whether a cache file is older than the maximum age, if any.
+    fn is_stale(&self, cache_path: &Path) -> Result<bool> {
+        let Some(max_age) = self.max_age else {
+            return Ok(false);
+        };
+        let age = fs::metadata(cache_path)?
+            .modified()?
+            .elapsed()
+            .unwrap_or_default();
+        return Ok(age > max_age);
+    }
+
+    /// The day before which the events of an older cache are dropped when merging:
+    /// the start of the window or `max_age` ago, whichever is later.
+    fn merge_cutoff(&self) -> Option<NaiveDate> {
+        let max_age_cutoff = self.max_age.map(|max_age| {
+            let today = chrono::Local::now().date_naive();
+            today - chrono::Duration::from_std(max_age).unwrap_or_default()
+        });
+        return self.window_start.max(max_age_cutoff);
     }
 
     /// Load from cache if exists and valid, otherwise fetch and cache.
     ///
+    /// `fetcher` gets the maximum number of events the venue may return, to reject a
+    /// listing that goes over it before fetching the details of its events.
+    ///
     /// Returns the data whether from cache or freshly fetched.
-    pub async fn get_or_fetch<V, F>(&self, venue_name: &str, fetcher: F) -> Result<Option<V>>
+    pub async fn get_or_fetch<V, F>(&mut self, venue_name: &str, fetcher: F) -> Result<Option<V>>
     where
-        V: Serialize + DeserializeOwned,
-        F: AsyncFnOnce() -> Result<V>,
+        V: Serialize + DeserializeOwned + VenueEvents,
+        F: AsyncFnOnce(usize) -> Result<V>,
     {
+        if let Some(venues) = &self.venues_to_fetch {
+            if !venues.contains(&venue_name.to_string()) {
+                if self.dry_run {
+                    println!("{venue_name}: skipped, not in the city profile");
+                }
+                return Ok(None);
+            }
+        }
+
         if self.venues_to_skip.contains(&venue_name.to_string()) {
-            println!("Skipping {venue_name}");
+            info!("Skipping {venue_name}");
             return Ok(None);
         }
 
-        let cache_path = self.cache_dir.join(format!("{venue_name}.json"));
+        let cache_file = format!("{venue_name}.{}", self.format.extension());
+        let cache_path = self.cache_dir.join(&cache_file);
+
+        if self.dry_run {
+            println!(
+                "{venue_name}: {}",
+                self.dry_run_decision(venue_name, &cache_path)?
+            );
+            return Ok(None);
+        }
 
         // Try to load from cache
         if self.cache && !self.rebuild && !self.venues_to_rebuild.contains(&venue_name.to_string())
         {
             if let Ok(exists) = fs::exists(&cache_path) {
-                if exists {
-                    println!("Loading {venue_name}.json from cache");
-                    let content = fs::read_to_string(&cache_path)?;
-                    return Ok(Some(serde_json::from_str(&content)?));
+                if exists && self.is_stale(&cache_path)? {
+                    info!("Cache {cache_file} is stale, fetching again");
+                } else if exists {
+                    info!("Loading {cache_file} from cache");
+                    let content = fs::read(&cache_path)?;
+                    let mut result: V = self.format.decode(&content)?;
+                    result.retain_events(&|e| !self.blocklist.is_blocked(venue_name, e));
+                    self.venue_done(venue_name, &result.events());
+                    return Ok(Some(result));
                 }
             }
         }
 
         // Fetch from API
-        let result = fetcher().await?;
+        let mut result = fetcher(self.max_events)
+            .instrument(info_span!("fetch_venue", venue = venue_name))
+            .await?;
+        result.retain_events(&|e| !self.blocklist.is_blocked(venue_name, e));
+
+        // Abort venues that return an unreasonable amount of events, as that
+        // most likely means the page markup changed under our selectors. Venues with a
+        // listing page are already stopped there, this catches the ones without one
+        let count = result.event_count();
+        // Listings that match nothing at all fail in fetch_listing, but selectors
+        // within each show can break too, leaving no events and no error
+        if count == 0 {
+            warn!("{venue_name} returned 0 events, its selectors may be stale");
+        }
+        if count > self.max_events {
+            bail!(
+                "{venue_name} returned {count} events, more than the maximum of {}",
+                self.max_events
+            );
+        }
 
         // Write to cache if caching is enabled
         if self.cache {
+            if self.merge && fs::exists(&cache_path)? {
+                match self.format.decode::<V>(&fs::read(&cache_path)?) {
+                    Ok(mut cached) => {
+                        // Events no longer listed are kept only until they're over
+                        let listed: HashSet<String> =
+                            result.events().into_iter().map(|e| e.id).collect();
+                        let cutoff = self.merge_cutoff();
+                        cached.retain_events(&|e| {
+                            let over = cutoff.is_some_and(|cutoff| {
+                                e.time_frame
+                                    .as_ref()
+                                    .is_none_or(|tf| tf.as_range().end < cutoff)
+                            });
+                            !self.blocklist.is_blocked(venue_name, e)
+                                && (listed.contains(&e.id) || !over)
+                        });
+                        result.merge_cached(cached);
+                    }
+                    Err(e) => warn!("Replacing unreadable cache {cache_file}: {e}"),
+                }
+            }
             fs::create_dir_all(&self.cache_dir)?;
-            let serialized = serde_json::to_string(&result)?;
+            let serialized = self.format.encode(&result)?;
             fs::write(&cache_path, serialized)?;
         }
 
+        self.venue_done(venue_name, &result.events());
         Ok(Some(result))
     }
 }
 
+/// How the events of different venues are put together once fetched. Unlike the
+/// options of the [CacheManager], these don't change what is fetched or cached.
+#[derive(Default)]
+pub struct ScrapeSettings {
+    /// Merge the same show playing at different theaters into a single event, with
+    /// the locations and dates of all of them, instead of listing it once per theater.
+    pub merge_theaters: bool,
+    /// How to merge the descriptions of a show playing at more than one theater.
+    pub description_merge: theaters::DescriptionMerge,
+    /// The canonical titles of films that cinemas spell differently, used when
+    /// grouping their screenings.
+    pub aliases: TitleAliases,
+    /// The cinemas, by venue name, whose subtitles tell apart different films. None by
+    /// default, so that the same film is grouped across cinemas even if only some of
+    /// them write its subtitle.
+    pub significant_subtitles: HashSet<String>,
+}
+
+/// Fetches the events of every venue in every category, plus the custom events,
+/// grouped by category and sorted by category name.
+///
+/// `on_venue_done` is called with the events of each venue as soon as it's done,
+/// for consumers that want to show results incrementally.
+pub async fn scrape_all(
+    client: &Client,
+    date_range: &DateRange,
+    cache_manager: &mut CacheManager,
+    settings: &ScrapeSettings,
+    on_venue_done: impl FnMut(&str, &[Event]) + 'static,
+) -> Vec<Category> {
+    info!("Fetching events...");
+    cache_manager.set_on_venue_done(Box::new(on_venue_done));
+    cache_manager.window_start = Some(date_range.start);
+    let mut events_by_category: HashMap<String, Vec<Event>> = HashMap::new();
+
+    let movies = cinemas::fetch(client, date_range, cache_manager, settings)
+        .await
+        .unwrap();
+    events_by_category.insert(CATEGORY_MOVIES.to_string(), movies);
+
+    let shows = theaters::fetch(client, date_range, cache_manager, settings)
+        .await
+        .unwrap();
+    events_by_category.insert(CATEGORY_THEATRES.to_string(), shows);
+
+    let libraries = libraries::fetch(client, date_range, cache_manager)
+        .await
+        .unwrap();
+    events_by_category.insert(CATEGORY_BOOKSTORES.to_string(), libraries);
+
+    let exhibitions = exhibitions::fetch(client, date_range, cache_manager)
+        .await
+        .unwrap();
+    events_by_category.insert(CATEGORY_EXHIBITIONS.to_string(), exhibitions);
+
+    let ical = ical_source::fetch_all(client, "ical_sources.toml", date_range, cache_manager)
+        .await
+        .inspect_err(|e| error!("Failed to load iCalendar sources: {e}"))
+        .unwrap_or_default();
+    for event in ical {
+        events_by_category
+            .entry(event.category.clone())
+            .or_insert_with(Vec::new)
+            .push(event);
+    }
+
+    let custom = custom::fetch("custom_events.toml", date_range).unwrap();
+    cache_manager.venue_done("custom", &custom);
+    merge_custom_events(&mut events_by_category, custom);
+
+    let mut categories: Vec<Category> = events_by_category
+        .into_iter()
+        .map(|(name, events)| Category {
+            name,
+            events: clamp_to_window(events, date_range),
+        })
+        .filter(|category| !category.events.is_empty())
+        .collect();
+    categories.sort_by(|a, b| a.name.cmp(&b.name));
+
+    return categories;
+}
+
+/// Drops the dates outside of the time window, which merging the cards of an event
+/// can bring in, and the events left with none. Periods are kept whole if they
+/// overlap the window, since they show how long an exhibition runs. Events with
+/// unknown dates are kept.
+fn clamp_to_window(events: Vec<Event>, date_range: &DateRange) -> Vec<Event> {
+    return events
+        .into_iter()
+        .filter_map(|event| match &event.time_frame {
+            Some(TimeFrame::Dates(_)) => event.clamp(date_range),
+            Some(TimeFrame::Period(period)) => period.overlaps(date_range).then_some(event),
+            None => Some(event),
+        })
+        .collect();
+}
+
+/// Adds the custom events to their categories. Custom events replace the scraped
+/// events they duplicate, as found by [Event::dedup_key], since they were written
+/// by hand and are assumed to be more accurate.
+fn merge_custom_events(events_by_category: &mut HashMap<String, Vec<Event>>, custom: Vec<Event>) {
+    for event in custom {
+        let events = events_by_category
+            .entry(event.category.clone())
+            .or_insert_with(Vec::new);
+        let key = event.dedup_key();
+        events.retain(|e| e.dedup_key() != key);
+        events.push(event);
+    }
+}
+
+/// Checks that every cache file under `cache_root` still deserializes into the current
+/// data model, to catch schema changes that would break old caches.
+///
+/// Returns the path and deserialization error of each file that failed.
+pub fn validate_cache(cache_root: &Path) -> Result<Vec<(PathBuf, anyhow::Error)>> {
+    let mut failures = Vec::new();
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    collect_files(cache_root, &mut paths)?;
+    paths.retain(|p| CacheFormat::from_path(p).is_some());
+    paths.sort();
+
+    for path in paths {
+        let format = CacheFormat::from_path(&path).unwrap();
+        let content = fs::read(&path)?;
+        // Cinemas cache movie groups rather than plain events
+        let in_cinema_dir = path
+            .parent()
+            .and_then(|p| p.file_name())
+            .is_some_and(|name| name == "cinema");
+        let is_history = path.file_name().is_some_and(|name| name == HISTORY_FILE);
+        let result = if is_history {
+            format.decode::<SeenHistory>(&content).map(drop)
+        } else if in_cinema_dir {
+            format
+                .decode::<Vec<cinemas::MovieGroup>>(&content)
+                .map(drop)
+        } else {
+            format.decode::<Vec<Event>>(&content).map(drop)
+        };
+
+        if let Err(err) = result {
+            failures.push((path, err));
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Finds the cache files under `cache_root` that are older than `max_age` or that
+/// belong to venues no longer in [VENUES], and deletes them unless `dry_run` is set.
+/// The seen history is always kept, since it's meant to last across runs.
+///
+/// Returns the paths of the files that were, or would have been, deleted.
+pub fn prune_cache(cache_root: &Path, max_age: Duration, dry_run: bool) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    collect_files(cache_root, &mut paths)?;
+    paths.sort();
+
+    let mut stale = Vec::new();
+    for path in paths {
+        if path.file_name().is_some_and(|name| name == HISTORY_FILE) {
+            continue;
+        }
+
+        let is_unknown_venue = CacheFormat::from_path(&path).is_some()
+            && path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_none_or(|stem| !VENUES.contains(&stem));
+        let age = fs::metadata(&path)?
+            .modified()?
+            .elapsed()
+            .unwrap_or_default();
+        if !is_unknown_venue && age <= max_age {
+            continue;
+        }
+
+        if !dry_run {
+            fs::remove_file(&path)?;
+        }
+        stale.push(path);
+    }
+
+    Ok(stale)
+}
+
+/// Recursively collects the paths of all files in a directory.
+fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
 pub trait StandardCasing {
     /// Casing conversion with extra grammatical rules.
     /// Provide the current casing of the string, if known, through `starting_case`
@@ -156,3 +1178,780 @@ lazy_static! {
     static ref QUOTES: Regex = Regex::new(r#"("|“|”)\w"#).unwrap();
     static ref QUOTES_FANCY: Regex = Regex::new(r#""(.*?)""#).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::{dates::DateSet, events::Location};
+
+    fn make_events(count: usize) -> Vec<Event> {
+        (0..count)
+            .map(|i| Event::new(&format!("Evento {i}"), HashSet::new(), CATEGORY_THEATRES))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_does_not_fetch() {
+        let dir = std::env::temp_dir().join(format!("dry_run_{}", std::process::id()));
+        let mut cache_manager = CacheManager::default().with_cache(true).with_dry_run(true);
+        cache_manager.cache_root = dir.clone();
+        cache_manager.set_category("teatri");
+        fs::create_dir_all(&cache_manager.cache_dir).unwrap();
+        fs::write(cache_manager.cache_dir.join("miela.json"), "[]").unwrap();
+
+        let fetched = std::cell::Cell::new(false);
+        let result = cache_manager
+            .get_or_fetch("verdi", async |_| {
+                fetched.set(true);
+                Ok(make_events(1))
+            })
+            .await
+            .unwrap();
+        assert!(result.is_none());
+        assert!(!fetched.get());
+        assert!(!fs::exists(cache_manager.cache_dir.join("verdi.json")).unwrap());
+
+        let verdi_path = cache_manager.cache_dir.join("verdi.json");
+        let miela_path = cache_manager.cache_dir.join("miela.json");
+        assert_eq!(
+            cache_manager
+                .dry_run_decision("verdi", &verdi_path)
+                .unwrap(),
+            "would fetch, no cache"
+        );
+        assert_eq!(
+            cache_manager
+                .dry_run_decision("miela", &miela_path)
+                .unwrap(),
+            "cache hit"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_gives_up_on_hung_sites() {
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            loop {
+                streams.push(listener.accept().await.unwrap());
+            }
+        });
+
+        let client = build_client(Duration::from_millis(200), None).unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), client.get(&url).send()).await;
+
+        assert!(result.expect("the client didn't time out").is_err());
+    }
+
+    #[test]
+    fn test_client_rejects_invalid_proxy() {
+        assert!(build_client(DEFAULT_FETCH_TIMEOUT, Some("not a proxy")).is_err());
+        assert!(build_client(DEFAULT_FETCH_TIMEOUT, Some("http://localhost:3128")).is_ok());
+    }
+
+    #[test]
+    fn test_request_delay_overrides() {
+        let delays = RequestDelays {
+            global: Some(Duration::from_millis(100)),
+            venues: HashMap::from([("verdi".to_string(), Duration::from_millis(500))]),
+        };
+        assert_eq!(delays.delay_for("verdi"), Duration::from_millis(500));
+        assert_eq!(delays.delay_for("miela"), Duration::from_millis(100));
+        assert_eq!(
+            RequestDelays::default().delay_for("miela"),
+            DEFAULT_REQUEST_DELAY
+        );
+    }
+
+    #[tokio::test]
+    async fn test_venue_without_events_is_not_an_error() {
+        let mut cache_manager = CacheManager::default();
+
+        let empty = cache_manager
+            .get_or_fetch("miela", async |_| Ok(make_events(0)))
+            .await;
+
+        assert!(empty.unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_event_cap_aborts_only_offending_venue() {
+        let mut cache_manager = CacheManager::default().with_max_events(3);
+
+        let broken = cache_manager
+            .get_or_fetch("broken", async |_| Ok(make_events(4)))
+            .await;
+        let healthy = cache_manager
+            .get_or_fetch("healthy", async |_| Ok(make_events(3)))
+            .await;
+
+        assert!(broken.is_err());
+        assert_eq!(healthy.unwrap().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_custom_event_replaces_scraped_duplicate() {
+        let scraped = Event::new("AMLETO", HashSet::new(), CATEGORY_THEATRES)
+            .with_description(Some("Dal sito del teatro".to_string()));
+        let custom = Event::new("Amleto.", HashSet::new(), CATEGORY_THEATRES)
+            .with_id("custom-amleto".to_string())
+            .with_description(Some("Scritto a mano".to_string()));
+        let mut events_by_category =
+            HashMap::from([(CATEGORY_THEATRES.to_string(), vec![scraped])]);
+
+        merge_custom_events(&mut events_by_category, vec![custom]);
+
+        let theaters = &events_by_category[CATEGORY_THEATRES];
+        assert_eq!(theaters.len(), 1);
+        assert_eq!(theaters[0].id, "custom-amleto");
+        assert_eq!(theaters[0].description.as_deref(), Some("Scritto a mano"));
+    }
+
+    #[tokio::test]
+    async fn test_blocked_title_is_dropped_only_for_its_venue() {
+        let blocklist = VenueBlocklist::parse(r#"miela = ["Evento 1"]"#).unwrap();
+        let mut cache_manager = CacheManager::default().with_blocklist(blocklist);
+        let miela = cache_manager
+            .get_or_fetch("miela", async |_| Ok(make_events(3)))
+            .await
+            .unwrap()
+            .unwrap();
+        let verdi = cache_manager
+            .get_or_fetch("verdi", async |_| Ok(make_events(3)))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(miela.len(), 2);
+        assert!(miela.iter().all(|e| e.title != "Evento 1"));
+        assert_eq!(verdi.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stale_cache_is_fetched_again() {
+        let root = std::env::temp_dir().join(format!("scraper-caffe-ttl-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let cache_manager = || {
+            CacheManager {
+                cache_root: root.clone(),
+                cache_dir: root.clone(),
+                ..CacheManager::default()
+            }
+            .with_cache(true)
+            .with_max_age(Some(Duration::from_secs(24 * 3600)))
+        };
+        let fetches = Cell::new(0);
+        let fetcher = async |_| {
+            fetches.set(fetches.get() + 1);
+            Ok(make_events(2))
+        };
+
+        cache_manager()
+            .get_or_fetch("miela", fetcher)
+            .await
+            .unwrap();
+        cache_manager()
+            .get_or_fetch("miela", fetcher)
+            .await
+            .unwrap();
+        assert_eq!(fetches.get(), 1);
+
+        let two_days_ago = std::time::SystemTime::now() - Duration::from_secs(2 * 24 * 3600);
+        fs::File::options()
+            .write(true)
+            .open(root.join("miela.json"))
+            .unwrap()
+            .set_modified(two_days_ago)
+            .unwrap();
+        let events = cache_manager().get_or_fetch("miela", fetcher).await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(events.unwrap().unwrap().len(), 2);
+        assert_eq!(fetches.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_merge_keeps_events_no_longer_listed() {
+        let root = std::env::temp_dir().join(format!("scraper-caffe-merge-{}", std::process::id()));
+        let cache_manager = || CacheManager {
+            cache_root: root.clone(),
+            cache_dir: root.clone(),
+            ..CacheManager::default()
+        };
+        let show = |title: &str, day: u32| {
+            let date = NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+            Event::new(title, HashSet::new(), CATEGORY_THEATRES)
+                .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date]).unwrap())))
+        };
+
+        cache_manager()
+            .with_cache(true)
+            .get_or_fetch("miela", async |_| {
+                Ok(vec![show("Amleto", 10), show("Otello", 10)])
+            })
+            .await
+            .unwrap();
+        // The next week, the listing has scrolled past the first days
+        let events: Vec<Event> = cache_manager()
+            .with_cache(true)
+            .with_rebuild(true)
+            .with_merge(true)
+            .get_or_fetch("miela", async |_| Ok(vec![show("Amleto", 17)]))
+            .await
+            .unwrap()
+            .unwrap();
+        let cached: Vec<Event> =
+            serde_json::from_slice(&fs::read(root.join("miela.json")).unwrap()).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(cached.len(), 2);
+        assert_eq!(events.len(), 2);
+        let amleto = events.iter().find(|e| e.title == "Amleto").unwrap();
+        match &amleto.time_frame {
+            Some(TimeFrame::Dates(set)) => assert_eq!(set.dates().len(), 2),
+            other => panic!("unexpected time frame {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merge_drops_events_over_before_the_window() {
+        let root =
+            std::env::temp_dir().join(format!("scraper-caffe-merge-drop-{}", std::process::id()));
+        let date = |day| NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+        let cache_manager = |window_start| {
+            CacheManager {
+                cache_root: root.clone(),
+                cache_dir: root.clone(),
+                window_start: Some(date(window_start)),
+                ..CacheManager::default()
+            }
+            .with_cache(true)
+        };
+        let show = |title: &str, time_frame| {
+            Event::new(title, HashSet::new(), CATEGORY_THEATRES).with_time_frame(Some(time_frame))
+        };
+        let period = |start, end| TimeFrame::Period(DateRange::new(date(start), date(end)));
+
+        cache_manager(5)
+            .get_or_fetch("miela", async |_| {
+                Ok(vec![
+                    show("Amleto", period(5, 10)),
+                    show("Otello", period(5, 10)),
+                    show("Mostra", period(5, 31)),
+                ])
+            })
+            .await
+            .unwrap();
+        let events: Vec<Event> = cache_manager(12)
+            .with_rebuild(true)
+            .with_merge(true)
+            .get_or_fetch("miela", async |_| {
+                let dates = DateSet::new(vec![date(12)]).unwrap();
+                Ok(vec![show("Amleto", TimeFrame::Dates(dates))])
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let mut titles: Vec<&str> = events.iter().map(|e| e.title.as_str()).collect();
+        titles.sort();
+        assert_eq!(titles, vec!["Amleto", "Mostra"]);
+        // Still listed, so merged with its older dates through the mixed time frames
+        let amleto = events.iter().find(|e| e.title == "Amleto").unwrap();
+        match &amleto.time_frame {
+            Some(TimeFrame::Period(range)) => {
+                assert_eq!((range.start, range.end), (date(5), date(12)))
+            }
+            other => panic!("unexpected time frame {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_same_title_far_apart_stays_separate() {
+        let date = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        let event = |title: &str, date: NaiveDate| {
+            let location = Location::new("Miela", None);
+            Event::new(title, HashSet::from([location]), CATEGORY_THEATRES)
+                .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date]).unwrap())))
+        };
+        let instances = [
+            event("Concerto di Capodanno", date(2025, 1, 1)),
+            event("Amleto", date(2025, 1, 10)),
+            event("Amleto", date(2025, 1, 11)),
+            event("Amleto", date(2025, 1, 13)),
+            event("Concerto di Capodanno", date(2025, 12, 31)),
+        ];
+
+        let mut events = HashSet::new();
+        for instance in instances.clone() {
+            add_instance(&mut events, instance, Some(MAX_RUN_GAP_DAYS));
+        }
+        let mut separate: Vec<(String, usize)> = events
+            .iter()
+            .map(|e| {
+                (
+                    e.id.clone(),
+                    e.time_frame.as_ref().unwrap().as_range().num_days() as usize,
+                )
+            })
+            .collect();
+        separate.sort();
+        assert_eq!(
+            separate,
+            vec![
+                ("Amleto".to_string(), 4),
+                ("Concerto di Capodanno".to_string(), 1),
+                ("Concerto di Capodanno@2025-12-31".to_string(), 1),
+            ]
+        );
+
+        // Without a gap, events are merged by title alone
+        let mut events = HashSet::new();
+        for instance in instances {
+            add_instance(&mut events, instance, None);
+        }
+        assert_eq!(events.len(), 2);
+    }
+
+    #[test]
+    fn test_out_of_window_dates_are_not_rendered() {
+        let date = |day| NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+        let week = DateRange::new(date(12), date(18));
+        // Two cards of the same show, one of them from the week after
+        let mut events = HashSet::new();
+        for day in [14, 21] {
+            let event = Event::new("Amleto", HashSet::new(), CATEGORY_THEATRES).with_time_frame(
+                Some(TimeFrame::Dates(DateSet::new(vec![date(day)]).unwrap())),
+            );
+            add_instance(&mut events, event, None);
+        }
+        let exhibition = Event::new("Mostra", HashSet::new(), CATEGORY_EXHIBITIONS)
+            .with_time_frame(Some(TimeFrame::Period(DateRange::new(date(1), date(31)))));
+        let gone = Event::new("Otello", HashSet::new(), CATEGORY_THEATRES).with_time_frame(Some(
+            TimeFrame::Dates(DateSet::new(vec![date(25)]).unwrap()),
+        ));
+        let mut events: Vec<Event> = events.into_iter().collect();
+        events.extend([exhibition, gone]);
+
+        let events = clamp_to_window(events, &week);
+
+        assert_eq!(events.len(), 2);
+        let html = crate::rendering::render_to_html(
+            vec![Category {
+                name: CATEGORY_THEATRES.to_string(),
+                events,
+            }],
+            &week,
+            "Titolo",
+            None,
+            None,
+            None,
+            false,
+            date(12),
+            None,
+            &crate::rendering::category_order::CategoryOrder::default(),
+        )
+        .unwrap();
+        assert!(html.contains("14/01"));
+        assert!(!html.contains("21/01"));
+        assert!(html.contains("in corso, fino al 31/01/2026"));
+    }
+
+    #[test]
+    fn test_builder_defaults() {
+        let cache_manager = CacheManager::default();
+        assert!(!cache_manager.cache);
+        assert!(!cache_manager.rebuild);
+        assert!(cache_manager.venues_to_skip.is_empty());
+        assert_eq!(cache_manager.max_events, DEFAULT_MAX_EVENTS_PER_VENUE);
+        assert_eq!(cache_manager.cache_root, Path::new(CACHE_ROOT));
+
+        let muggia = CityProfile {
+            name: "Muggia".to_string(),
+            title: "Questa Settimana a Muggia".to_string(),
+            venues: vec![],
+            cache_subdir: "muggia".to_string(),
+        };
+        let mut cache_manager = CacheManager::default()
+            .with_cache(true)
+            .with_profile(&muggia);
+        cache_manager.set_category("cinema");
+        assert!(cache_manager.cache);
+        assert_eq!(
+            cache_manager.cache_dir,
+            Path::new(CACHE_ROOT).join("muggia").join("cinema")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_profile_restricts_fetched_venues() {
+        let trieste = CityProfile::trieste();
+        let other = CityProfile {
+            name: "Muggia".to_string(),
+            title: "Questa Settimana a Muggia".to_string(),
+            venues: vec!["miela".to_string()],
+            cache_subdir: "muggia".to_string(),
+        };
+
+        for (profile, expected) in [(&trieste, VENUES.to_vec()), (&other, vec!["miela"])] {
+            let mut cache_manager = CacheManager::default().with_profile(profile);
+            let mut fetched = Vec::new();
+            for venue in VENUES {
+                let result = cache_manager
+                    .get_or_fetch(venue, async |_| Ok(make_events(1)))
+                    .await
+                    .unwrap();
+                if result.is_some() {
+                    fetched.push(*venue);
+                }
+            }
+            assert_eq!(fetched, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scrape_all_only_runs_profile_venues() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let root =
+            std::env::temp_dir().join(format!("scraper-caffe-profile-{}", std::process::id()));
+        let date = NaiveDate::from_ymd_opt(2026, 1, 12).unwrap();
+        let week = DateRange::new(date, date);
+        let muggia = CityProfile {
+            name: "Muggia".to_string(),
+            title: "Questa Settimana a Muggia".to_string(),
+            venues: vec!["miela".to_string(), "verdi".to_string()],
+            cache_subdir: "muggia".to_string(),
+        };
+        // Every venue of the profile is cached, so nothing is requested
+        let theaters_dir = root.join("muggia").join("teatri");
+        fs::create_dir_all(&theaters_dir).unwrap();
+        for venue in ["miela", "verdi", "rossetti"] {
+            let event = Event::new(
+                &format!("Spettacolo {venue}"),
+                HashSet::new(),
+                CATEGORY_THEATRES,
+            )
+            .with_time_frame(Some(TimeFrame::Dates(DateSet::new(vec![date]).unwrap())));
+            let json = serde_json::to_vec(&vec![event]).unwrap();
+            fs::write(theaters_dir.join(format!("{venue}.json")), json).unwrap();
+        }
+        let mut cache_manager = CacheManager {
+            cache_root: root.clone(),
+            ..CacheManager::default()
+        }
+        .with_cache(true)
+        .with_profile(&muggia);
+        // Requests would fail at once rather than reach the venues
+        let client = build_client(DEFAULT_FETCH_TIMEOUT, Some("http://127.0.0.1:1")).unwrap();
+
+        let done: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
+        let recorder = done.clone();
+        let settings = ScrapeSettings::default();
+        let categories = scrape_all(
+            &client,
+            &week,
+            &mut cache_manager,
+            &settings,
+            move |venue, _| recorder.borrow_mut().push(venue.to_string()),
+        )
+        .await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(*done.borrow(), vec!["miela", "verdi", "custom"]);
+        let titles: Vec<&str> = categories
+            .iter()
+            .flat_map(|c| &c.events)
+            .map(|e| e.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["Spettacolo miela", "Spettacolo verdi"]);
+
+        let html = crate::rendering::render_to_html(
+            categories,
+            &week,
+            &muggia.title,
+            Some(muggia.name.clone()),
+            None,
+            None,
+            false,
+            date,
+            None,
+            &crate::rendering::category_order::CategoryOrder::default(),
+        )
+        .unwrap();
+        assert!(html.contains("eventi a Muggia"));
+        assert!(!html.contains("Trieste"));
+    }
+
+    #[tokio::test]
+    async fn test_venue_callback_fires_once_per_venue() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let done: Rc<RefCell<Vec<(String, usize)>>> = Rc::new(RefCell::new(Vec::new()));
+        let recorder = done.clone();
+        let mut cache_manager = CacheManager::default().with_venues_to_skip(vec!["verdi".into()]);
+        cache_manager.set_on_venue_done(Box::new(move |venue, events| {
+            recorder
+                .borrow_mut()
+                .push((venue.to_string(), events.len()))
+        }));
+
+        for (venue, count) in [("miela", 2), ("verdi", 1), ("rossetti", 3)] {
+            cache_manager
+                .get_or_fetch(venue, async |_| Ok(make_events(count)))
+                .await
+                .unwrap();
+        }
+
+        // Skipped venues never finish
+        assert_eq!(
+            *done.borrow(),
+            vec![("miela".to_string(), 2), ("rossetti".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn test_validate_cache_reports_invalid_files() {
+        let root = std::env::temp_dir().join(format!("scraper-caffe-cache-{}", std::process::id()));
+        let theater_dir = root.join("teatri");
+        fs::create_dir_all(&theater_dir).unwrap();
+
+        let valid = serde_json::to_string(&make_events(2)).unwrap();
+        fs::write(theater_dir.join("miela.json"), valid).unwrap();
+        fs::write(
+            theater_dir.join("verdi.json"),
+            r#"[{ "title": "Senza id" }]"#,
+        )
+        .unwrap();
+
+        let failures = validate_cache(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, theater_dir.join("verdi.json"));
+    }
+
+    #[test]
+    fn test_event_binary_round_trip() {
+        let date = chrono::NaiveDate::from_ymd_opt(2026, 1, 14).unwrap();
+        let location = Location::new("Miela", Some("https://www.miela.it".to_string()));
+        let event = Event::new("Amleto", HashSet::from([location]), CATEGORY_THEATRES)
+            .with_time_frame(Some(crate::dates::TimeFrame::Dates(
+                crate::dates::DateSet::new(vec![date]).unwrap(),
+            )))
+            .with_description(Some("Una tragedia".to_string()))
+            .with_tags(HashSet::from(["Prima nazionale".to_string()]))
+            .with_metadata(HashMap::from([(
+                "cast".to_string(),
+                "Mario Rossi".to_string(),
+            )]));
+
+        let bytes = CacheFormat::Binary.encode(&vec![event.clone()]).unwrap();
+        let decoded: Vec<Event> = CacheFormat::Binary.decode(&bytes).unwrap();
+
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].id, event.id);
+        assert_eq!(decoded[0].locations, event.locations);
+        assert_eq!(
+            decoded[0].time_frame.as_ref().unwrap().as_range().start,
+            date
+        );
+        assert_eq!(decoded[0].description, event.description);
+        assert_eq!(decoded[0].tags, event.tags);
+        assert_eq!(decoded[0].metadata, event.metadata);
+    }
+
+    #[test]
+    fn test_cache_format_from_extension() {
+        assert_eq!(
+            CacheFormat::from_path(Path::new("cache/teatri/miela.bin")),
+            Some(CacheFormat::Binary)
+        );
+        assert_eq!(
+            CacheFormat::from_path(Path::new("cache/teatri/miela.json")),
+            Some(CacheFormat::Json)
+        );
+        assert_eq!(CacheFormat::from_path(Path::new("cache/notes.txt")), None);
+    }
+
+    #[test]
+    fn test_prune_cache_removes_old_and_unknown_files() {
+        let root = std::env::temp_dir().join(format!("scraper-caffe-prune-{}", std::process::id()));
+        let theater_dir = root.join("teatri");
+        fs::create_dir_all(&theater_dir).unwrap();
+        for name in ["miela.json", "verdi.json", "chiuso.json"] {
+            fs::write(theater_dir.join(name), "[]").unwrap();
+        }
+        let two_months_ago = std::time::SystemTime::now() - Duration::from_secs(60 * 24 * 3600);
+        fs::File::options()
+            .write(true)
+            .open(theater_dir.join("verdi.json"))
+            .unwrap()
+            .set_modified(two_months_ago)
+            .unwrap();
+
+        let max_age = Duration::from_secs(30 * 24 * 3600);
+        let dry_run = prune_cache(&root, max_age, true).unwrap();
+        let pruned = prune_cache(&root, max_age, false).unwrap();
+        let kept = fs::exists(theater_dir.join("miela.json")).unwrap();
+        let removed = !fs::exists(theater_dir.join("verdi.json")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let expected = vec![
+            theater_dir.join("chiuso.json"),
+            theater_dir.join("verdi.json"),
+        ];
+        assert_eq!(dry_run, expected);
+        assert_eq!(pruned, expected);
+        assert!(kept && removed);
+    }
+
+    #[test]
+    fn test_listing_selectors_are_valid() {
+        for (venue, _, selector) in listing_pages() {
+            assert!(
+                Selector::parse(selector).is_ok(),
+                "bad selector for {venue}"
+            );
+            assert!(VENUES.contains(&venue));
+        }
+    }
+
+    #[test]
+    fn test_check_listing_rejects_wrong_page() {
+        let selector = Selector::parse("div.single-show").unwrap();
+        let soft_404 = r#"
+            <html><body>
+                <h1>Pagina non trovata</h1>
+                <ul><li><a href="/">Torna alla home</a></li></ul>
+            </body></html>
+        "#;
+        let listing = r#"<html><body><div class="single-show">Amleto</div></body></html>"#;
+        let url = "https://example.com/cartellone";
+
+        let result = check_listing(url, StatusCode::OK, soft_404, &selector);
+        assert!(matches!(result, Err(ScraperError::NoMatches { .. })));
+
+        let result = check_listing(url, StatusCode::NOT_FOUND, listing, &selector);
+        assert!(matches!(result, Err(ScraperError::UnexpectedPage { .. })));
+
+        assert!(check_listing(url, StatusCode::OK, listing, &selector).is_ok());
+    }
+
+    /// Serves the given raw HTTP responses in order, one per connection, and
+    /// returns the URL of the server.
+    async fn serve(responses: Vec<&'static str>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        return url;
+    }
+
+    #[tokio::test]
+    async fn test_fetch_document_retries_server_errors() {
+        let url = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: 41\r\nConnection: close\r\n\r\n<html><body><h1>Città</h1></body></html>",
+        ])
+        .await;
+
+        let document = fetch_document(&Client::new(), &url).await.unwrap();
+        let title_sel = Selector::parse("h1").unwrap();
+        let title: String = document.select(&title_sel).next().unwrap().text().collect();
+        assert_eq!(title, "Città");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_page_gives_up_after_attempts() {
+        const SERVER_ERROR: &str =
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+        let client = Client::new();
+
+        let url = serve(vec![SERVER_ERROR, SERVER_ERROR, OK]).await;
+        let (status, body) = fetch_page_with_retry(&client, &url, 3, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "ok"));
+
+        let url = serve(vec![SERVER_ERROR, SERVER_ERROR, OK]).await;
+        let (status, _) = fetch_page_with_retry(&client, &url, 2, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        // Nothing listens on a closed port, so every attempt fails to connect
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", closed.local_addr().unwrap());
+        drop(closed);
+        assert!(
+            fetch_page_with_retry(&client, &url, 3, Duration::ZERO)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_document_honors_retry_after() {
+        let url = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 2\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+
+        let start = std::time::Instant::now();
+        fetch_document(&Client::new(), &url).await.unwrap();
+
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        assert_eq!(crawl_delay(&url), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_parse_retry_after_date() {
+        let in_half_a_minute = chrono::Utc::now() + chrono::Duration::seconds(30);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RETRY_AFTER,
+            in_half_a_minute
+                .to_rfc2822()
+                .replace("+0000", "GMT")
+                .parse()
+                .unwrap(),
+        );
+
+        let delay = parse_retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_document_rejects_client_errors() {
+        let url = serve(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+
+        let err = fetch_document(&Client::new(), &url).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ScraperError>(),
+            Some(ScraperError::UnexpectedPage { .. })
+        ));
+    }
+}
@@ -1,40 +1,48 @@
-use std::{collections::HashSet, time::Duration};
+use std::collections::HashSet;
 
 use anyhow::Result;
-use chrono::NaiveDate;
 use convert_case::Case;
 use indicatif::{ProgressBar, ProgressFinish, ProgressIterator, ProgressStyle};
 use reqwest::Client;
-use scraper::{Html, Selector};
+use scraper::Selector;
+use tracing::{debug, warn};
 
 use crate::{
     INFERENCE_SERVICE,
-    dates::{DateRange, DateSet, TimeFrame, italian_month_to_number},
+    dates::{
+        DateParseError, DateRange, DateSet, TimeFrame, expect_parts, make_date, parse_month,
+        parse_number,
+    },
     events::{Event, Location},
-    inference::SUMMARY_PROMPT,
-    utils::PROGRESS_BAR_TEMPLATE,
-    venues::{CATEGORY_THEATRES, StandardCasing},
+    utils::{PROGRESS_BAR_TEMPLATE, normalize_whitespace},
+    venues::{
+        CATEGORY_THEATRES, StandardCasing, fetch_document, fetch_listing, request_pause,
+        retry_descriptions,
+    },
 };
 
-pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>> {
+/// The page listing the upcoming shows.
+pub(crate) const LISTING_URL: &str = "https://www.hangarteatri.com/eventi/";
+/// Matches each show in the listing page.
+pub(crate) const LISTING_SELECTOR: &str =
+    "li.tribe-common-g-row.tribe-events-calendar-list__event-row";
+
+pub async fn fetch(
+    client: &Client,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
     let mut events: HashSet<Event> = HashSet::new();
+    let mut failed = HashSet::new();
 
-    let url = "https://www.hangarteatri.com/eventi/";
-    let html_body = client
-        .get(url)
-        .send()
-        .await
-        .inspect_err(|e| println!("GET request failed: {e}"))?
-        .text()
-        .await?;
-
-    let document = Html::parse_document(&html_body);
-    let shows_sel =
-        Selector::parse("li.tribe-common-g-row.tribe-events-calendar-list__event-row").unwrap();
+    let url = LISTING_URL;
+    let shows_sel = Selector::parse(LISTING_SELECTOR).unwrap();
     let link_sel = Selector::parse("h4.tribe-events-calendar-list__event-title > a").unwrap();
     let date_sel =
         Selector::parse("time.tribe-events-calendar-list__event-datetime > span").unwrap();
 
+    let document = fetch_listing(client, url, &shows_sel, max_events).await?;
+
     let show_count = document.select(&shows_sel).count();
     let progress = ProgressBar::new(show_count as u64)
         .with_style(ProgressStyle::with_template(PROGRESS_BAR_TEMPLATE).unwrap())
@@ -44,44 +52,57 @@ pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>
     for show in document.select(&shows_sel).progress_with(progress) {
         let link_el = show.select(&link_sel).next();
         let date_el = show.select(&date_sel).next();
-        if link_el.is_none() || date_el.is_none() {
+        let (Some(link_el), Some(date_el)) = (link_el, date_el) else {
             continue;
-        }
+        };
 
-        let date_str = date_el
-            .and_then(|el| el.text().next())
-            .map(|t| t.to_string())
-            .expect("Each event date should have text");
-        let dates = parse_date(&date_str).expect("Date should be in a standardized format");
+        let Some(date_str) = date_el.text().next().map(|t| t.to_string()) else {
+            warn!("Skipping Hangar Teatri show without a date");
+            continue;
+        };
+        let dates = match parse_date(&date_str) {
+            Ok(dates) => dates,
+            Err(err) => {
+                warn!("Skipping Hangar Teatri show with unparsable date: {err}");
+                continue;
+            }
+        };
         // Skip events not in the current week
-        if !dates.as_range().overlaps(&date_range) {
+        if !dates.overlaps(&date_range) {
             continue;
         }
         let time_frame = TimeFrame::Dates(dates);
 
-        let title = link_el
-            .and_then(|el| el.text().next())
-            .map(|t| t.trim().standardize_case(Some(Case::Title)))
-            .expect("Each event card should have a link with text");
-        let event_url = link_el.unwrap().attr("href").unwrap();
+        let (Some(title), Some(event_url)) = (link_el.text().next(), link_el.attr("href")) else {
+            warn!("Skipping Hangar Teatri show link without a title or address");
+            continue;
+        };
+        let title = title.trim().standardize_case(Some(Case::Title));
         let location = Location::new("Hangar Teatri", Some(event_url.to_string()));
         let locations = HashSet::from_iter([location]);
 
-        let (description, summary) = get_description(client, event_url)
-            .await
-            .unwrap_or((None, None));
-
-        let event = Event::new(&title, locations, CATEGORY_THEATRES)
-            .with_time_frame(Some(time_frame))
-            .with_description(description)
-            .with_summary(summary);
+        let event =
+            Event::new(&title, locations, CATEGORY_THEATRES).with_time_frame(Some(time_frame));
+        let event = match get_description(client, event_url).await {
+            Ok((description, summary)) => event.with_description(description).with_summary(summary),
+            Err(_) => {
+                failed.insert(event.id.clone());
+                event
+            }
+        };
 
         events.insert(event);
 
-        tokio::time::sleep(Duration::from_millis(20)).await;
+        request_pause("hangarteatri").await;
     }
 
-    Ok(events.into_iter().collect())
+    let mut events: Vec<Event> = events.into_iter().collect();
+    retry_descriptions(&mut events, &failed, async |url, _| {
+        get_description(client, url).await
+    })
+    .await;
+
+    Ok(events)
 }
 
 /// Parse a date string from Hangar Teatri data and return a DateRange
@@ -89,41 +110,79 @@ pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>
 /// This function handles these formats:
 /// - Single dates with time: "9 Gennaio 2026 @ 20:30"
 /// - Single dates with time ranges: "9 Gennaio 2026 @ 20:30 - 22:00"
-fn parse_date(date_str: &str) -> Option<DateSet> {
-    let trimmed = date_str.trim();
+/// - Multi-day ranges: "9 Gennaio 2026 @ 20:30 - 11 Gennaio 2026 @ 22:00"
+/// - Multi-day ranges with the year only at the end: "30 Dicembre @ 20:30 - 2 Gennaio 2026 @ 22:00"
+fn parse_date(date_str: &str) -> Result<DateSet, DateParseError> {
+    let normalized = normalize_whitespace(date_str);
+    let trimmed = normalized.as_str();
     if trimmed.is_empty() {
-        return None;
+        return Err(DateParseError::Empty);
     }
 
-    // Extract just the date part (before @)
-    let date_part = trimmed.split('@').next().unwrap().trim();
+    // The dash separates either the start and end times of the same day or two dates
+    let (start_str, end_str) = match trimmed.split_once('-') {
+        Some((start, end)) if end.contains('@') => (start, Some(end)),
+        _ => (trimmed, None),
+    };
 
-    // Expected format: [day] [month] [year]
-    // Indexes:         0     1       2
-    let parts: Vec<&str> = date_part.split_whitespace().collect();
-    if parts.len() != 3 {
-        return None;
-    }
+    let start_parts = date_parts(start_str);
+    let Some(end_str) = end_str else {
+        expect_parts(&start_parts, 3)?;
+        let day = parse_number::<u32>(start_parts[0])?;
+        let month = parse_month(start_parts[1])?;
+        let year = parse_number::<i32>(start_parts[2])?;
+        let date = make_date(year, month, day)?;
+        // For single dates, create a date range that spans one day
+        return Ok(DateSet::new(vec![date]).unwrap());
+    };
 
-    let day = parts[0].parse::<u32>().ok()?;
-    let month = italian_month_to_number(parts[1])?;
-    let year = parts[2].parse::<i32>().ok()?;
+    let end_parts = date_parts(end_str);
+    expect_parts(&end_parts, 3)?;
+    let end_year = parse_number::<i32>(end_parts[2])?;
+    let end_month = parse_month(end_parts[1])?;
+    let end = make_date(end_year, end_month, parse_number::<u32>(end_parts[0])?)?;
 
-    let date = NaiveDate::from_ymd_opt(year, month, day)?;
+    let (day, month, year) = match start_parts[..] {
+        [day, month, year] => (day, parse_month(month)?, parse_number::<i32>(year)?),
+        // Ranges may only give the year of the end, which is the next one across new year
+        [day, month] => {
+            let month = parse_month(month)?;
+            let year = if month > end_month {
+                end_year - 1
+            } else {
+                end_year
+            };
+            (day, month, year)
+        }
+        _ => {
+            return Err(DateParseError::WrongArity {
+                expected: 3,
+                found: start_parts.len(),
+            });
+        }
+    };
+    let start = make_date(year, month, parse_number::<u32>(day)?)?;
+    if start > end {
+        return Err(DateParseError::InvalidDate(trimmed.to_string()));
+    }
 
-    // For single dates, create a date range that spans one day
-    return Some(DateSet::new(vec![date]).unwrap());
+    let dates = DateRange::new(start, end).iter_days().collect();
+    return Ok(DateSet::new(dates).unwrap());
+}
+
+/// Splits the date before the `@` of a Hangar Teatri date into its words.
+fn date_parts(text: &str) -> Vec<&str> {
+    return text.split('@').next().unwrap().split_whitespace().collect();
 }
 
 async fn get_description(client: &Client, url: &str) -> Result<(Option<String>, Option<String>)> {
     let desc_sel = Selector::parse(".cmsmasters_row .cmsmasters_text").unwrap();
 
-    let html_body = client.get(url).send().await?.text().await?;
-    let document = Html::parse_document(&html_body);
+    let document = fetch_document(client, url).await?;
     let desc_el = document.select(&desc_sel);
 
     if desc_el.clone().count() == 0 {
-        println!("No desc_el");
+        debug!("No desc_el");
         return Ok((None, None));
     }
 
@@ -138,12 +197,7 @@ async fn get_description(client: &Client, url: &str) -> Result<(Option<String>,
         .trim()
         .to_string();
 
-    let prompt = format!("{SUMMARY_PROMPT}\n\n{description}");
-    let summary = INFERENCE_SERVICE
-        .infer(&prompt)
-        .await
-        .inspect_err(|err| eprintln!("Failed to generate summary: {err}"))
-        .ok();
+    let summary = INFERENCE_SERVICE.summarize_description(&description).await;
 
     return Ok((Some(description), summary));
 }
@@ -171,4 +225,50 @@ mod tests {
         assert_eq!(range.first().month(), 1);
         assert_eq!(range.first().year(), 2026);
     }
+
+    #[test]
+    fn test_single_date_with_time_range() {
+        let set = parse_date("9 Gennaio 2026 @ 20:30 - 22:00").unwrap();
+        assert_eq!(set.dates().len(), 1);
+        assert_eq!(set.first().day(), 9);
+    }
+
+    #[test]
+    fn test_multi_day_range() {
+        let set = parse_date("9 Gennaio 2026 @ 20:30 - 11 Gennaio 2026 @ 22:00").unwrap();
+        assert_eq!(set.dates().len(), 3);
+        assert_eq!(set.first().day(), 9);
+        assert_eq!(set.last().day(), 11);
+    }
+
+    #[test]
+    fn test_multi_day_range_across_new_year() {
+        let set = parse_date("30 Dicembre @ 20:30 - 2 Gennaio 2026 @ 22:00").unwrap();
+        assert_eq!(set.dates().len(), 4);
+        assert_eq!(set.first().year(), 2025);
+        assert_eq!(set.first().month(), 12);
+        assert_eq!(set.last().year(), 2026);
+        assert_eq!(set.last().day(), 2);
+    }
+
+    #[test]
+    fn test_error_bad_month() {
+        let result = parse_date("9 Gennaro 2026 @ 20:30");
+        assert_eq!(
+            result.unwrap_err(),
+            DateParseError::UnknownMonth("Gennaro".into())
+        );
+    }
+
+    #[test]
+    fn test_error_wrong_arity() {
+        let result = parse_date("9 Gennaio @ 20:30");
+        assert_eq!(
+            result.unwrap_err(),
+            DateParseError::WrongArity {
+                expected: 3,
+                found: 2
+            }
+        );
+    }
 }
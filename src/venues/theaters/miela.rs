@@ -1,38 +1,54 @@
-use std::{collections::HashSet, time::Duration};
+use std::collections::HashSet;
 
 use anyhow::Result;
-use chrono::NaiveDate;
 use convert_case::Case;
 use indicatif::{ProgressBar, ProgressFinish, ProgressIterator, ProgressStyle};
 use reqwest::Client;
-use scraper::{Html, Selector};
+use scraper::Selector;
+use tracing::{debug, warn};
 
 use crate::{
     INFERENCE_SERVICE,
-    dates::{DateRange, DateSet, TimeFrame},
+    dates::{DateParseError, DateRange, DateSet, TimeFrame, make_date, parse_number},
     events::{Event, Location},
-    inference::SUMMARY_PROMPT,
     utils::PROGRESS_BAR_TEMPLATE,
-    venues::{CATEGORY_THEATRES, StandardCasing},
+    venues::{
+        CATEGORY_THEATRES, MAX_RUN_GAP_DAYS, StandardCasing, add_instance, fetch_document,
+        fetch_listing, request_pause, retry_descriptions,
+    },
 };
 
-pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>> {
-    let mut events: HashSet<Event> = HashSet::new();
+/// The page listing the shows of the coming days.
+pub(crate) const LISTING_URL: &str = "https://www.miela.it/calendario/";
+/// Matches each show in the listing page.
+pub(crate) const LISTING_SELECTOR: &str = "a.calendar-show";
+/// Matches each day of the calendar in the listing page.
+const DAY_SELECTOR: &str = "div.calendar-day";
+
+pub async fn fetch(
+    client: &Client,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
+    return fetch_from(client, LISTING_URL, date_range, max_events).await;
+}
 
-    let url = "https://www.miela.it/calendario/";
-    let html_body = client
-        .get(url)
-        .send()
-        .await
-        .inspect_err(|e| println!("GET request failed: {e}"))?
-        .text()
-        .await?;
+/// Fetches the shows from the calendar at `url`, which tests point at saved pages.
+pub async fn fetch_from(
+    client: &Client,
+    url: &str,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
+    let mut events: HashSet<Event> = HashSet::new();
+    let mut failed = HashSet::new();
 
-    let document = Html::parse_document(&html_body);
-    let shows_sel = Selector::parse("div.calendar-day").unwrap();
-    let link_sel = Selector::parse("a.calendar-show").unwrap();
+    let shows_sel = Selector::parse(DAY_SELECTOR).unwrap();
+    let link_sel = Selector::parse(LISTING_SELECTOR).unwrap();
     let title_sel = Selector::parse("a.calendar-show > p > span.font-bold").unwrap();
 
+    let document = fetch_listing(client, url, &shows_sel, max_events).await?;
+
     let show_count = document.select(&shows_sel).count();
     let progress = ProgressBar::new(show_count as u64)
         .with_style(ProgressStyle::with_template(PROGRESS_BAR_TEMPLATE).unwrap())
@@ -42,88 +58,99 @@ pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>
     for show in document.select(&shows_sel).progress_with(progress) {
         let link_el = show.select(&link_sel).next();
         let title_el = show.select(&title_sel).next();
-        if link_el.is_none() {
+        let Some(event_url) = link_el.and_then(|el| el.attr("href")) else {
             continue;
-        }
+        };
 
-        let date_str = show
-            .attr("data-calendar-day")
-            .expect("Each calendar day should have a date");
-        let dates = parse_date(&date_str).expect("Date should be in a standardized format");
+        let Some(date_str) = show.attr("data-calendar-day") else {
+            warn!("Skipping Miela calendar day without a date");
+            continue;
+        };
+        let dates = match parse_date(&date_str) {
+            Ok(dates) => dates,
+            Err(err) => {
+                warn!("Skipping Miela calendar day with unparsable date: {err}");
+                continue;
+            }
+        };
         // Skip events not in the current week
-        if !dates.as_range().overlaps(&date_range) {
+        if !dates.overlaps(&date_range) {
             continue;
         }
         let time_frame = TimeFrame::Dates(dates);
 
-        let title = title_el
+        let Some(title) = title_el
             .and_then(|el| el.text().next())
             .map(|t| t.trim().standardize_case(Some(Case::Upper)))
-            .expect("Each event card should have text");
+        else {
+            warn!("Skipping Miela event card without a title");
+            continue;
+        };
 
-        let event_url = link_el.unwrap().attr("href").unwrap();
         let location = Location::new("Miela", Some(event_url.to_string()));
         let locations = HashSet::from_iter([location]);
 
-        let (description, summary) = get_description(client, event_url)
-            .await
-            .unwrap_or((None, None));
-
-        let event = Event::new(&title, locations, CATEGORY_THEATRES)
-            .with_time_frame(Some(time_frame))
-            .with_description(description)
-            .with_summary(summary);
-
-        // Merge time frames if needed
-        if let Some(mut ext_event) = events.take(&event) {
-            let old_tf = ext_event.time_frame.unwrap();
-            let new_tf = old_tf.merge(event.time_frame.unwrap());
-            ext_event.time_frame = Some(new_tf);
-            events.insert(ext_event);
-        } else {
-            events.insert(event);
+        let event =
+            Event::new(&title, locations, CATEGORY_THEATRES).with_time_frame(Some(time_frame));
+        let (event, fetched) = match get_description(client, event_url).await {
+            Ok((description, summary)) => (
+                event.with_description(description).with_summary(summary),
+                true,
+            ),
+            Err(_) => (event, false),
+        };
+
+        // Merge the dates of a run, but keep unrelated shows with the same title apart
+        let id = add_instance(&mut events, event, Some(MAX_RUN_GAP_DAYS));
+        if !fetched {
+            failed.insert(id);
         }
 
-        tokio::time::sleep(Duration::from_millis(20)).await;
+        request_pause("miela").await;
     }
 
-    Ok(events.into_iter().collect())
+    let mut events: Vec<Event> = events.into_iter().collect();
+    retry_descriptions(&mut events, &failed, async |url, _| {
+        get_description(client, url).await
+    })
+    .await;
+
+    Ok(events)
 }
 
 /// Parse a date string from Miela data and return a DateSet
 ///
 /// This function handles the format: "20260109" (YYYYMMDD)
 /// which is stored in the data-calendar-day attribute
-fn parse_date(date_str: &str) -> Option<DateSet> {
+fn parse_date(date_str: &str) -> Result<DateSet, DateParseError> {
     // The date_str is in format "20260109" (YYYYMMDD)
     // Extract year, month, and day
-    if date_str.len() != 8 {
-        return None;
+    if date_str.len() != 8 || !date_str.is_ascii() {
+        return Err(DateParseError::UnknownFormat(date_str.to_string()));
     }
 
     let year_str = &date_str[0..4];
     let month_str = &date_str[4..6];
     let day_str = &date_str[6..8];
 
-    let year = year_str.parse::<i32>().ok()?;
-    let month = month_str.parse::<u32>().ok()?;
-    let day = day_str.parse::<u32>().ok()?;
+    let year = parse_number::<i32>(year_str)?;
+    let month = parse_number::<u32>(month_str)?;
+    let day = parse_number::<u32>(day_str)?;
 
-    let date = NaiveDate::from_ymd_opt(year, month, day)?;
+    let date = make_date(year, month, day)?;
 
     // For single dates, create a date range that spans one day
-    return Some(DateSet::new(vec![date]).unwrap());
+    return Ok(DateSet::new(vec![date]).unwrap());
 }
 
 async fn get_description(client: &Client, url: &str) -> Result<(Option<String>, Option<String>)> {
     let desc_sel = Selector::parse("div.article__body.prose").unwrap();
 
-    let html_body = client.get(url).send().await?.text().await?;
-    let document = Html::parse_document(&html_body);
+    let document = fetch_document(client, url).await?;
     let desc_el = document.select(&desc_sel).next();
 
     if desc_el.is_none() {
-        println!("No desc_el");
+        debug!("No desc_el");
         return Ok((None, None));
     }
 
@@ -135,12 +162,7 @@ async fn get_description(client: &Client, url: &str) -> Result<(Option<String>,
         })
         .replace("\n", "");
 
-    let prompt = format!("{SUMMARY_PROMPT}\n\n{description}");
-    let summary = INFERENCE_SERVICE
-        .infer(&prompt)
-        .await
-        .inspect_err(|err| eprintln!("Failed to generate summary: {err}"))
-        .ok();
+    let summary = INFERENCE_SERVICE.summarize_description(&description).await;
 
     return Ok((Some(description), summary));
 }
@@ -159,4 +181,22 @@ mod tests {
         assert_eq!(range.first().month(), 1);
         assert_eq!(range.first().year(), 2026);
     }
+
+    #[test]
+    fn test_error_wrong_format() {
+        let result = parse_date("2026-01-09");
+        assert_eq!(
+            result.unwrap_err(),
+            DateParseError::UnknownFormat("2026-01-09".into())
+        );
+    }
+
+    #[test]
+    fn test_error_invalid_date() {
+        let result = parse_date("20261309");
+        assert_eq!(
+            result.unwrap_err(),
+            DateParseError::InvalidDate("9/13/2026".into())
+        );
+    }
 }
@@ -3,47 +3,441 @@ pub mod miela;
 pub mod rossetti;
 pub mod verdi;
 
+use std::collections::HashMap;
+
 use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+use clap::ValueEnum;
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
 use reqwest::Client;
+use scraper::{Html, Selector};
+use tracing::error;
 
 use crate::{
-    dates::DateRange,
-    events::Event,
-    venues::{CATEGORY_THEATRES, CacheManager},
+    INFERENCE_SERVICE,
+    dates::{DateRange, DateSet, italian_month_to_number},
+    events::{Event, dedup_key, merge_locations},
+    utils::normalize_whitespace,
+    venues::{CATEGORY_THEATRES, CacheManager, ScrapeSettings},
 };
 
+/// Metadata key of the premiere date of a show, formatted as "10/01".
+pub const PREMIERE_KEY: &str = "premiere";
+
+/// Metadata key of the rassegna (a themed season or festival) a show is part of.
+pub const RASSEGNA_KEY: &str = "rassegna";
+
+lazy_static! {
+    /// Matches premiere notes such as "prima il 10/01" or "prima nazionale 10 gennaio".
+    static ref PREMIERE: Regex = Regex::new(
+        r"(?i)\bprima(?:\s+(?:nazionale|assoluta|regionale))?\s+(?:il\s+)?(\d{1,2})(?:/(\d{1,2})|\s+([a-z]+))"
+    )
+    .unwrap();
+
+    /// Matches the words that name a rassegna, such as "Rassegna", "Festival" or
+    /// "Mittelfest", but not words that merely contain "fest" like "Manifesto".
+    static ref RASSEGNA_WORD: Regex = Regex::new(r"(?i)\b(?:rassegna|festival|\w*fest)\b").unwrap();
+}
+
+/// Separator between the descriptions of different theaters when they're combined.
+const DESCRIPTION_SEPARATOR: &str = "\n\n";
+
+/// What to do with the descriptions of a show playing at more than one theater.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DescriptionMerge {
+    /// Keep the description of the first theater.
+    #[default]
+    First,
+    /// Combine the descriptions of all theaters, dropping near-identical ones.
+    Combine,
+    /// Like `combine`, then summarize the combined description again.
+    Summarize,
+}
+
 pub async fn fetch(
     client: &Client,
     date_range: &DateRange,
     cache_manager: &mut CacheManager,
+    settings: &ScrapeSettings,
 ) -> Result<Vec<Event>> {
     cache_manager.set_category(&CATEGORY_THEATRES.to_lowercase());
     let hangarteatri = cache_manager
-        .get_or_fetch("hangarteatri", async || {
-            hangarteatri::fetch(client, date_range).await
+        .get_or_fetch("hangarteatri", async |max_events| {
+            hangarteatri::fetch(client, date_range, max_events).await
         })
-        .await?
+        .await
+        .inspect_err(|e| error!("Failed to fetch Hangar Teatri: {e}"))
+        .ok()
+        .flatten()
         .unwrap_or_else(Vec::new);
 
     let miela = cache_manager
-        .get_or_fetch("miela", async || miela::fetch(client, date_range).await)
-        .await?
+        .get_or_fetch("miela", async |max_events| {
+            miela::fetch(client, date_range, max_events).await
+        })
+        .await
+        .inspect_err(|e| error!("Failed to fetch Miela: {e}"))
+        .ok()
+        .flatten()
         .unwrap_or_else(Vec::new);
 
     let rossetti = cache_manager
-        .get_or_fetch("rossetti", async || {
-            rossetti::fetch(client, date_range).await
+        .get_or_fetch("rossetti", async |max_events| {
+            rossetti::fetch(client, date_range, max_events).await
         })
-        .await?
+        .await
+        .inspect_err(|e| error!("Failed to fetch Rossetti: {e}"))
+        .ok()
+        .flatten()
         .unwrap_or_else(Vec::new);
 
     let verdi = cache_manager
-        .get_or_fetch("verdi", async || verdi::fetch(client, date_range).await)
-        .await?
+        .get_or_fetch("verdi", async |max_events| {
+            verdi::fetch(client, date_range, max_events).await
+        })
+        .await
+        .inspect_err(|e| error!("Failed to fetch Verdi: {e}"))
+        .ok()
+        .flatten()
         .unwrap_or_else(Vec::new);
 
     let mut events: Vec<Event> = [hangarteatri, miela, rossetti, verdi].concat();
+    if !settings.merge_theaters {
+        events.sort();
+        return Ok(events);
+    }
+
+    let mut events = merge_shows(events, settings.description_merge);
+    if settings.description_merge == DescriptionMerge::Summarize {
+        // Combined descriptions had their summary dropped, as it covered only one of them
+        for event in events.iter_mut().filter(|e| e.summary.is_none()) {
+            if let Some(description) = &event.description {
+                event.summary = INFERENCE_SERVICE.summarize_description(description).await;
+            }
+        }
+    }
     events.sort();
 
     Ok(events)
 }
+
+/// Merges the same show playing at different theaters into a single event, with
+/// the locations and dates of all of them. Theaters don't agree on casing, so
+/// shows are matched by [Event::dedup_key].
+fn merge_shows(events: Vec<Event>, description_merge: DescriptionMerge) -> Vec<Event> {
+    let mut merged: Vec<Event> = Vec::new();
+    let mut by_key: HashMap<String, usize> = HashMap::new();
+
+    for event in events {
+        let key = event.dedup_key();
+        // Shows with the same title at the same theater were kept apart on purpose
+        let index = by_key
+            .get(&key)
+            .copied()
+            .filter(|&index| merged[index].locations.is_disjoint(&event.locations));
+        let Some(index) = index else {
+            by_key.insert(key, merged.len());
+            merged.push(event);
+            continue;
+        };
+
+        let show = &mut merged[index];
+        merge_locations(&mut show.locations, event.locations);
+        show.tags.extend(event.tags);
+        show.showtimes.extend(event.showtimes);
+        for (key, value) in event.metadata {
+            show.metadata.entry(key).or_insert(value);
+        }
+        show.time_frame = match (show.time_frame.take(), event.time_frame) {
+            (Some(time_frame), Some(other)) => Some(time_frame.merge(other)),
+            (time_frame, other) => time_frame.or(other),
+        };
+
+        if description_merge == DescriptionMerge::First || show.description.is_none() {
+            show.description = show.description.take().or(event.description);
+            show.summary = show.summary.take().or(event.summary);
+            continue;
+        }
+        if let Some(description) = event.description {
+            let current = show.description.take().unwrap();
+            let combined = combine_descriptions(&current, &description);
+            if combined != current {
+                show.summary = match description_merge {
+                    DescriptionMerge::Summarize => None,
+                    _ => match (show.summary.take(), event.summary) {
+                        (Some(a), Some(b)) => Some(combine_descriptions(&a, &b)),
+                        (a, b) => a.or(b),
+                    },
+                };
+            }
+            show.description = Some(combined);
+        }
+    }
+
+    return merged;
+}
+
+/// Combines two descriptions of the same show. Near-identical descriptions, which
+/// only differ in casing, punctuation or spacing, or where one contains the other,
+/// are deduplicated by keeping the longest.
+fn combine_descriptions(first: &str, second: &str) -> String {
+    let (first_norm, second_norm) = (dedup_key(first), dedup_key(second));
+
+    if first_norm.contains(&second_norm) {
+        return first.to_string();
+    }
+    if second_norm.contains(&first_norm) {
+        return second.to_string();
+    }
+    return format!("{first}{DESCRIPTION_SEPARATOR}{second}");
+}
+
+/// Finds the premiere of a show in its detail page, e.g. "prima il 10/01". Pages
+/// don't give its year, so it's the one that puts the premiere within the run.
+pub(super) fn parse_premiere(document: &Html, dates: &DateSet) -> Option<NaiveDate> {
+    let text = normalize_whitespace(&document.root_element().text().collect::<Vec<_>>().join(" "));
+    let captures = PREMIERE.captures(&text).ok()??;
+
+    let day: u32 = captures.get(1)?.as_str().parse().ok()?;
+    let month = match (captures.get(2), captures.get(3)) {
+        (Some(month), _) => month.as_str().parse().ok()?,
+        (None, Some(month)) => italian_month_to_number(month.as_str())?,
+        (None, None) => return None,
+    };
+
+    return date_within_run(day, month, &dates.as_range());
+}
+
+/// The date of a day and month given without their year, which is the one that puts
+/// the date within the run of a show, or the year the run starts otherwise.
+pub(super) fn date_within_run(day: u32, month: u32, run: &DateRange) -> Option<NaiveDate> {
+    let candidates: Vec<NaiveDate> = [run.start.year(), run.end.year()]
+        .iter()
+        .filter_map(|year| NaiveDate::from_ymd_opt(*year, month, day))
+        .collect();
+    return candidates
+        .iter()
+        .find(|date| run.contains(date))
+        .or(candidates.first())
+        .copied();
+}
+
+/// Finds the rassegna a show is part of in its detail page, from the links of the
+/// breadcrumb or of the tags, e.g. "Rassegna Teatro Ragazzi" or "Mittelfest".
+pub(super) fn parse_rassegna(document: &Html) -> Option<String> {
+    let links_sel =
+        Selector::parse(".breadcrumb a, .breadcrumbs a, .tags a, a[rel~=\"tag\"]").unwrap();
+    return document
+        .select(&links_sel)
+        .map(|el| normalize_whitespace(&el.text().collect::<String>()))
+        .find(|text| RASSEGNA_WORD.is_match(text).unwrap_or(false));
+}
+
+/// The metadata of a show found in its detail page: its premiere and rassegna. The
+/// premiere can only be placed if the dates of the show are known.
+pub(super) fn detail_metadata(document: &Html, dates: Option<&DateSet>) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    if let Some(premiere) = dates.and_then(|dates| parse_premiere(document, dates)) {
+        metadata.insert(
+            PREMIERE_KEY.to_string(),
+            premiere.format("%d/%m").to_string(),
+        );
+    }
+    if let Some(rassegna) = parse_rassegna(document) {
+        metadata.insert(RASSEGNA_KEY.to_string(), rassegna);
+    }
+    return metadata;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{dates::TimeFrame, events::Location};
+
+    fn show(venue: &str, title: &str, description: &str) -> Event {
+        let location = Location::new(venue, None);
+        Event::new(title, HashSet::from([location]), CATEGORY_THEATRES)
+            .with_description(Some(description.to_string()))
+    }
+
+    #[test]
+    fn test_distinct_descriptions_are_combined() {
+        let events = vec![
+            show(
+                "Rossetti",
+                "Amleto",
+                "La tragedia del principe di Danimarca.",
+            ),
+            show("Verdi", "AMLETO", "Regia di Mario Rossi."),
+        ];
+
+        let merged = merge_shows(events, DescriptionMerge::Combine);
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].locations.len(), 2);
+        assert_eq!(
+            merged[0].description.as_deref(),
+            Some("La tragedia del principe di Danimarca.\n\nRegia di Mario Rossi.")
+        );
+    }
+
+    #[test]
+    fn test_near_identical_descriptions_are_deduplicated() {
+        let events = vec![
+            show(
+                "Rossetti",
+                "Amleto",
+                "La tragedia del principe di Danimarca.",
+            ),
+            show("Verdi", "Amleto", "La  tragedia del Principe di Danimarca"),
+        ];
+
+        let merged = merge_shows(events, DescriptionMerge::Combine);
+
+        assert_eq!(
+            merged[0].description.as_deref(),
+            Some("La tragedia del principe di Danimarca.")
+        );
+    }
+
+    #[test]
+    fn test_first_description_is_kept_by_default() {
+        let events = vec![
+            show(
+                "Rossetti",
+                "Amleto",
+                "La tragedia del principe di Danimarca.",
+            ),
+            show("Verdi", "Amleto", "Regia di Mario Rossi."),
+        ];
+
+        let merged = merge_shows(events, DescriptionMerge::First);
+
+        assert_eq!(
+            merged[0].description.as_deref(),
+            Some("La tragedia del principe di Danimarca.")
+        );
+    }
+
+    const DETAIL_PAGE: &str = r#"
+        <html><body>
+          <nav class="breadcrumb">
+            <a href="/">Prima pagina</a>
+            <a href="/stagione">Stagione</a>
+            <a href="/rassegne/ragazzi">Rassegna Teatro Ragazzi</a>
+          </nav>
+          <section class="mnk-block spettacolo-block">
+            <div>Dal 9 al 18 gennaio, prima il 10/01 con la compagnia al completo.</div>
+          </section>
+        </body></html>
+    "#;
+
+    fn run(start: NaiveDate, end: NaiveDate) -> DateSet {
+        return DateSet::new(DateRange::new(start, end).iter_days().collect()).unwrap();
+    }
+
+    #[test]
+    fn test_parse_premiere_from_detail_page() {
+        let document = Html::parse_document(DETAIL_PAGE);
+        let dates = run(
+            NaiveDate::from_ymd_opt(2025, 12, 30).unwrap(),
+            NaiveDate::from_ymd_opt(2026, 1, 18).unwrap(),
+        );
+
+        let premiere = parse_premiere(&document, &dates);
+
+        assert_eq!(premiere, NaiveDate::from_ymd_opt(2026, 1, 10));
+        assert_eq!(
+            detail_metadata(&document, Some(&dates))[PREMIERE_KEY],
+            "10/01"
+        );
+    }
+
+    #[test]
+    fn test_parse_rassegna_from_breadcrumb() {
+        let document = Html::parse_document(DETAIL_PAGE);
+        assert_eq!(
+            parse_rassegna(&document).as_deref(),
+            Some("Rassegna Teatro Ragazzi")
+        );
+
+        let tagged = Html::parse_fragment(
+            r#"<div class="tags"><a href="/t">Prosa</a> <a href="/f">Mittelfest 2026</a></div>"#,
+        );
+        assert_eq!(parse_rassegna(&tagged).as_deref(), Some("Mittelfest 2026"));
+    }
+
+    #[test]
+    fn test_words_containing_fest_are_not_rassegne() {
+        let tagged = Html::parse_fragment(
+            r#"<div class="tags"><a href="/m">Manifesto</a> <a href="/f">Festa di Natale</a></div>"#,
+        );
+        assert_eq!(parse_rassegna(&tagged), None);
+
+        let festival = Html::parse_fragment(
+            r#"<div class="tags"><a href="/f">Festa di Natale</a> <a href="/t">Trieste Film Festival</a></div>"#,
+        );
+        assert_eq!(
+            parse_rassegna(&festival).as_deref(),
+            Some("Trieste Film Festival")
+        );
+    }
+
+    #[test]
+    fn test_parse_premiere_with_month_name() {
+        let document = Html::parse_fragment("<p>Prima nazionale 12 marzo</p>");
+        let day = NaiveDate::from_ymd_opt(2026, 3, 10).unwrap();
+
+        let premiere = parse_premiere(&document, &run(day, day + chrono::Days::new(5)));
+
+        assert_eq!(premiere, NaiveDate::from_ymd_opt(2026, 3, 12));
+    }
+
+    #[test]
+    fn test_no_premiere() {
+        let document = Html::parse_fragment("<p>Prima pagina</p><p>Repliche fino al 10/01</p>");
+        let day = NaiveDate::from_ymd_opt(2026, 1, 9).unwrap();
+
+        assert_eq!(parse_premiere(&document, &run(day, day)), None);
+        assert!(detail_metadata(&document, Some(&run(day, day))).is_empty());
+    }
+
+    #[test]
+    fn test_separate_shows_at_the_same_theater_are_not_merged() {
+        let events = vec![
+            show("Miela", "Concerto di Capodanno", "Orchestra giovanile."),
+            show("Miela", "Concerto di Capodanno", "Coro cittadino.")
+                .with_id("Concerto di Capodanno@2025-12-31".to_string()),
+            show("Verdi", "Concerto di capodanno", "Orchestra del Verdi."),
+        ];
+
+        let merged = merge_shows(events, DescriptionMerge::First);
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[1].locations.len(), 2);
+    }
+
+    #[test]
+    fn test_dates_and_period_are_merged() {
+        let date = |day| NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+        let events = vec![
+            show("Rossetti", "Amleto", "Una tragedia.")
+                .with_time_frame(Some(TimeFrame::Period(DateRange::new(date(10), date(15))))),
+            show("Verdi", "Amleto", "Una tragedia.")
+                .with_time_frame(Some(TimeFrame::Dates(run(date(20), date(20))))),
+        ];
+
+        let merged = merge_shows(events, DescriptionMerge::First);
+
+        assert_eq!(merged.len(), 1);
+        match &merged[0].time_frame {
+            Some(TimeFrame::Period(range)) => {
+                assert_eq!((range.start, range.end), (date(10), date(20)));
+            }
+            other => panic!("unexpected time frame {other:?}"),
+        }
+    }
+}
@@ -1,38 +1,76 @@
-use std::{collections::HashSet, time::Duration};
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Result;
-use chrono::{Datelike, NaiveDate};
+use chrono::NaiveDate;
 use convert_case::Case;
 use indicatif::{ProgressBar, ProgressFinish, ProgressIterator, ProgressStyle};
 use reqwest::Client;
-use scraper::{Html, Selector};
+use scraper::Selector;
+use tracing::{debug, warn};
 
 use crate::{
     INFERENCE_SERVICE,
-    dates::{DateRange, DateSet, TimeFrame, italian_month_to_number},
-    events::{Event, Location},
-    inference::SUMMARY_PROMPT,
-    utils::PROGRESS_BAR_TEMPLATE,
-    venues::{CATEGORY_THEATRES, StandardCasing},
+    dates::{
+        DateParseError, DateRange, DateSet, TimeFrame, expect_parts, is_italian_weekday,
+        italian_month_to_number, parse_month,
+    },
+    events::{Event, HALL_SEPARATOR, Location},
+    utils::{PROGRESS_BAR_TEMPLATE, normalize_whitespace},
+    venues::{
+        CATEGORY_THEATRES, StandardCasing, fetch_document, fetch_listing, request_pause,
+        retry_details,
+        theaters::{date_within_run, detail_metadata},
+    },
 };
 
-pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>> {
+pub(crate) const BASE_URL: &str = "https://www.ilrossetti.it";
+/// The page listing the shows of the season, under [BASE_URL].
+pub(crate) const LISTING_PATH: &str = "/it/stagione/cartellone";
+/// Matches each show in the listing page, except the ones no longer on sale.
+pub(crate) const LISTING_SELECTOR: &str = "div.single-show:not(.single-show--disabled)";
+
+/// The main hall of the theater, whose shows are listed under the name of the theater
+/// alone. Shows in the other halls, such as the Sala Bartoli, have it in their location.
+const MAIN_HALL: &str = "Sala Assicurazioni Generali";
+
+/// What the page of a show says about it.
+#[derive(Default)]
+struct ShowDetails {
+    description: Option<String>,
+    summary: Option<String>,
+    dates: Option<DateSet>,
+    metadata: HashMap<String, String>,
+    /// The hall the show is in, if the page says.
+    hall: Option<String>,
+}
+
+pub async fn fetch(
+    client: &Client,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
+    return fetch_from(client, BASE_URL, date_range, max_events).await;
+}
+
+/// Fetches the shows from the site at `base_url`, which tests point at saved pages.
+pub async fn fetch_from(
+    client: &Client,
+    base_url: &str,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
     let mut events: HashSet<Event> = HashSet::new();
+    // Shows whose page couldn't be fetched, with their dates in the listing
+    let mut failed: HashSet<String> = HashSet::new();
+    let mut listed: HashMap<String, DateSet> = HashMap::new();
 
-    let url = "https://www.ilrossetti.it/it/stagione/cartellone";
-    let html_body = client
-        .get(url)
-        .send()
-        .await
-        .inspect_err(|e| println!("GET request failed: {e}"))?
-        .text()
-        .await?;
-
-    let document = Html::parse_document(&html_body);
-    let shows_sel = Selector::parse("div.single-show:not(.single-show--disabled)").unwrap();
+    let url = format!("{base_url}{LISTING_PATH}");
+    let shows_sel = Selector::parse(LISTING_SELECTOR).unwrap();
     let link_sel = Selector::parse("div.single-show__title > a").unwrap();
     let date_sel = Selector::parse("div.single-show__date").unwrap();
 
+    let document = fetch_listing(client, &url, &shows_sel, max_events).await?;
+
     let show_count = document.select(&shows_sel).count();
     let progress = ProgressBar::new(show_count as u64)
         .with_style(ProgressStyle::with_template(PROGRESS_BAR_TEMPLATE).unwrap())
@@ -42,50 +80,100 @@ pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>
     for show in document.select(&shows_sel).progress_with(progress) {
         let link_el = show.select(&link_sel).next();
         let date_el = show.select(&date_sel).next();
-        if link_el.is_none() || date_el.is_none() {
+        let (Some(link_el), Some(date_el)) = (link_el, date_el) else {
             continue;
-        }
+        };
 
         // The date is selected just to check if the event is in the current week
         // The real dates in selected in the event's page later
-        let date_str = date_el
-            // First text elem is an empty string (due to the icon probably)
-            .and_then(|el| el.text().skip(1).next())
-            .map(|t| t.trim().to_string())
-            .expect("Second text element should always be the date");
-        let dates = parse_date(&date_str).expect("Date should be in a standardized format");
-        if !dates.as_range().overlaps(&date_range) {
+        // First text elem is an empty string (due to the icon probably)
+        let Some(date_str) = date_el.text().skip(1).next().map(|t| t.trim().to_string()) else {
+            warn!("Skipping Rossetti show without a date");
+            continue;
+        };
+        let dates = match parse_date(&date_str) {
+            Ok(dates) => dates,
+            Err(err) => {
+                warn!("Skipping Rossetti show with unparsable date: {err}");
+                continue;
+            }
+        };
+        if !dates.overlaps(&date_range) {
             continue;
         }
 
-        let title = link_el
-            .and_then(|el| el.text().next())
-            .map(|t| t.trim().standardize_case(Some(Case::Upper)))
-            .expect("Each event card should have text");
-
-        let event_url = format!(
-            "https://www.ilrossetti.it{}",
-            link_el.unwrap().attr("href").unwrap()
-        );
-        let location = Location::new("Rossetti", Some(event_url.clone()));
+        let (Some(title), Some(href)) = (link_el.text().next(), link_el.attr("href")) else {
+            warn!("Skipping Rossetti show link without a title or address");
+            continue;
+        };
+        let title = title.trim().standardize_case(Some(Case::Upper));
+
+        let event_url = format!("{base_url}{href}");
+
+        // Shows whose dates can't be found are kept without any, rather than
+        // with the span of the listing, which doesn't say which days are on
+        let details = get_description_and_dates(client, &event_url, &dates).await;
+        let fetched = details.is_ok();
+        let details = details.unwrap_or_default();
+        let time_frame = details.dates.map(TimeFrame::Dates);
+
+        // The same show in two halls is kept as two events here, which are then
+        // merged with both locations like a show playing at two theaters
+        let name = location_name(details.hall.as_deref());
+        let location = Location::new(&name, Some(event_url.clone()));
         let locations = HashSet::from_iter([location]);
 
-        let (description, summary, dates) = get_description_and_dates(client, &event_url)
-            .await
-            .unwrap_or((None, None, DateSet::today()));
-        let time_frame = TimeFrame::Dates(dates);
-
         let event = Event::new(&title, locations, CATEGORY_THEATRES)
-            .with_time_frame(Some(time_frame))
-            .with_description(description)
-            .with_summary(summary);
-
+            .with_id(show_id(&title, &name))
+            .with_time_frame(time_frame)
+            .with_description(details.description)
+            .with_summary(details.summary)
+            .with_metadata(details.metadata);
+
+        if !fetched {
+            failed.insert(event.id.clone());
+            listed.insert(event_url, dates);
+        }
         events.insert(event);
 
-        tokio::time::sleep(Duration::from_millis(20)).await;
+        request_pause("rossetti").await;
     }
 
-    Ok(events.into_iter().collect())
+    let mut events: Vec<Event> = events.into_iter().collect();
+    retry_details(&mut events, &failed, async |url, event| {
+        let details = get_description_and_dates(client, url, &listed[url]).await?;
+        let name = location_name(details.hall.as_deref());
+        event.id = show_id(&event.title, &name);
+        event.locations = HashSet::from_iter([Location::new(&name, Some(url.to_string()))]);
+        event.time_frame = details.dates.map(TimeFrame::Dates);
+        event.description = details.description;
+        event.summary = details.summary;
+        event.metadata = details.metadata;
+        return Ok(());
+    })
+    .await;
+
+    Ok(events)
+}
+
+/// The id of a show at the location called `name`, so that the same show in two halls
+/// is kept as two events.
+fn show_id(title: &str, name: &str) -> String {
+    return format!(
+        "{title}{}",
+        name.strip_prefix("Rossetti").unwrap_or_default()
+    );
+}
+
+/// The name of the location of a show in `hall`, e.g. "Rossetti – Sala Bartoli". Shows
+/// in the main hall or in no hall in particular are just at the "Rossetti".
+fn location_name(hall: Option<&str>) -> String {
+    return match hall {
+        Some(hall) if !hall.eq_ignore_ascii_case(MAIN_HALL) => {
+            format!("Rossetti{HALL_SEPARATOR}{hall}")
+        }
+        _ => "Rossetti".to_string(),
+    };
 }
 
 /// Parse a date string from the Rossetti calendar and return a [DateSet]
@@ -95,13 +183,15 @@ pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>
 /// - Date ranges with same month: "23 - 24 Set 2025"
 /// - Date ranges spanning months: "8 - 19 Ott 2025", "27/2 - 1/3 2026"
 /// - Date ranges with different year formats: "30/12/2025 - 1/1/2026"
-fn parse_date(date_str: &str) -> Option<DateSet> {
-    let trimmed = date_str.trim();
+/// - Two dates with weekdays: "Sab 23 e Dom 24 Set 2025"
+fn parse_date(date_str: &str) -> Result<DateSet, DateParseError> {
+    let normalized = strip_weekdays(&normalize_whitespace(date_str));
+    let trimmed = normalized.as_str();
     if trimmed.is_empty() {
-        return None;
+        return Err(DateParseError::Empty);
     }
 
-    if !trimmed.contains('-') {
+    if !trimmed.contains('-') && !trimmed.contains(" e ") {
         // Case 1: Single date format (e.g., "22 Set 2025")
         return parse_single_date(trimmed);
     } else {
@@ -110,30 +200,43 @@ fn parse_date(date_str: &str) -> Option<DateSet> {
     }
 }
 
+/// Removes the weekday names before the days (e.g., "Sab 23 e Dom 24 Set 2025"
+/// becomes "23 e 24 Set 2025"). Only names at the start of a date are removed, as
+/// "mar" also stands for March.
+fn strip_weekdays(date_str: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in date_str.split_whitespace() {
+        let starts_date = matches!(parts.last(), None | Some(&"-") | Some(&"e"));
+        if starts_date && is_italian_weekday(part) {
+            continue;
+        }
+        parts.push(part);
+    }
+    return parts.join(" ");
+}
+
 /// Parse a single date string (e.g., "22 Set 2025")
-fn parse_single_date(date_str: &str) -> Option<DateSet> {
+fn parse_single_date(date_str: &str) -> Result<DateSet, DateParseError> {
     let parts: Vec<&str> = date_str.split_whitespace().collect();
 
     // Expected format: [day] [month] [year]
     // Indexes:         0     1       2
-    if parts.len() != 3 {
-        return None;
-    }
+    expect_parts(&parts, 3)?;
 
-    let month = italian_month_to_number(parts[1])?;
+    let month = parse_month(parts[1])?;
     let date_str = format!("{}/{}/{}", parts[0], month, parts[2]); // e.g. 22/9/2025
-    let date = NaiveDate::parse_from_str(&date_str, "%d/%m/%Y").ok()?;
+    let date = parse_slash_date(&date_str)?;
 
     // For single dates, create a date range that spans one day
-    return Some(DateSet::new(vec![date]).unwrap());
+    return Ok(DateSet::new(vec![date]).unwrap());
 }
 
 /// Parse a date range string
-fn parse_date_range(date_str: &str) -> Option<DateSet> {
+fn parse_date_range(date_str: &str) -> Result<DateSet, DateParseError> {
     // Handle different date range formats
 
-    // Format 1: "23 - 24 Set 2025" (same month)
-    if date_str.contains(" - ") && !date_str.contains('/') {
+    // Format 1: "23 - 24 Set 2025" or "23 e 24 Set 2025" (same month)
+    if (date_str.contains(" - ") || date_str.contains(" e ")) && !date_str.contains('/') {
         return parse_same_month_range(date_str);
     }
 
@@ -148,78 +251,81 @@ fn parse_date_range(date_str: &str) -> Option<DateSet> {
         return parse_full_date_range(date_str);
     }
 
-    return None;
+    return Err(DateParseError::UnknownFormat(date_str.to_string()));
 }
 
-/// Parse date range with same month (e.g., "23 - 24 Set 2025")
-fn parse_same_month_range(date_str: &str) -> Option<DateSet> {
+/// Parse date range with same month (e.g., "23 - 24 Set 2025" or "23 e 24 Set 2025")
+fn parse_same_month_range(date_str: &str) -> Result<DateSet, DateParseError> {
     let parts: Vec<&str> = date_str.split_whitespace().collect();
 
-    // Expected format: [start_day] - [end_day] [month] [year]
+    // Expected format: [start_day] -|e [end_day] [month] [year]
     // Indexes:         0           1 2         3       4
-    if parts.len() != 5 {
-        return None;
-    }
+    expect_parts(&parts, 5)?;
 
-    let month = italian_month_to_number(parts[3])?;
+    let month = parse_month(parts[3])?;
     let start_str = format!("{}/{}/{}", parts[0], month, parts[4]); // e.g. 23/9/2025
-    let start_date = NaiveDate::parse_from_str(&start_str, "%d/%m/%Y").ok()?;
+    let start_date = parse_slash_date(&start_str)?;
     let end_str = format!("{}/{}/{}", parts[2], month, parts[4]); // e.g. 24/9/2025
-    let end_date = NaiveDate::parse_from_str(&end_str, "%d/%m/%Y").ok()?;
+    let end_date = parse_slash_date(&end_str)?;
 
-    return Some(DateSet::new(vec![start_date, end_date]).unwrap());
+    return Ok(DateSet::new(vec![start_date, end_date]).unwrap());
 }
 
 /// Parse date range with slash format (e.g., "27/2 - 1/3 2026")
-fn parse_slash_date_range(date_str: &str) -> Option<DateSet> {
+fn parse_slash_date_range(date_str: &str) -> Result<DateSet, DateParseError> {
     let parts: Vec<&str> = date_str.split_whitespace().collect();
 
     // Expected format: [start_day]/[start_month] - [end_day]/[end_month] [year]
     // Indexes:         0                         1 2                     3
-    if parts.len() != 4 {
-        return None;
-    }
+    expect_parts(&parts, 4)?;
 
     let start_str = format!("{}/{}", parts[0], parts[3]); // e.g. 27/2/2026
-    let start_date = NaiveDate::parse_from_str(&start_str, "%d/%m/%Y").ok()?;
+    let start_date = parse_slash_date(&start_str)?;
     let end_str = format!("{}/{}", parts[2], parts[3]); // e.g. 1/3/2026
-    let end_date = NaiveDate::parse_from_str(&end_str, "%d/%m/%Y").ok()?;
+    let end_date = parse_slash_date(&end_str)?;
 
-    return Some(DateSet::new(vec![start_date, end_date]).unwrap());
+    return Ok(DateSet::new(vec![start_date, end_date]).unwrap());
 }
 
 /// Parse date range with full date format (e.g., "30/12/2025 - 1/1/2026")
-fn parse_full_date_range(date_str: &str) -> Option<DateSet> {
+fn parse_full_date_range(date_str: &str) -> Result<DateSet, DateParseError> {
     let parts: Vec<&str> = date_str.split(" - ").collect();
 
     // Expected format: [start_day]/[start_month]/[start_year] - [end_day]/[end_month]/[end_year]
     // Indexes:         0                                        1
-    if parts.len() != 2 {
-        return None;
-    }
+    expect_parts(&parts, 2)?;
+
+    let start_date = parse_slash_date(parts[0])?;
+    let end_date = parse_slash_date(parts[1])?;
 
-    let start_date = NaiveDate::parse_from_str(parts[0], "%d/%m/%Y").ok()?;
-    let end_date = NaiveDate::parse_from_str(parts[1], "%d/%m/%Y").ok()?;
+    return Ok(DateSet::new(vec![start_date, end_date]).unwrap());
+}
 
-    return Some(DateSet::new(vec![start_date, end_date]).unwrap());
+/// Parse a date in the "30/12/2025" format
+fn parse_slash_date(date_str: &str) -> Result<NaiveDate, DateParseError> {
+    NaiveDate::parse_from_str(date_str, "%d/%m/%Y")
+        .map_err(|_| DateParseError::InvalidDate(date_str.to_string()))
 }
 
+/// Fetches the details of a show from its page. The page doesn't give the year of
+/// the dates, so it's taken from the `listed` run of the show in the listing.
 async fn get_description_and_dates(
     client: &Client,
     url: &str,
-) -> Result<(Option<String>, Option<String>, DateSet)> {
+    listed: &DateSet,
+) -> Result<ShowDetails> {
     let desc_paras_sel = Selector::parse("div.section div.u-unknown-content p").unwrap();
     let dates_sel = Selector::parse("div.recite__date").unwrap();
+    let hall_sel = Selector::parse("div.recite__location").unwrap();
 
-    let html_body = client.get(url).send().await?.text().await?;
-    let document = Html::parse_document(&html_body);
+    let document = fetch_document(client, url).await?;
     let desc_el = document.select(&desc_paras_sel);
     let date_els = document.select(&dates_sel);
 
     let description;
     let summary;
     if desc_el.clone().count() == 0 {
-        eprintln!("No desc_el in {url}");
+        debug!("No desc_el in {url}");
         description = None;
         summary = None;
     } else {
@@ -235,35 +341,38 @@ async fn get_description_and_dates(
             .trim()
             .to_string();
 
-        let prompt = format!("{}\n\n{}", SUMMARY_PROMPT, desc);
-
+        summary = INFERENCE_SERVICE.summarize_description(&desc).await;
         description = Some(desc);
-        summary = INFERENCE_SERVICE
-            .infer(&prompt)
-            .await
-            .inspect_err(|err| eprintln!("Failed to generate summary in {url}: {err}"))
-            .ok();
     }
 
-    let dates;
-    if date_els.clone().count() == 0 {
-        eprintln!("No dates found in {url}");
-        dates = DateSet::today();
-    } else {
-        let naive_dates: Vec<NaiveDate> = date_els
-            .filter_map(|el| el.text().next())
-            .map(|t| {
-                let split: Vec<&str> = t.split_whitespace().collect();
-                let day: u32 = split[1].parse().unwrap();
-                let month = italian_month_to_number(split[2]).unwrap();
-                let year = chrono::Local::now().year();
-                NaiveDate::from_ymd_opt(year, month, day).unwrap()
-            })
-            .collect();
-        dates = DateSet::new(naive_dates).unwrap();
+    let run = listed.as_range();
+    let naive_dates: Vec<NaiveDate> = date_els
+        .filter_map(|el| el.text().next())
+        .filter_map(|t| {
+            let split: Vec<&str> = t.split_whitespace().collect();
+            let day: u32 = split.get(1)?.parse().ok()?;
+            let month = italian_month_to_number(split.get(2)?)?;
+            date_within_run(day, month, &run)
+        })
+        .collect();
+    let dates = DateSet::new(naive_dates);
+    if dates.is_none() {
+        debug!("No dates found in {url}");
     }
 
-    return Ok((description, summary, dates));
+    let metadata = detail_metadata(&document, dates.as_ref());
+    let hall = document
+        .select(&hall_sel)
+        .map(|el| normalize_whitespace(&el.text().collect::<String>()))
+        .find(|hall| !hall.is_empty());
+
+    return Ok(ShowDetails {
+        description,
+        summary,
+        dates,
+        metadata,
+        hall,
+    });
 }
 
 #[cfg(test)]
@@ -292,6 +401,40 @@ mod tests {
         assert_eq!(result.last().year(), 2025);
     }
 
+    #[test]
+    fn test_weekday_pair_set() {
+        let result = parse_date("Sab 23 e Dom 24 Set 2025").unwrap();
+        assert_eq!(result.dates().len(), 2);
+        assert_eq!(result.first().day(), 23);
+        assert_eq!(result.first().month(), 9);
+        assert_eq!(result.first().year(), 2025);
+        assert_eq!(result.last().day(), 24);
+        assert_eq!(result.last().month(), 9);
+        assert_eq!(result.last().year(), 2025);
+    }
+
+    #[test]
+    fn test_weekday_range_in_march() {
+        // "Mar" is both Tuesday and March
+        let result = parse_date("Mar 3 - Gio 5 Mar 2026").unwrap();
+        assert_eq!(result.first().day(), 3);
+        assert_eq!(result.first().month(), 3);
+        assert_eq!(result.last().day(), 5);
+        assert_eq!(result.last().month(), 3);
+
+        let result = parse_date("Mar 3 Mar 2026").unwrap();
+        assert_eq!(result.first().day(), 3);
+        assert_eq!(result.first().month(), 3);
+    }
+
+    #[test]
+    fn test_non_breaking_spaces() {
+        let result = parse_date("23\u{00A0}-\u{00A0}24\u{00A0}Set 2025").unwrap();
+        assert_eq!(result.first().day(), 23);
+        assert_eq!(result.last().day(), 24);
+        assert_eq!(result.last().month(), 9);
+    }
+
     #[test]
     fn test_slash_date_set() {
         let result = parse_date("27/2 - 1/3 2026").unwrap();
@@ -313,4 +456,43 @@ mod tests {
         assert_eq!(result.last().month(), 1);
         assert_eq!(result.last().year(), 2026);
     }
+
+    #[test]
+    fn test_error_bad_month() {
+        let result = parse_date("22 Foo 2025");
+        assert_eq!(
+            result.unwrap_err(),
+            DateParseError::UnknownMonth("Foo".into())
+        );
+    }
+
+    #[test]
+    fn test_error_wrong_arity() {
+        let result = parse_date("22 Set");
+        assert_eq!(
+            result.unwrap_err(),
+            DateParseError::WrongArity {
+                expected: 3,
+                found: 2
+            }
+        );
+
+        let result = parse_date("23 - 24 - 25 Set 2025");
+        assert_eq!(
+            result.unwrap_err(),
+            DateParseError::WrongArity {
+                expected: 5,
+                found: 7
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_empty_and_invalid() {
+        assert_eq!(parse_date("  ").unwrap_err(), DateParseError::Empty);
+        assert_eq!(
+            parse_date("31 Feb 2026").unwrap_err(),
+            DateParseError::InvalidDate("31/2/2026".into())
+        );
+    }
 }
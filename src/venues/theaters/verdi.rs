@@ -1,37 +1,47 @@
-use std::{collections::HashSet, time::Duration};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use anyhow::Result;
 use chrono::NaiveDate;
 use indicatif::{ProgressBar, ProgressFinish, ProgressIterator, ProgressStyle};
 use reqwest::Client;
-use scraper::{Html, Selector};
+use scraper::Selector;
+use tracing::{debug, warn};
 
 use crate::{
     INFERENCE_SERVICE,
-    dates::{DateRange, DateSet, TimeFrame, italian_month_to_number},
+    dates::{
+        DateParseError, DateRange, DateSet, Showtime, TimeFrame, is_italian_weekday, make_date,
+        parse_month, parse_number, parse_showtime,
+    },
     events::{Event, Location},
-    inference::SUMMARY_PROMPT,
-    utils::PROGRESS_BAR_TEMPLATE,
-    venues::CATEGORY_THEATRES,
+    utils::{PROGRESS_BAR_TEMPLATE, normalize_whitespace},
+    venues::{
+        CATEGORY_THEATRES, fetch_document, fetch_listing, request_pause, retry_details,
+        theaters::detail_metadata,
+    },
 };
 
-pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>> {
+/// The page listing the shows of the season.
+pub(crate) const LISTING_URL: &str =
+    "https://www.teatroverdi-trieste.com/it/calendario-spettacoli/";
+/// Matches each show in the listing page.
+pub(crate) const LISTING_SELECTOR: &str = "ul.spettacolo-list div.list-text";
+
+pub async fn fetch(
+    client: &Client,
+    date_range: &DateRange,
+    max_events: usize,
+) -> Result<Vec<Event>> {
     let mut events: HashSet<Event> = HashSet::new();
+    let mut failed: HashSet<String> = HashSet::new();
 
-    let url = "https://www.teatroverdi-trieste.com/it/calendario-spettacoli/";
-    let html_body = client
-        .get(url)
-        .send()
-        .await
-        .inspect_err(|e| println!("GET request failed: {e}"))?
-        .text()
-        .await?;
-
-    let document = Html::parse_document(&html_body);
-    let shows_sel = Selector::parse("ul.spettacolo-list div.list-text").unwrap();
+    let url = LISTING_URL;
+    let shows_sel = Selector::parse(LISTING_SELECTOR).unwrap();
     let link_sel = Selector::parse("h2.spettacolo-list-title > a").unwrap();
     let date_sel = Selector::parse("span.spettacolo-list-date > strong").unwrap();
 
+    let document = fetch_listing(client, url, &shows_sel, max_events).await?;
+
     let show_count = document.select(&shows_sel).count();
     let progress = ProgressBar::new(show_count as u64)
         .with_style(ProgressStyle::with_template(PROGRESS_BAR_TEMPLATE).unwrap())
@@ -41,77 +51,210 @@ pub async fn fetch(client: &Client, date_range: &DateRange) -> Result<Vec<Event>
     for show in document.select(&shows_sel).progress_with(progress) {
         let link_el = show.select(&link_sel).next();
         let date_el = show.select(&date_sel).next();
-        if link_el.is_none() || date_el.is_none() {
+        let (Some(link_el), Some(_)) = (link_el, date_el) else {
             continue;
-        }
+        };
 
-        let title = link_el
-            .and_then(|el| el.text().next())
-            .map(|t| t.to_string())
-            .expect("Each link element should have text");
-
-        let event_url = link_el.unwrap().attr("href").unwrap();
+        let (Some(title), Some(event_url)) = (link_el.text().next(), link_el.attr("href")) else {
+            warn!("Skipping Verdi show link without a title or address");
+            continue;
+        };
+        let title = title.to_string();
         let location = Location::new("Verdi", Some(event_url.to_string()));
         let locations = HashSet::from_iter([location]);
 
-        let (description, summary, dates) = get_description_and_dates(client, event_url)
-            .await
-            .unwrap_or((None, None, DateSet::today()));
+        let details = get_description_and_dates(client, event_url).await;
+        let fetched = details.is_ok();
+        let (description, summary, dates, showtimes, metadata) =
+            details.unwrap_or((None, None, None, BTreeMap::new(), HashMap::new()));
 
-        // Events are chronological: stop as soon as one is beyond the given range
-        if !dates.as_range().overlaps(&date_range) {
-            break;
+        // Shows whose dates can't be found are kept, to be confirmed
+        if let Some(dates) = &dates {
+            // Events are chronological: stop as soon as one is beyond the given range
+            if dates.first() > date_range.end {
+                break;
+            }
+            if !dates.overlaps(&date_range) {
+                continue;
+            }
         }
 
-        let time_frame = TimeFrame::Dates(dates);
-
         let event = Event::new(&title, locations, CATEGORY_THEATRES)
-            .with_time_frame(Some(time_frame))
+            .with_time_frame(dates.map(TimeFrame::Dates))
             .with_description(description)
-            .with_summary(summary);
+            .with_summary(summary)
+            .with_showtimes(showtimes)
+            .with_metadata(metadata);
 
+        if !fetched {
+            failed.insert(event.id.clone());
+        }
         events.insert(event);
 
-        tokio::time::sleep(Duration::from_millis(20)).await;
+        request_pause("verdi").await;
     }
 
-    return Ok(events.into_iter().collect());
+    let mut events: Vec<Event> = events.into_iter().collect();
+    retry_details(&mut events, &failed, async |url, event| {
+        let (description, summary, dates, showtimes, metadata) =
+            get_description_and_dates(client, url).await?;
+        event.time_frame = dates.map(TimeFrame::Dates);
+        event.description = description;
+        event.summary = summary;
+        event.showtimes = showtimes;
+        event.metadata = metadata;
+        return Ok(());
+    })
+    .await;
+    // The dates of a retried show are only known now
+    events.retain(|e| {
+        e.time_frame
+            .as_ref()
+            .is_none_or(|tf| tf.overlaps(date_range))
+    });
+
+    return Ok(events);
 }
 
-fn parse_date(date_str: &str) -> Option<NaiveDate> {
-    let split: Vec<&str> = date_str.split_whitespace().collect();
-    let day = split[0].parse::<u32>().ok()?;
-    let month = italian_month_to_number(split[1])?;
-    let year = split[2].parse::<i32>().ok()?;
+/// Parses a date such as "Martedì 23 dicembre 2025 ore 19.30", along with its
+/// showtime if any. The weekday is optional.
+fn parse_date(date_str: &str) -> Result<(NaiveDate, Option<Showtime>), DateParseError> {
+    let date_str = normalize_whitespace(date_str);
+    let mut split: Vec<&str> = date_str.split_whitespace().collect();
+    if split.first().is_some_and(|word| is_italian_weekday(word)) {
+        split.remove(0);
+    }
+    if split.len() < 3 {
+        return Err(DateParseError::WrongArity {
+            expected: 3,
+            found: split.len(),
+        });
+    }
+    let day = parse_number::<u32>(split[0])?;
+    let month = parse_month(split[1])?;
+    let year = parse_number::<i32>(split[2])?;
+    let showtime = lenient_showtime(&split[3..].join(" "));
 
-    return NaiveDate::from_ymd_opt(year, month, day);
+    return Ok((make_date(year, month, day)?, showtime));
+}
+
+/// Parses the showtime after a date, if any. A malformed time only loses the
+/// showtime, not the date it belongs to.
+fn lenient_showtime(text: &str) -> Option<Showtime> {
+    return parse_showtime(text)
+        .inspect_err(|err| warn!("Ignoring unparsable showtime \"{text}\": {err}"))
+        .ok()
+        .flatten();
+}
+
+/// Parses a list of dates such as "28, 31 dicembre, 2 gennaio 2026", where the
+/// month and year are only written after the last day they apply to. Going back
+/// from the end, a month later than the one after it means the list crosses into
+/// a new year, so the earlier dates belong to the year before.
+fn parse_multiple_dates(dates_str: &str) -> Result<Vec<NaiveDate>, DateParseError> {
+    let dates_str = normalize_whitespace(dates_str);
+    let mut dates = Vec::new();
+    let mut month: Option<u32> = None;
+    let mut year: Option<i32> = None;
+    for part in dates_str.split(',').rev() {
+        let mut split: Vec<&str> = part.split_whitespace().collect();
+        if split.first().is_some_and(|word| is_italian_weekday(word)) {
+            split.remove(0);
+        }
+        if split.is_empty() || split.len() > 3 {
+            return Err(DateParseError::WrongArity {
+                expected: 3,
+                found: split.len(),
+            });
+        }
+        if let Some(part_month) = split.get(1) {
+            let part_month = parse_month(part_month)?;
+            match split.get(2) {
+                Some(part_year) => year = Some(parse_number::<i32>(part_year)?),
+                None if month.is_some_and(|next_month| part_month > next_month) => {
+                    year = year.map(|y| y - 1);
+                }
+                None => {}
+            }
+            month = Some(part_month);
+        }
+
+        let (Some(month), Some(year)) = (month, year) else {
+            return Err(DateParseError::UnknownFormat(dates_str.to_string()));
+        };
+        let day = parse_number::<u32>(split[0])?;
+        dates.push(make_date(year, month, day)?);
+    }
+    dates.reverse();
+    return Ok(dates);
+}
+
+/// Parses the dates of a ticket, either a single date such as "Martedì, 23 dicembre
+/// 2025 ore 19.30" or a list of days such as "28, 31 dicembre, 2 gennaio 2026 ore
+/// 20.30", whose showtime applies to every day.
+fn parse_ticket_dates(text: &str) -> Result<Vec<(NaiveDate, Option<Showtime>)>, DateParseError> {
+    let text = normalize_whitespace(text);
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+    // The weekday may be followed by a comma, which doesn't start a list
+    if words.first().is_some_and(|word| is_italian_weekday(word)) {
+        words.remove(0);
+    }
+    let text = words.join(" ");
+    if !text.contains(',') {
+        return parse_date(&text).map(|date| vec![date]);
+    }
+
+    let time_start = words
+        .iter()
+        .position(|word| word.eq_ignore_ascii_case("ore"))
+        .unwrap_or(words.len());
+    let showtime = lenient_showtime(&words[time_start..].join(" "));
+    let dates = parse_multiple_dates(&words[..time_start].join(" "))?;
+    return Ok(dates.into_iter().map(|date| (date, showtime)).collect());
 }
 
 async fn get_description_and_dates(
     client: &Client,
     url: &str,
-) -> Result<(Option<String>, Option<String>, DateSet)> {
+) -> Result<(
+    Option<String>,
+    Option<String>,
+    Option<DateSet>,
+    BTreeMap<NaiveDate, Showtime>,
+    HashMap<String, String>,
+)> {
     let desc_sel = Selector::parse("section.mnk-block.spettacolo-block:not([id]) div").unwrap();
     let date_sel = Selector::parse("span.spettacolo-ticket-date").unwrap();
 
-    let html_body = client.get(url).send().await?.text().await?;
-    let document = Html::parse_document(&html_body);
+    let document = fetch_document(client, url).await?;
     let desc_els = document.select(&desc_sel);
     let date_els = document.select(&date_sel);
 
-    let mut dates: Vec<NaiveDate> = date_els
-        .filter_map(|el| el.text().next().and_then(|t| parse_date(t)))
+    let parsed: Vec<(NaiveDate, Option<Showtime>)> = date_els
+        .filter_map(|el| el.text().next())
+        .filter_map(|t| {
+            parse_ticket_dates(t)
+                .inspect_err(|err| warn!("Skipping unparsable date in {url}: {err}"))
+                .ok()
+        })
+        .flatten()
         .collect();
+    let showtimes: BTreeMap<NaiveDate, Showtime> = parsed
+        .iter()
+        .filter_map(|(date, showtime)| showtime.map(|s| (*date, s)))
+        .collect();
+    let mut dates: Vec<NaiveDate> = parsed.into_iter().map(|(date, _)| date).collect();
     dates.dedup();
     if dates.is_empty() {
-        println!("No date_els");
-        return Ok((None, None, DateSet::today()));
+        debug!("No date_els");
+        return Ok((None, None, None, showtimes, HashMap::new()));
     }
-    let dateset = DateSet::new(dates).unwrap();
+    let dateset = DateSet::new(dates);
+    let metadata = detail_metadata(&document, dateset.as_ref());
 
     if desc_els.clone().count() == 0 {
-        println!("No desc_els");
-        return Ok((None, None, dateset));
+        debug!("No desc_els");
+        return Ok((None, None, dateset, showtimes, metadata));
     }
 
     let description = desc_els.fold(String::new(), |acc, el| {
@@ -124,12 +267,152 @@ async fn get_description_and_dates(
         format!("{acc}. {text}",)
     });
 
-    let prompt = format!("{SUMMARY_PROMPT}\n\n{description}");
-    let summary = INFERENCE_SERVICE
-        .infer(&prompt)
-        .await
-        .inspect_err(|err| eprintln!("Failed to generate summary: {err}"))
-        .ok();
+    let summary = INFERENCE_SERVICE.summarize_description(&description).await;
+
+    return Ok((Some(description), summary, dateset, showtimes, metadata));
+}
 
-    return Ok((Some(description), summary, dateset));
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, NaiveTime};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        let (date, _) = parse_date("23 dicembre 2025 ore 19.30").unwrap();
+        assert_eq!(date.day(), 23);
+        assert_eq!(date.month(), 12);
+        assert_eq!(date.year(), 2025);
+    }
+
+    #[test]
+    fn test_parse_start_time() {
+        let (_, showtime) = parse_date("23 dicembre 2025 ore 19.30").unwrap();
+        let showtime = showtime.unwrap();
+        assert_eq!(showtime.start, NaiveTime::from_hms_opt(19, 30, 0).unwrap());
+        assert_eq!(showtime.end, None);
+    }
+
+    #[test]
+    fn test_parse_time_range() {
+        let (_, showtime) = parse_date("23 dicembre 2025 ore 19.30 - 22.00").unwrap();
+        let showtime = showtime.unwrap();
+        assert_eq!(showtime.start, NaiveTime::from_hms_opt(19, 30, 0).unwrap());
+        assert_eq!(showtime.end, NaiveTime::from_hms_opt(22, 0, 0));
+    }
+
+    #[test]
+    fn test_parse_with_weekday() {
+        let (date, showtime) = parse_date("Martedì 23 dicembre 2025 ore 19.30").unwrap();
+        assert_eq!(date.day(), 23);
+        assert_eq!(date.month(), 12);
+        assert_eq!(
+            showtime.unwrap().start,
+            NaiveTime::from_hms_opt(19, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_whole_hour() {
+        let (_, showtime) = parse_date("23 dicembre 2025 ore 21").unwrap();
+        assert_eq!(
+            showtime.unwrap().start,
+            NaiveTime::from_hms_opt(21, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_without_time() {
+        let (date, showtime) = parse_date("23 dicembre 2025").unwrap();
+        assert_eq!(date.day(), 23);
+        assert_eq!(showtime, None);
+    }
+
+    #[test]
+    fn test_malformed_time_keeps_date() {
+        let (date, showtime) = parse_date("23 dicembre 2025 ore 19,3O").unwrap();
+        assert_eq!(date, NaiveDate::from_ymd_opt(2025, 12, 23).unwrap());
+        assert_eq!(showtime, None);
+
+        let dates = parse_ticket_dates("28, 31 dicembre 2025 ore da definire").unwrap();
+        assert_eq!(dates.len(), 2);
+        assert!(dates.iter().all(|(_, showtime)| showtime.is_none()));
+    }
+
+    #[test]
+    fn test_error_bad_month() {
+        assert_eq!(
+            parse_date("23 decembre 2025").unwrap_err(),
+            DateParseError::UnknownMonth("decembre".into())
+        );
+    }
+
+    #[test]
+    fn test_error_wrong_arity() {
+        assert_eq!(
+            parse_date("23 dicembre").unwrap_err(),
+            DateParseError::WrongArity {
+                expected: 3,
+                found: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_ticket_with_weekday_and_comma() {
+        let dates = parse_ticket_dates("Martedì, 23 dicembre 2025 ore 19.30").unwrap();
+        assert_eq!(
+            dates,
+            vec![(
+                NaiveDate::from_ymd_opt(2025, 12, 23).unwrap(),
+                Some(Showtime {
+                    start: NaiveTime::from_hms_opt(19, 30, 0).unwrap(),
+                    end: None
+                })
+            )]
+        );
+    }
+
+    #[test]
+    fn test_ticket_with_list_keeps_showtime() {
+        let dates = parse_ticket_dates("28, 31 dicembre, 2 gennaio 2026 ore 20.30").unwrap();
+        let showtime = Some(Showtime {
+            start: NaiveTime::from_hms_opt(20, 30, 0).unwrap(),
+            end: None,
+        });
+        assert_eq!(
+            dates,
+            vec![
+                (NaiveDate::from_ymd_opt(2025, 12, 28).unwrap(), showtime),
+                (NaiveDate::from_ymd_opt(2025, 12, 31).unwrap(), showtime),
+                (NaiveDate::from_ymd_opt(2026, 1, 2).unwrap(), showtime),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_multiple_dates_across_new_year() {
+        let dates = parse_multiple_dates("28, 31 dicembre, 2 gennaio 2026").unwrap();
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd_opt(2025, 12, 28).unwrap(),
+                NaiveDate::from_ymd_opt(2025, 12, 31).unwrap(),
+                NaiveDate::from_ymd_opt(2026, 1, 2).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_multiple_dates_across_months() {
+        let dates = parse_multiple_dates("30 novembre, 1 dicembre 2025").unwrap();
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd_opt(2025, 11, 30).unwrap(),
+                NaiveDate::from_ymd_opt(2025, 12, 1).unwrap(),
+            ]
+        );
+    }
 }
@@ -0,0 +1,354 @@
+//! Runs the venue scrapers against saved pages served by a mock server.
+
+use chrono::NaiveDate;
+use reqwest::Client;
+use scraper_caffe::{
+    dates::{DateRange, TimeFrame},
+    venues::{
+        DEFAULT_MAX_EVENTS_PER_VENUE, ScraperError, count_matches,
+        libraries::minerva,
+        theaters::{miela, rossetti},
+    },
+};
+use wiremock::{
+    Mock, MockServer, ResponseTemplate,
+    matchers::{method, path},
+};
+
+/// Serves `body` as an HTML page at `page_path`. Unknown paths get a 404.
+async fn serve_page(server: &MockServer, page_path: &str, body: &str) {
+    Mock::given(method("GET"))
+        .and(path(page_path))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/html; charset=utf-8"))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_count_matches() {
+    let server = MockServer::start().await;
+    serve_page(
+        &server,
+        "/cartellone",
+        r#"<div class="single-show">Amleto</div><div class="single-show">Otello</div>"#,
+    )
+    .await;
+    let client = Client::new();
+
+    let listing = format!("{}/cartellone", server.uri());
+
+    assert_eq!(
+        count_matches(&client, &listing, "div.single-show")
+            .await
+            .unwrap(),
+        2
+    );
+    // A stale selector matches nothing, without failing
+    assert_eq!(
+        count_matches(&client, &listing, "div.show").await.unwrap(),
+        0
+    );
+    let missing = format!("{}/altro", server.uri());
+    assert!(
+        count_matches(&client, &missing, "div.single-show")
+            .await
+            .is_err()
+    );
+}
+
+/// A Miela calendar with a show on two days of the week and one on the week after.
+/// `{base}` stands for the URL of the server.
+const MIELA_CALENDAR: &str = r#"
+    <html><body>
+      <div class="calendar-day" data-calendar-day="20260113">
+        <a class="calendar-show" href="{base}/spettacoli/bella-ciao">
+          <p><span class="font-bold">BELLA CIAO</span></p>
+        </a>
+      </div>
+      <div class="calendar-day" data-calendar-day="20260115">
+        <a class="calendar-show" href="{base}/spettacoli/bella-ciao">
+          <p><span class="font-bold">BELLA CIAO</span></p>
+        </a>
+      </div>
+      <div class="calendar-day" data-calendar-day="20260121">
+        <a class="calendar-show" href="{base}/spettacoli/altro">
+          <p><span class="font-bold">ALTRO</span></p>
+        </a>
+      </div>
+    </body></html>
+"#;
+
+const MIELA_BELLA_CIAO: &str = r#"
+    <html><body>
+      <div class="article__body prose"><p>Canzoni della Resistenza.</p></div>
+    </body></html>
+"#;
+
+fn miela_week() -> DateRange {
+    return DateRange::new(
+        NaiveDate::from_ymd_opt(2026, 1, 12).unwrap(),
+        NaiveDate::from_ymd_opt(2026, 1, 18).unwrap(),
+    );
+}
+
+#[tokio::test]
+async fn test_miela_saved_pages() {
+    let server = MockServer::start().await;
+    let calendar = MIELA_CALENDAR.replace("{base}", &server.uri());
+    serve_page(&server, "/calendario/", &calendar).await;
+    serve_page(&server, "/spettacoli/bella-ciao", MIELA_BELLA_CIAO).await;
+
+    let events = miela::fetch_from(
+        &Client::new(),
+        &format!("{}/calendario/", server.uri()),
+        &miela_week(),
+        DEFAULT_MAX_EVENTS_PER_VENUE,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(events.len(), 1);
+    let show = &events[0];
+    assert_eq!(show.title, "Bella Ciao");
+    assert_eq!(
+        show.description.as_deref(),
+        Some("Canzoni della Resistenza.")
+    );
+    // The two days are merged into one event, in no particular order
+    let Some(TimeFrame::Dates(set)) = &show.time_frame else {
+        panic!("unexpected time frame {:?}", show.time_frame);
+    };
+    let mut dates = set.dates().clone();
+    dates.sort();
+    let date = |day| NaiveDate::from_ymd_opt(2026, 1, day).unwrap();
+    assert_eq!(dates, vec![date(13), date(15)]);
+}
+
+#[tokio::test]
+async fn test_miela_listing_over_cap_is_rejected() {
+    let server = MockServer::start().await;
+    let calendar = MIELA_CALENDAR.replace("{base}", &server.uri());
+    serve_page(&server, "/calendario/", &calendar).await;
+    // A listing over the cap must fail before any show page is fetched
+    Mock::given(method("GET"))
+        .and(path("/spettacoli/bella-ciao"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(MIELA_BELLA_CIAO, "text/html"))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let err = miela::fetch_from(
+        &Client::new(),
+        &format!("{}/calendario/", server.uri()),
+        &miela_week(),
+        2,
+    )
+    .await
+    .unwrap_err();
+
+    assert!(matches!(
+        err.downcast_ref::<ScraperError>(),
+        Some(ScraperError::TooManyMatches {
+            count: 3,
+            max: 2,
+            ..
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_miela_failed_description_is_retried() {
+    let server = MockServer::start().await;
+    let calendar = format!(
+        r#"<div class="calendar-day" data-calendar-day="20260113">
+          <a class="calendar-show" href="{}/spettacoli/bella-ciao">
+            <p><span class="font-bold">BELLA CIAO</span></p>
+          </a>
+        </div>"#,
+        server.uri()
+    );
+    serve_page(&server, "/calendario/", &calendar).await;
+    // The show page fails the first time, which isn't retried right away, then loads
+    Mock::given(method("GET"))
+        .and(path("/spettacoli/bella-ciao"))
+        .respond_with(ResponseTemplate::new(404))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/spettacoli/bella-ciao"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(MIELA_BELLA_CIAO, "text/html"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let events = miela::fetch_from(
+        &Client::new(),
+        &format!("{}/calendario/", server.uri()),
+        &miela_week(),
+        DEFAULT_MAX_EVENTS_PER_VENUE,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0].description.as_deref(),
+        Some("Canzoni della Resistenza.")
+    );
+}
+
+const MINERVA_EVENTI: &str = r#"
+    <html><body>
+      <article class="evento">
+        <h2 class="entry-title"><a href="/eventi/presentazione">PRESENTAZIONE DEL LIBRO</a></h2>
+        <span class="data-evento">Venerdì 30 gennaio 2026, ore 18.00</span>
+      </article>
+      <article class="evento">
+        <h2 class="entry-title"><a href="/eventi/lontano">LETTURE PER BAMBINI</a></h2>
+        <span class="data-evento">Sabato 14 febbraio 2026, ore 10.30</span>
+      </article>
+    </body></html>
+"#;
+
+const MINERVA_PRESENTAZIONE: &str = r#"
+    <html><body>
+      <div class="entry-content"><p>L'autrice presenta il suo nuovo romanzo.</p></div>
+    </body></html>
+"#;
+
+#[tokio::test]
+async fn test_minerva_saved_pages() {
+    let server = MockServer::start().await;
+    serve_page(&server, "/eventi/", MINERVA_EVENTI).await;
+    serve_page(&server, "/eventi/presentazione", MINERVA_PRESENTAZIONE).await;
+    let week = DateRange::new(
+        NaiveDate::from_ymd_opt(2026, 1, 26).unwrap(),
+        NaiveDate::from_ymd_opt(2026, 2, 1).unwrap(),
+    );
+
+    let events = minerva::fetch_from(
+        &Client::new(),
+        &format!("{}/eventi/", server.uri()),
+        &week,
+        DEFAULT_MAX_EVENTS_PER_VENUE,
+    )
+    .await
+    .unwrap();
+
+    // The event outside of the week is skipped
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.title, "Presentazione del Libro");
+    assert_eq!(
+        event.description.as_deref(),
+        Some("L'autrice presenta il suo nuovo romanzo.")
+    );
+    let date = NaiveDate::from_ymd_opt(2026, 1, 30).unwrap();
+    assert!(event.showtimes.contains_key(&date));
+}
+
+const ROSSETTI_CARTELLONE: &str = r#"
+    <html><body>
+      <div class="single-show">
+        <div class="single-show__title"><a href="/it/spettacoli/amleto">AMLETO</a></div>
+        <div class="single-show__date">
+          <i class="icon-calendar"></i>23 - 24 Set 2025
+        </div>
+      </div>
+      <div class="single-show single-show--disabled">
+        <div class="single-show__title"><a href="/it/spettacoli/annullato">ANNULLATO</a></div>
+        <div class="single-show__date">
+          <i class="icon-calendar"></i>23 Set 2025
+        </div>
+      </div>
+      <div class="single-show">
+        <div class="single-show__title"><a>SENZA LINK</a></div>
+        <div class="single-show__date">
+          <i class="icon-calendar"></i>24 Set 2025
+        </div>
+      </div>
+      <div class="single-show">
+        <div class="single-show__title"><a href="/it/spettacoli/amleto-bartoli">AMLETO</a></div>
+        <div class="single-show__date">
+          <i class="icon-calendar"></i>27 Set 2025
+        </div>
+      </div>
+      <div class="single-show">
+        <div class="single-show__title"><a href="/it/spettacoli/otello">OTELLO</a></div>
+        <div class="single-show__date">
+          <i class="icon-calendar"></i>8 - 19 Ott 2025
+        </div>
+      </div>
+    </body></html>
+"#;
+
+const ROSSETTI_AMLETO: &str = r#"
+    <html><body>
+      <div class="section"><div class="u-unknown-content">
+        <p>La tragedia del principe di Danimarca.</p>
+      </div></div>
+      <div class="recite__date">Mar 23 Set</div>
+      <div class="recite__date">Mer 24 Set</div>
+      <div class="recite__date">Da definire</div>
+      <div class="recite__location">Sala Assicurazioni Generali</div>
+    </body></html>
+"#;
+
+const ROSSETTI_AMLETO_BARTOLI: &str = r#"
+    <html><body>
+      <div class="recite__date">Sab 27 Set</div>
+      <div class="recite__location">Sala  Bartoli</div>
+    </body></html>
+"#;
+
+#[tokio::test]
+async fn test_rossetti_saved_pages() {
+    let server = MockServer::start().await;
+    serve_page(&server, "/it/stagione/cartellone", ROSSETTI_CARTELLONE).await;
+    serve_page(&server, "/it/spettacoli/amleto", ROSSETTI_AMLETO).await;
+    serve_page(
+        &server,
+        "/it/spettacoli/amleto-bartoli",
+        ROSSETTI_AMLETO_BARTOLI,
+    )
+    .await;
+    let url = server.uri();
+    let week = DateRange::new(
+        NaiveDate::from_ymd_opt(2025, 9, 22).unwrap(),
+        NaiveDate::from_ymd_opt(2025, 9, 28).unwrap(),
+    );
+
+    let events = rossetti::fetch_from(&Client::new(), &url, &week, DEFAULT_MAX_EVENTS_PER_VENUE)
+        .await
+        .unwrap();
+
+    // The disabled show, the broken one and the one outside of the week are
+    // skipped, while the same show in another hall is kept apart
+    assert_eq!(events.len(), 2);
+    let bartoli = events
+        .iter()
+        .find(|e| e.id == "Amleto – Sala Bartoli")
+        .unwrap();
+    let location = bartoli.locations.iter().next().unwrap();
+    assert_eq!(location.name, "Rossetti – Sala Bartoli");
+    let amleto = events.iter().find(|e| e.id == "Amleto").unwrap();
+    assert_eq!(amleto.title, "Amleto");
+    assert_eq!(
+        amleto.description.as_deref(),
+        Some("La tragedia del principe di Danimarca.")
+    );
+    let location = amleto.locations.iter().next().unwrap();
+    assert_eq!(location.name, "Rossetti");
+    assert_eq!(location.url, Some(format!("{url}/it/spettacoli/amleto")));
+    // Detail pages don't give the year, so it's taken from the listing
+    let expected = vec![
+        NaiveDate::from_ymd_opt(2025, 9, 23).unwrap(),
+        NaiveDate::from_ymd_opt(2025, 9, 24).unwrap(),
+    ];
+    match &amleto.time_frame {
+        Some(TimeFrame::Dates(set)) => assert_eq!(set.dates(), &expected),
+        other => panic!("unexpected time frame {other:?}"),
+    }
+}